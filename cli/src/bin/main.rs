@@ -2,8 +2,17 @@ use clap::Parser;
 use std::net::SocketAddr;
 use std::path::PathBuf;
 use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
 
-use mpc_websocket::{Result, Server};
+use mpc_websocket::{
+    admin::AdminOptions,
+    backplane::{RedisBackplane, DEFAULT_CHANNEL},
+    cors::AllowedOrigins,
+    files::{StaticFiles, StaticSource},
+    http::HttpOptions,
+    Features, Heartbeat, Result, Server, ServerOptions, Shutdown,
+};
 
 #[derive(Debug, Parser)]
 #[clap(
@@ -15,31 +24,167 @@ struct Options {
     /// Bind to host:port.
     #[clap(short, long)]
     bind: Option<String>,
+    /// Interval in seconds between heartbeat pings.
+    #[clap(
+        long,
+        default_value = "15",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    ping_interval: u64,
+    /// Disconnect clients that do not respond to pings
+    /// within this number of seconds, must be greater
+    /// than the ping interval.
+    #[clap(long, default_value = "45")]
+    ping_timeout: u64,
+    /// Maximum number of seconds to wait for sessions to
+    /// finish when shutting down.
+    #[clap(long, default_value = "30")]
+    shutdown_timeout: u64,
+    /// Redis server (host:port) used as a backplane
+    /// shared with other server instances.
+    #[clap(long)]
+    redis: Option<String>,
+    /// Channel for backplane messages.
+    #[clap(long, default_value = DEFAULT_CHANNEL)]
+    redis_channel: String,
+    /// Bearer token that enables the admin API.
+    #[clap(long, env = "MPC_WEBSOCKET_ADMIN_TOKEN", hide_env_values = true)]
+    admin_token: Option<String>,
+    /// Origin allowed to connect, may be repeated.
+    ///
+    /// When no origins are given any origin is allowed.
+    #[clap(long = "allow-origin")]
+    allow_origins: Vec<String>,
+    /// Enable the HTTP transport (Server-Sent Events and long-polling).
+    #[clap(long)]
+    http: bool,
+    /// Seconds a long-poll request waits for messages.
+    #[clap(long, default_value = "25", requires = "http")]
+    poll_timeout: u64,
+    /// Advertise that clients encrypt messages end-to-end.
+    #[clap(long)]
+    encryption: bool,
+    /// Advertise that sessions may use the CGGMP protocol.
+    #[clap(long)]
+    cggmp: bool,
+    /// Do not serve static files, websocket only mode.
+    #[clap(long, conflicts_with = "files")]
+    no_static: bool,
+    /// Mount prefix for static files.
+    #[clap(long)]
+    mount: Option<String>,
+    /// Serve index.html when a request does not match a file.
+    #[clap(long)]
+    spa: bool,
+    /// Cache-Control header for static files.
+    #[clap(long)]
+    cache_control: Option<String>,
     /// Path to static files to serve
     files: Option<PathBuf>,
 }
 
+/// Static files embedded in the binary.
+#[cfg(feature = "embed")]
+fn embedded() -> Option<StaticSource> {
+    use include_dir::{include_dir, Dir, DirEntry};
+    use std::collections::HashMap;
+
+    static DAPP: Dir<'_> =
+        include_dir!("$CARGO_MANIFEST_DIR/../snap/dapp/dist");
+
+    fn collect(dir: &'static Dir<'_>, files: &mut HashMap<String, &[u8]>) {
+        for entry in dir.entries() {
+            match entry {
+                DirEntry::Dir(dir) => collect(dir, files),
+                DirEntry::File(file) => {
+                    let path =
+                        file.path().to_string_lossy().replace('\\', "/");
+                    files.insert(path, file.contents());
+                }
+            }
+        }
+    }
+
+    let mut files = HashMap::new();
+    collect(&DAPP, &mut files);
+    Some(StaticSource::Embedded(Arc::new(files)))
+}
+
+/// Static files embedded in the binary.
+#[cfg(not(feature = "embed"))]
+fn embedded() -> Option<StaticSource> {
+    None
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     let opts: Options = Parser::parse();
     let bind = opts.bind.unwrap_or_else(|| "127.0.0.1:3030".to_string());
     let addr = SocketAddr::from_str(&bind)?;
 
-    let static_files = if let Some(static_files) = opts.files {
-        if static_files.is_absolute() {
+    let source = if opts.no_static {
+        None
+    } else if let Some(static_files) = opts.files {
+        let static_files = if static_files.is_absolute() {
             static_files
         } else {
             let cwd = std::env::current_dir()?;
             cwd.join(static_files)
-        }
+        };
+        Some(StaticSource::Directory(static_files))
+    } else if let Some(source) = embedded() {
+        Some(source)
     } else {
         let mut static_files = std::env::current_dir()?;
         static_files.pop();
         static_files.push("snap");
         static_files.push("dapp");
         static_files.push("dist");
-        static_files
+        if static_files.is_dir() {
+            Some(StaticSource::Directory(static_files))
+        } else {
+            None
+        }
+    };
+
+    let static_files = source.map(|source| StaticFiles {
+        source,
+        prefix: opts.mount,
+        spa_fallback: opts.spa,
+        cache_control: opts.cache_control,
+    });
+
+    let allowed_origins = if opts.allow_origins.is_empty() {
+        AllowedOrigins::any()
+    } else {
+        AllowedOrigins::list(opts.allow_origins)?
+    };
+
+    let heartbeat = Heartbeat::new(
+        Duration::from_secs(opts.ping_interval),
+        Duration::from_secs(opts.ping_timeout),
+    )?;
+
+    let options = ServerOptions {
+        heartbeat,
+        shutdown: Shutdown {
+            timeout: Duration::from_secs(opts.shutdown_timeout),
+        },
+        backplane: opts.redis.map(|addr| {
+            Arc::new(RedisBackplane::new(addr, opts.redis_channel)) as _
+        }),
+        admin: opts.admin_token.map(|token| AdminOptions { token }),
+        static_files,
+        allowed_origins,
+        http: opts.http.then(|| HttpOptions {
+            poll_timeout: Duration::from_secs(opts.poll_timeout),
+            ..Default::default()
+        }),
+        features: Features {
+            encryption: opts.encryption,
+            cggmp: opts.cggmp,
+        },
     };
 
-    Server::start("mpc", (addr.ip(), addr.port()), static_files).await
+    Server::start_with_options("mpc", (addr.ip(), addr.port()), options).await
 }
@@ -0,0 +1,607 @@
+//! Admin HTTP API for inspecting and managing the server.
+//!
+//! The admin API is only mounted when [AdminOptions] are assigned
+//! to the server options and every request must include the configured
+//! token as a bearer token in the `Authorization` header.
+//!
+//! Responses only include routing information; message bodies and
+//! the public session `value` are never exposed.
+//!
+//! | Method   | Path                                  | Description                      |
+//! |----------|---------------------------------------|----------------------------------|
+//! | `GET`    | `/admin/groups`                       | List groups.                     |
+//! | `DELETE` | `/admin/groups/{group}`               | Force close a group.             |
+//! | `GET`    | `/admin/sessions`                     | List sessions.                   |
+//! | `GET`    | `/admin/groups/{group}/sessions`      | List sessions for a group.       |
+//! | `DELETE` | `/admin/groups/{group}/sessions/{id}` | Force close a session.           |
+//! | `GET`    | `/admin/connections`                  | List connection identifiers.     |
+//! | `DELETE` | `/admin/connections/{id}`             | Disconnect a connection.         |
+//! | `GET`    | `/admin/metrics`                      | Server metrics.                  |
+//!
+//! Closing a group or session sends a `groupRemoved` or `sessionRemoved`
+//! event to the connected members before it is removed.
+//!
+//! When a backplane is configured the removal of groups and sessions is
+//! published to the other server instances but a connection can only be
+//! disconnected by the instance that the client is connected to.
+use serde::Serialize;
+use std::convert::Infallible;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use uuid::Uuid;
+use warp::http::{Method, StatusCode};
+use warp::reply::Reply;
+use warp::{filters::BoxedFilter, Filter};
+
+use crate::compression::CompressionSnapshot;
+use crate::server::rpc_notify;
+use crate::services::{GROUP_REMOVED_EVENT, SESSION_REMOVED_EVENT};
+use crate::{Group, Notification, Parameters, Session, SessionKind, State};
+
+/// Path prefix for the admin API.
+pub const ADMIN_PATH: &str = "admin";
+
+/// Options for the admin API.
+#[derive(Debug, Clone)]
+pub struct AdminOptions {
+    /// Bearer token required to access the admin API.
+    pub token: String,
+}
+
+/// Summary of a group.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GroupSummary {
+    /// Unique identifier for the group.
+    pub uuid: Uuid,
+    /// Human-readable label for the group.
+    pub label: String,
+    /// Parameters for key generation.
+    pub params: Parameters,
+    /// Number of connected members.
+    pub members: usize,
+    /// Number of sessions.
+    pub sessions: usize,
+}
+
+impl From<&Group> for GroupSummary {
+    fn from(group: &Group) -> Self {
+        Self {
+            uuid: group.uuid,
+            label: group.label.clone(),
+            params: group.params.clone(),
+            members: group.clients.len(),
+            sessions: group.sessions.len(),
+        }
+    }
+}
+
+/// Summary of a session.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionSummary {
+    /// Unique identifier for the session.
+    pub uuid: Uuid,
+    /// Group that the session belongs to.
+    pub group_id: Uuid,
+    /// Kind of the session.
+    pub kind: SessionKind,
+    /// Number of parties required by the session.
+    pub required: usize,
+    /// Number of parties signed up to the session.
+    pub signups: usize,
+    /// Number of parties that have finished the session.
+    pub finished: usize,
+}
+
+impl SessionSummary {
+    fn new(group: &Group, session: &Session) -> Self {
+        let required = match session.kind {
+            SessionKind::Keygen => group.params.parties as usize,
+            SessionKind::Sign => group.params.threshold as usize + 1,
+        };
+        Self {
+            uuid: session.uuid,
+            group_id: group.uuid,
+            kind: session.kind.clone(),
+            required,
+            signups: session.party_signups.len(),
+            finished: session.finished.len(),
+        }
+    }
+}
+
+/// Server metrics.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Metrics {
+    /// Number of connected clients.
+    pub connections: usize,
+    /// Metrics for compressed messages.
+    pub compression: CompressionSnapshot,
+}
+
+#[derive(Serialize)]
+struct ErrorReply {
+    error: String,
+}
+
+/// Routes for the admin API.
+///
+/// When no options are given every request is rejected so
+/// the filter can always be combined with the other routes.
+pub(crate) fn routes(
+    options: Option<AdminOptions>,
+    state: impl Filter<Extract = (Arc<RwLock<State>>,), Error = Infallible>
+        + Clone
+        + Send
+        + Sync
+        + 'static,
+) -> BoxedFilter<(warp::reply::Response,)> {
+    if let Some(options) = options {
+        let token = Arc::new(options.token);
+        warp::path(ADMIN_PATH)
+            .and(warp::path::tail())
+            .and(warp::method())
+            .and(warp::header::optional::<String>("authorization"))
+            .and(warp::any().map(move || Arc::clone(&token)))
+            .and(state)
+            .then(
+                |tail: warp::path::Tail,
+                 method: Method,
+                 authorization: Option<String>,
+                 token: Arc<String>,
+                 state: Arc<RwLock<State>>| async move {
+                    if !authorized(authorization.as_deref(), &token) {
+                        tracing::warn!("admin request unauthorized");
+                        return error(StatusCode::UNAUTHORIZED, "unauthorized");
+                    }
+                    let segments = tail
+                        .as_str()
+                        .split('/')
+                        .filter(|s| !s.is_empty())
+                        .collect::<Vec<_>>();
+                    handle(method, &segments, &state).await
+                },
+            )
+            .boxed()
+    } else {
+        warp::any()
+            .and_then(|| async {
+                Err::<warp::reply::Response, _>(warp::reject::not_found())
+            })
+            .boxed()
+    }
+}
+
+/// Compare the authorization header with the token in constant time.
+fn authorized(authorization: Option<&str>, token: &str) -> bool {
+    let value = match authorization.and_then(|s| s.strip_prefix("Bearer ")) {
+        Some(value) => value.as_bytes(),
+        None => return false,
+    };
+    let token = token.as_bytes();
+    if value.len() != token.len() {
+        return false;
+    }
+    value
+        .iter()
+        .zip(token.iter())
+        .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+        == 0
+}
+
+async fn handle(
+    method: Method,
+    segments: &[&str],
+    state: &Arc<RwLock<State>>,
+) -> warp::reply::Response {
+    match (method, segments) {
+        (Method::GET, ["groups"]) => {
+            let reader = state.read().await;
+            let groups = reader
+                .groups
+                .values()
+                .map(GroupSummary::from)
+                .collect::<Vec<_>>();
+            warp::reply::json(&groups).into_response()
+        }
+        (Method::GET, ["sessions"]) => {
+            let reader = state.read().await;
+            let sessions = reader
+                .groups
+                .values()
+                .flat_map(|group| {
+                    group
+                        .sessions
+                        .values()
+                        .map(move |session| SessionSummary::new(group, session))
+                })
+                .collect::<Vec<_>>();
+            warp::reply::json(&sessions).into_response()
+        }
+        (Method::GET, ["groups", group_id, "sessions"]) => {
+            let group_id = match group_id.parse::<Uuid>() {
+                Ok(group_id) => group_id,
+                Err(_) => return bad_request(),
+            };
+            let reader = state.read().await;
+            if let Some(group) = reader.groups.get(&group_id) {
+                let sessions = group
+                    .sessions
+                    .values()
+                    .map(|session| SessionSummary::new(group, session))
+                    .collect::<Vec<_>>();
+                warp::reply::json(&sessions).into_response()
+            } else {
+                not_found()
+            }
+        }
+        (Method::DELETE, ["groups", group_id]) => {
+            let group_id = match group_id.parse::<Uuid>() {
+                Ok(group_id) => group_id,
+                Err(_) => return bad_request(),
+            };
+            close_group(group_id, state).await
+        }
+        (Method::DELETE, ["groups", group_id, "sessions", session_id]) => {
+            let (group_id, session_id) =
+                match (group_id.parse::<Uuid>(), session_id.parse::<Uuid>()) {
+                    (Ok(group_id), Ok(session_id)) => (group_id, session_id),
+                    _ => return bad_request(),
+                };
+            close_session(group_id, session_id, state).await
+        }
+        (Method::GET, ["connections"]) => {
+            let reader = state.read().await;
+            let mut connections =
+                reader.clients.keys().copied().collect::<Vec<_>>();
+            connections.sort();
+            warp::reply::json(&connections).into_response()
+        }
+        (Method::GET, ["metrics"]) => {
+            let reader = state.read().await;
+            let metrics = Metrics {
+                connections: reader.clients.len(),
+                compression: reader.compression_metrics.snapshot(),
+            };
+            warp::reply::json(&metrics).into_response()
+        }
+        (Method::DELETE, ["connections", conn_id]) => {
+            let conn_id = match conn_id.parse::<usize>() {
+                Ok(conn_id) => conn_id,
+                Err(_) => return bad_request(),
+            };
+            disconnect(conn_id, state).await
+        }
+        _ => not_found(),
+    }
+}
+
+/// Notify the members of a group and remove the group.
+async fn close_group(
+    group_id: Uuid,
+    state: &Arc<RwLock<State>>,
+) -> warp::reply::Response {
+    if !state.read().await.groups.contains_key(&group_id) {
+        return not_found();
+    }
+
+    let value = serde_json::to_value((GROUP_REMOVED_EVENT, group_id)).unwrap();
+    rpc_notify(
+        state,
+        Notification::Group {
+            group_id,
+            filter: None,
+            response: value.into(),
+        },
+    )
+    .await;
+
+    let cluster = {
+        let mut writer = state.write().await;
+        writer.groups.remove(&group_id);
+        writer.cluster.clone()
+    };
+
+    if let Some(cluster) = cluster {
+        if let Err(e) = cluster.publish_remove_group(group_id).await {
+            tracing::error!(?e, "backplane publish error");
+        }
+    }
+
+    tracing::info!(%group_id, "admin removed group");
+    StatusCode::NO_CONTENT.into_response()
+}
+
+/// Notify the participants of a session and remove the session.
+async fn close_session(
+    group_id: Uuid,
+    session_id: Uuid,
+    state: &Arc<RwLock<State>>,
+) -> warp::reply::Response {
+    let exists = state
+        .read()
+        .await
+        .groups
+        .get(&group_id)
+        .map(|group| group.sessions.contains_key(&session_id))
+        .unwrap_or(false);
+    if !exists {
+        return not_found();
+    }
+
+    let value =
+        serde_json::to_value((SESSION_REMOVED_EVENT, session_id)).unwrap();
+    rpc_notify(
+        state,
+        Notification::Session {
+            group_id,
+            session_id,
+            filter: None,
+            response: value.into(),
+        },
+    )
+    .await;
+
+    let cluster = {
+        let mut writer = state.write().await;
+        if let Some(group) = writer.groups.get_mut(&group_id) {
+            group.sessions.remove(&session_id);
+        }
+        writer.cluster.clone()
+    };
+
+    if let Some(cluster) = cluster {
+        if let Err(e) =
+            cluster.publish_remove_session(group_id, session_id).await
+        {
+            tracing::error!(?e, "backplane publish error");
+        }
+    }
+
+    tracing::info!(%group_id, %session_id, "admin removed session");
+    StatusCode::NO_CONTENT.into_response()
+}
+
+/// Ask a connected client to close the connection.
+///
+/// The connection is cleaned up in the usual way once
+/// it has been closed.
+async fn disconnect(
+    conn_id: usize,
+    state: &Arc<RwLock<State>>,
+) -> warp::reply::Response {
+    let reader = state.read().await;
+    if let Some(conn) = reader.clients.get(&conn_id) {
+        conn.close();
+        tracing::info!(conn_id, "admin disconnected client");
+        StatusCode::NO_CONTENT.into_response()
+    } else {
+        not_found()
+    }
+}
+
+fn error(status: StatusCode, message: &str) -> warp::reply::Response {
+    let reply = ErrorReply {
+        error: message.to_string(),
+    };
+    warp::reply::with_status(warp::reply::json(&reply), status).into_response()
+}
+
+fn bad_request() -> warp::reply::Response {
+    error(StatusCode::BAD_REQUEST, "bad request")
+}
+
+fn not_found() -> warp::reply::Response {
+    error(StatusCode::NOT_FOUND, "not found")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identity::ClientId;
+    use crate::transport::Connection;
+    use serde_json::Value;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    const TOKEN: &str = "secret";
+
+    #[derive(Debug, Default)]
+    struct MockConnection {
+        closed: AtomicBool,
+    }
+
+    impl Connection for MockConnection {
+        fn transport(&self) -> &'static str {
+            "mock"
+        }
+
+        fn send(&self, _: &json_rpc2::Response) -> crate::Result<()> {
+            Ok(())
+        }
+
+        fn close(&self) {
+            self.closed.store(true, Ordering::SeqCst);
+        }
+    }
+
+    /// Server state with a group that has a signing session.
+    fn state() -> (Arc<RwLock<State>>, Uuid, Uuid) {
+        let client_id = ClientId::random();
+        let params = Parameters {
+            parties: 3,
+            threshold: 1,
+        };
+        let mut group = Group::new(client_id, params, String::from("test"));
+        let mut session = Session::from((SessionKind::Sign, None));
+        session.signup(client_id);
+        let (group_id, session_id) = (group.uuid, session.uuid);
+        group.sessions.insert(session_id, session);
+
+        let mut state = State::new(Default::default(), None);
+        state.groups.insert(group_id, group);
+        (Arc::new(RwLock::new(state)), group_id, session_id)
+    }
+
+    async fn request(
+        state: &Arc<RwLock<State>>,
+        method: &str,
+        path: &str,
+    ) -> (StatusCode, Value) {
+        let state = Arc::clone(state);
+        let options = AdminOptions {
+            token: TOKEN.to_owned(),
+        };
+        let filter =
+            routes(Some(options), warp::any().map(move || Arc::clone(&state)));
+        let response = warp::test::request()
+            .method(method)
+            .path(path)
+            .header("authorization", format!("Bearer {}", TOKEN))
+            .reply(&filter)
+            .await;
+        let body = serde_json::from_slice(response.body()).unwrap_or_default();
+        (response.status(), body)
+    }
+
+    #[test]
+    fn authorized_compares_bearer_token() {
+        assert!(authorized(Some("Bearer secret"), TOKEN));
+        assert!(!authorized(Some("Bearer secreT"), TOKEN));
+        assert!(!authorized(Some("Bearer secret2"), TOKEN));
+        assert!(!authorized(Some("secret"), TOKEN));
+        assert!(!authorized(Some("Basic secret"), TOKEN));
+        assert!(!authorized(None, TOKEN));
+    }
+
+    #[tokio::test]
+    async fn rejects_requests() {
+        let (state, _, _) = state();
+        let options = AdminOptions {
+            token: TOKEN.to_owned(),
+        };
+        let filter = {
+            let state = Arc::clone(&state);
+            routes(Some(options), warp::any().map(move || Arc::clone(&state)))
+        };
+        let response = warp::test::request()
+            .path("/admin/groups")
+            .header("authorization", "Bearer wrong")
+            .reply(&filter)
+            .await;
+        assert_eq!(StatusCode::UNAUTHORIZED, response.status());
+
+        // Not mounted without options
+        let filter = routes(None, warp::any().map(move || Arc::clone(&state)));
+        let response = warp::test::request()
+            .path("/admin/groups")
+            .header("authorization", format!("Bearer {}", TOKEN))
+            .reply(&filter)
+            .await;
+        assert_eq!(StatusCode::NOT_FOUND, response.status());
+    }
+
+    #[tokio::test]
+    async fn lists_groups_and_sessions() {
+        let (state, group_id, session_id) = state();
+
+        let (status, groups) = request(&state, "GET", "/admin/groups").await;
+        assert_eq!(StatusCode::OK, status);
+        assert_eq!(
+            serde_json::json!([{
+                "uuid": group_id,
+                "label": "test",
+                "params": { "parties": 3, "threshold": 1 },
+                "members": 1,
+                "sessions": 1,
+            }]),
+            groups
+        );
+
+        let expected = serde_json::json!([{
+            "uuid": session_id,
+            "groupId": group_id,
+            "kind": "sign",
+            "required": 2,
+            "signups": 1,
+            "finished": 0,
+        }]);
+        let (status, sessions) =
+            request(&state, "GET", "/admin/sessions").await;
+        assert_eq!(StatusCode::OK, status);
+        assert_eq!(expected, sessions);
+
+        let path = format!("/admin/groups/{}/sessions", group_id);
+        let (status, sessions) = request(&state, "GET", &path).await;
+        assert_eq!(StatusCode::OK, status);
+        assert_eq!(expected, sessions);
+
+        let path = format!("/admin/groups/{}/sessions", Uuid::new_v4());
+        let (status, _) = request(&state, "GET", &path).await;
+        assert_eq!(StatusCode::NOT_FOUND, status);
+
+        let (status, _) =
+            request(&state, "GET", "/admin/groups/bad/sessions").await;
+        assert_eq!(StatusCode::BAD_REQUEST, status);
+
+        let (status, _) = request(&state, "POST", "/admin/groups").await;
+        assert_eq!(StatusCode::NOT_FOUND, status);
+    }
+
+    #[tokio::test]
+    async fn closes_groups_and_sessions() {
+        let (state, group_id, session_id) = state();
+
+        let path =
+            format!("/admin/groups/{}/sessions/{}", group_id, session_id);
+        let (status, _) = request(&state, "DELETE", &path).await;
+        assert_eq!(StatusCode::NO_CONTENT, status);
+        assert!(state.read().await.groups[&group_id].sessions.is_empty());
+        let (status, _) = request(&state, "DELETE", &path).await;
+        assert_eq!(StatusCode::NOT_FOUND, status);
+
+        let path = format!("/admin/groups/{}", group_id);
+        let (status, _) = request(&state, "DELETE", &path).await;
+        assert_eq!(StatusCode::NO_CONTENT, status);
+        assert!(state.read().await.groups.is_empty());
+        let (status, _) = request(&state, "DELETE", &path).await;
+        assert_eq!(StatusCode::NOT_FOUND, status);
+    }
+
+    #[tokio::test]
+    async fn lists_and_disconnects_connections() {
+        let (state, _, _) = state();
+        let conn = Arc::new(MockConnection::default());
+        {
+            let mut writer = state.write().await;
+            writer
+                .clients
+                .insert(7, Arc::clone(&conn) as Arc<dyn Connection>);
+            writer
+                .clients
+                .insert(3, Arc::new(MockConnection::default()));
+        }
+
+        let (status, connections) =
+            request(&state, "GET", "/admin/connections").await;
+        assert_eq!(StatusCode::OK, status);
+        assert_eq!(serde_json::json!([3, 7]), connections);
+
+        let (status, metrics) = request(&state, "GET", "/admin/metrics").await;
+        assert_eq!(StatusCode::OK, status);
+        assert_eq!(serde_json::json!(2), metrics["connections"]);
+
+        let (status, _) =
+            request(&state, "DELETE", "/admin/connections/7").await;
+        assert_eq!(StatusCode::NO_CONTENT, status);
+        assert!(conn.closed.load(Ordering::SeqCst));
+
+        let (status, _) =
+            request(&state, "DELETE", "/admin/connections/8").await;
+        assert_eq!(StatusCode::NOT_FOUND, status);
+        let (status, _) =
+            request(&state, "DELETE", "/admin/connections/x").await;
+        assert_eq!(StatusCode::BAD_REQUEST, status);
+    }
+}
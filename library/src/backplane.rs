@@ -0,0 +1,1193 @@
+//! Backplane for routing notifications between server instances.
+//!
+//! Without a backplane all the members of a group must be connected
+//! to the same server process because the server [State](crate::State)
+//! and the channels used to send messages to clients are in-process.
+//!
+//! When a backplane is configured each server instance:
+//!
+//! * Allocates connection identifiers from a shared counter so they are
+//!   unique across all instances.
+//! * Publishes a snapshot of a group whenever a request changes the
+//!   group; other instances merge the snapshot into their own state.
+//! * Publishes every notification; other instances deliver the
+//!   notification to the recipients that are connected locally.
+//! * Uses shared counters to decide when a session threshold has
+//!   been reached so that signups on different instances are
+//!   counted correctly.
+//!
+//! Group snapshots only contain routing information (client
+//! identities, party numbers and the public session `value`); message
+//! bodies are never stored in the backplane but they are published
+//! to other instances inside notifications so the backplane must be
+//! trusted in the same way as the server.
+//!
+//! Two implementations are provided, [InProcessBackplane] which can be
+//! shared by multiple servers running in the same process and
+//! [RedisBackplane] which speaks the Redis protocol (RESP) and may be
+//! used with Redis or any compatible server.
+//!
+//! When the connection used by the Redis subscription is lost it is
+//! re-established with a backoff; messages published while the
+//! subscription is down are not delivered and the backplane reports
+//! that it is unavailable (see [Backplane::ping]) until it has
+//! resubscribed.
+use async_trait::async_trait;
+use futures_util::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::BufStream;
+use tokio::io::{
+    AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt,
+};
+use tokio::net::TcpStream;
+use tokio::sync::{broadcast, mpsc, Mutex};
+use uuid::Uuid;
+
+use crate::identity::ClientId;
+use crate::{
+    Blame, Group, Notification, Parameters, Result, ServerError, Session,
+    SessionKind,
+};
+
+/// Default channel used to publish messages.
+pub const DEFAULT_CHANNEL: &str = "mpc-websocket";
+
+/// Initial delay before re-establishing a lost subscription.
+const RECONNECT_DELAY: Duration = Duration::from_millis(100);
+
+/// Maximum delay before re-establishing a lost subscription.
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Error reported while a subscription is being re-established.
+const SUBSCRIPTION_LOST: &str = "subscription disconnected";
+
+/// Names of the shared counters for a session.
+const SESSION_COUNTERS: [&str; 4] = ["signup", "load", "finish", "confirm"];
+
+/// Transport for messages and shared data between server instances.
+#[async_trait]
+pub trait Backplane: fmt::Debug + Send + Sync {
+    /// Publish a message to all server instances.
+    async fn publish(&self, message: String) -> Result<()>;
+
+    /// Subscribe to the messages published by all server instances.
+    async fn subscribe(&self) -> Result<mpsc::UnboundedReceiver<String>>;
+
+    /// Get a value from the shared store.
+    async fn get(&self, key: &str) -> Result<Option<String>>;
+
+    /// Set a value in the shared store.
+    async fn set(&self, key: &str, value: String) -> Result<()>;
+
+    /// Delete a value from the shared store.
+    async fn delete(&self, key: &str) -> Result<()>;
+
+    /// Atomically increment a counter in the shared store
+    /// and return the new value.
+    async fn incr(&self, key: &str) -> Result<u64>;
+
+    /// Check the backplane is available, including any
+    /// subscriptions that have been created.
+    async fn ping(&self) -> Result<()>;
+}
+
+/// Message sent between server instances.
+#[derive(Debug, Serialize, Deserialize)]
+pub enum BackplaneMessage<N = Notification> {
+    /// Notification to deliver to locally connected clients.
+    Notify(N),
+    /// Group has changed.
+    Group(GroupSnapshot),
+    /// Client has disconnected.
+    Disconnect(ClientId),
+    /// Group was removed by an administrator.
+    RemoveGroup(Uuid),
+    /// Session was removed by an administrator.
+    RemoveSession(Uuid, Uuid),
+}
+
+/// Envelope for a message that identifies the sending instance.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Envelope<N = Notification> {
+    /// Identifier of the instance that published the message.
+    pub origin: Uuid,
+    /// The message.
+    pub message: BackplaneMessage<N>,
+}
+
+/// Snapshot of a group including the routing information
+/// that is not exposed to clients.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GroupSnapshot {
+    /// Unique identifier for the group.
+    pub uuid: Uuid,
+    /// Parameters for key generation.
+    pub params: Parameters,
+    /// Human-readable label for the group.
+    pub label: String,
+    /// Collection of client identities.
+    pub clients: Vec<ClientId>,
+    /// Sessions belonging to the group.
+    pub sessions: Vec<SessionSnapshot>,
+}
+
+/// Snapshot of a session.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionSnapshot {
+    /// Unique identifier for the session.
+    pub uuid: Uuid,
+    /// Kind of the session.
+    pub kind: SessionKind,
+    /// Public value associated with the session.
+    pub value: Option<Value>,
+    /// Map party number to client identity.
+    pub party_signups: Vec<(u16, ClientId)>,
+    /// Party numbers that have finished the session.
+    pub finished: HashSet<u16>,
+    /// Map receiver indices to party numbers.
+    pub participants: HashMap<u16, u16>,
+    /// Blame reported by parties that aborted the session.
+    #[serde(default)]
+    pub aborted: HashMap<u16, Blame>,
+    /// Public key hashes reported by parties.
+    #[serde(default)]
+    pub confirmations: HashMap<u16, String>,
+}
+
+impl From<&Group> for GroupSnapshot {
+    fn from(group: &Group) -> Self {
+        Self {
+            uuid: group.uuid,
+            params: group.params.clone(),
+            label: group.label.clone(),
+            clients: group.clients.clone(),
+            sessions: group
+                .sessions
+                .values()
+                .map(|session| SessionSnapshot {
+                    uuid: session.uuid,
+                    kind: session.kind.clone(),
+                    value: session.value.clone(),
+                    party_signups: session.party_signups.clone(),
+                    finished: session.finished.clone(),
+                    participants: session.participants.clone(),
+                    aborted: session.aborted.clone(),
+                    confirmations: session.confirmations.clone(),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl From<GroupSnapshot> for Group {
+    fn from(snapshot: GroupSnapshot) -> Self {
+        let mut group = Group {
+            uuid: snapshot.uuid,
+            params: snapshot.params.clone(),
+            label: snapshot.label.clone(),
+            clients: Default::default(),
+            sessions: Default::default(),
+        };
+        merge_group(&mut group, snapshot);
+        group
+    }
+}
+
+/// Merge a snapshot into a group.
+///
+/// Snapshots from different instances may be published concurrently
+/// so the merge only ever adds information; clients are removed from
+/// a group by a [BackplaneMessage::Disconnect] message.
+pub(crate) fn merge_group(group: &mut Group, snapshot: GroupSnapshot) {
+    for client_id in snapshot.clients {
+        if !group.clients.contains(&client_id) {
+            group.clients.push(client_id);
+        }
+    }
+
+    for incoming in snapshot.sessions {
+        let session =
+            group.sessions.entry(incoming.uuid).or_insert_with(|| {
+                let mut session = Session::from((
+                    incoming.kind.clone(),
+                    incoming.value.clone(),
+                ));
+                session.uuid = incoming.uuid;
+                session
+            });
+
+        for (number, client_id) in incoming.party_signups {
+            if !session.party_signups.iter().any(|(n, _)| *n == number) {
+                session.party_signups.push((number, client_id));
+            }
+        }
+        session.party_signups.sort_by_key(|(n, _)| *n);
+        session.finished.extend(incoming.finished);
+        session.participants.extend(incoming.participants);
+        session.aborted.extend(incoming.aborted);
+        session.confirmations.extend(incoming.confirmations);
+    }
+}
+
+/// Membership of a server instance in a cluster of
+/// instances that share a backplane.
+#[derive(Debug, Clone)]
+pub struct Cluster {
+    instance_id: Uuid,
+    backplane: Arc<dyn Backplane>,
+}
+
+impl Cluster {
+    /// Create a cluster member using a backplane.
+    pub fn new(backplane: Arc<dyn Backplane>) -> Self {
+        Self {
+            instance_id: Uuid::new_v4(),
+            backplane,
+        }
+    }
+
+    /// Unique identifier for this server instance.
+    pub fn instance_id(&self) -> &Uuid {
+        &self.instance_id
+    }
+
+    /// The backplane for the cluster.
+    pub fn backplane(&self) -> &Arc<dyn Backplane> {
+        &self.backplane
+    }
+
+    /// Allocate a connection identifier that is unique across
+    /// all the server instances.
+    pub async fn next_connection_id(&self) -> Result<usize> {
+        Ok(self.backplane.incr("connection-id").await? as usize)
+    }
+
+    /// Increment a shared counter for a session and return the new value.
+    ///
+    /// The counters are removed by [Cluster::remove_counters] when
+    /// the session is closed or removed.
+    pub async fn count(&self, session_id: &Uuid, name: &str) -> Result<usize> {
+        let key = format!("session:{}:{}", session_id, name);
+        Ok(self.backplane.incr(&key).await? as usize)
+    }
+
+    /// Remove the shared counters for a session.
+    pub async fn remove_counters(&self, session_id: &Uuid) -> Result<()> {
+        for name in SESSION_COUNTERS {
+            let key = format!("session:{}:{}", session_id, name);
+            self.backplane.delete(&key).await?;
+        }
+        Ok(())
+    }
+
+    /// Publish a notification to the other server instances.
+    pub async fn publish_notification(
+        &self,
+        notification: &Notification,
+    ) -> Result<()> {
+        self.publish(BackplaneMessage::Notify(notification)).await
+    }
+
+    /// Store a group snapshot and publish it to the other
+    /// server instances.
+    pub async fn publish_group(&self, snapshot: GroupSnapshot) -> Result<()> {
+        let key = format!("group:{}", snapshot.uuid);
+        self.backplane
+            .set(&key, serde_json::to_string(&snapshot)?)
+            .await?;
+        self.publish(BackplaneMessage::<&Notification>::Group(snapshot))
+            .await
+    }
+
+    /// Publish that a client has disconnected.
+    pub async fn publish_disconnect(&self, client_id: ClientId) -> Result<()> {
+        self.publish(BackplaneMessage::<&Notification>::Disconnect(client_id))
+            .await
+    }
+
+    /// Publish that a group was removed.
+    pub async fn publish_remove_group(&self, group_id: Uuid) -> Result<()> {
+        self.remove_group(&group_id).await?;
+        self.publish(BackplaneMessage::<&Notification>::RemoveGroup(group_id))
+            .await
+    }
+
+    /// Publish that a session was removed.
+    pub async fn publish_remove_session(
+        &self,
+        group_id: Uuid,
+        session_id: Uuid,
+    ) -> Result<()> {
+        self.remove_counters(&session_id).await?;
+        self.publish(BackplaneMessage::<&Notification>::RemoveSession(
+            group_id, session_id,
+        ))
+        .await
+    }
+
+    /// Load a group snapshot from the shared store.
+    pub async fn load_group(
+        &self,
+        group_id: &Uuid,
+    ) -> Result<Option<GroupSnapshot>> {
+        let key = format!("group:{}", group_id);
+        if let Some(value) = self.backplane.get(&key).await? {
+            Ok(Some(serde_json::from_str(&value)?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Remove a group snapshot from the shared store.
+    pub async fn remove_group(&self, group_id: &Uuid) -> Result<()> {
+        let key = format!("group:{}", group_id);
+        self.backplane.delete(&key).await
+    }
+
+    /// Subscribe to messages published by the other server instances.
+    ///
+    /// Messages published by this instance are ignored.
+    pub async fn subscribe(
+        &self,
+    ) -> Result<mpsc::UnboundedReceiver<BackplaneMessage>> {
+        let mut incoming = self.backplane.subscribe().await?;
+        let (tx, rx) = mpsc::unbounded_channel();
+        let instance_id = self.instance_id;
+        tokio::task::spawn(async move {
+            while let Some(message) = incoming.recv().await {
+                match serde_json::from_str::<Envelope>(&message) {
+                    Ok(envelope) => {
+                        if envelope.origin != instance_id
+                            && tx.send(envelope.message).is_err()
+                        {
+                            break;
+                        }
+                    }
+                    Err(e) => {
+                        tracing::warn!(?e, "backplane message JSON error")
+                    }
+                }
+            }
+        });
+        Ok(rx)
+    }
+
+    async fn publish(
+        &self,
+        message: BackplaneMessage<&Notification>,
+    ) -> Result<()> {
+        let envelope = Envelope {
+            origin: self.instance_id,
+            message,
+        };
+        self.backplane
+            .publish(serde_json::to_string(&envelope)?)
+            .await
+    }
+}
+
+/// Backplane for servers running in the same process.
+#[derive(Debug, Clone)]
+pub struct InProcessBackplane {
+    sender: broadcast::Sender<String>,
+    store: Arc<Mutex<HashMap<String, String>>>,
+    counters: Arc<Mutex<HashMap<String, u64>>>,
+}
+
+impl Default for InProcessBackplane {
+    fn default() -> Self {
+        let (sender, _) = broadcast::channel(1024);
+        Self {
+            sender,
+            store: Default::default(),
+            counters: Default::default(),
+        }
+    }
+}
+
+#[async_trait]
+impl Backplane for InProcessBackplane {
+    async fn publish(&self, message: String) -> Result<()> {
+        // No subscribers is not an error
+        let _ = self.sender.send(message);
+        Ok(())
+    }
+
+    async fn subscribe(&self) -> Result<mpsc::UnboundedReceiver<String>> {
+        let mut receiver = self.sender.subscribe();
+        let (tx, rx) = mpsc::unbounded_channel();
+        tokio::task::spawn(async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(message) => {
+                        if tx.send(message).is_err() {
+                            break;
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        tracing::warn!(skipped, "backplane receiver lagged");
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        });
+        Ok(rx)
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<String>> {
+        Ok(self.store.lock().await.get(key).cloned())
+    }
+
+    async fn set(&self, key: &str, value: String) -> Result<()> {
+        self.store.lock().await.insert(key.to_owned(), value);
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        // Counters share the key space with values
+        self.store.lock().await.remove(key);
+        self.counters.lock().await.remove(key);
+        Ok(())
+    }
+
+    async fn incr(&self, key: &str) -> Result<u64> {
+        let mut counters = self.counters.lock().await;
+        let value = counters.entry(key.to_owned()).or_insert(0);
+        *value += 1;
+        Ok(*value)
+    }
+
+    async fn ping(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Reply from a server that speaks the Redis protocol.
+#[derive(Debug)]
+enum Reply {
+    Status(String),
+    Integer(i64),
+    Bulk(Option<Vec<u8>>),
+    Array(Option<Vec<Reply>>),
+}
+
+impl Reply {
+    fn into_bulk_string(self) -> Option<String> {
+        match self {
+            Reply::Bulk(Some(bytes)) => String::from_utf8(bytes).ok(),
+            Reply::Status(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+type Connection = BufStream<TcpStream>;
+
+/// Backplane using a server that speaks the Redis protocol.
+///
+/// Messages are published to a single channel and shared data
+/// is stored using keys prefixed with the channel name.
+#[derive(Debug)]
+pub struct RedisBackplane {
+    addr: String,
+    channel: String,
+    connection: Mutex<Option<Connection>>,
+    subscribed: Arc<AtomicBool>,
+}
+
+impl RedisBackplane {
+    /// Create a backplane for the server at `addr` (`host:port`).
+    ///
+    /// Connections are established lazily so the server
+    /// does not need to be available yet.
+    pub fn new(addr: impl Into<String>, channel: impl Into<String>) -> Self {
+        Self {
+            addr: addr.into(),
+            channel: channel.into(),
+            connection: Mutex::new(None),
+            subscribed: Arc::new(AtomicBool::new(true)),
+        }
+    }
+
+    fn key(&self, key: &str) -> String {
+        format!("{}:{}", self.channel, key)
+    }
+
+    async fn connect(&self) -> Result<Connection> {
+        connect(&self.addr).await
+    }
+
+    /// Send a command and read the reply.
+    ///
+    /// The connection is taken out of the mutex while the command is
+    /// in flight and only returned once the reply has been read so a
+    /// command that fails or is cancelled (for example by a timeout)
+    /// can never leave an unread reply for the next command.
+    async fn command(&self, args: &[&[u8]]) -> Result<Reply> {
+        let mut guard = self.connection.lock().await;
+        let mut connection = match guard.take() {
+            Some(connection) => connection,
+            None => self.connect().await?,
+        };
+        let reply = async {
+            write_command(&mut connection, args).await?;
+            read_reply(&mut connection).await
+        }
+        .await?;
+        *guard = Some(connection);
+        Ok(reply)
+    }
+}
+
+#[async_trait]
+impl Backplane for RedisBackplane {
+    async fn publish(&self, message: String) -> Result<()> {
+        self.command(&[
+            b"PUBLISH",
+            self.channel.as_bytes(),
+            message.as_bytes(),
+        ])
+        .await?;
+        Ok(())
+    }
+
+    async fn subscribe(&self) -> Result<mpsc::UnboundedReceiver<String>> {
+        let mut connection = subscription(&self.addr, &self.channel).await?;
+        let (tx, rx) = mpsc::unbounded_channel();
+        let addr = self.addr.clone();
+        let channel = self.channel.clone();
+        let subscribed = Arc::clone(&self.subscribed);
+        tokio::task::spawn(async move {
+            loop {
+                match read_reply(&mut connection).await {
+                    Ok(Reply::Array(Some(mut items))) if items.len() == 3 => {
+                        if let Some(message) =
+                            items.pop().unwrap().into_bulk_string()
+                        {
+                            if tx.send(message).is_err() {
+                                break;
+                            }
+                        }
+                    }
+                    Ok(reply) => {
+                        tracing::debug!(?reply, "backplane subscription reply")
+                    }
+                    Err(e) => {
+                        tracing::error!(?e, "backplane subscription error");
+                        subscribed.store(false, Ordering::SeqCst);
+                        let mut delay = RECONNECT_DELAY;
+                        connection = loop {
+                            if tx.is_closed() {
+                                return;
+                            }
+                            tokio::time::sleep(delay).await;
+                            match subscription(&addr, &channel).await {
+                                Ok(connection) => break connection,
+                                Err(e) => {
+                                    tracing::warn!(
+                                        ?e,
+                                        "backplane resubscribe error"
+                                    );
+                                    delay =
+                                        (delay * 2).min(MAX_RECONNECT_DELAY);
+                                }
+                            }
+                        };
+                        subscribed.store(true, Ordering::SeqCst);
+                        tracing::info!("backplane resubscribed");
+                    }
+                }
+            }
+        });
+        Ok(rx)
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<String>> {
+        let key = self.key(key);
+        let reply = self.command(&[b"GET", key.as_bytes()]).await?;
+        Ok(reply.into_bulk_string())
+    }
+
+    async fn set(&self, key: &str, value: String) -> Result<()> {
+        let key = self.key(key);
+        self.command(&[b"SET", key.as_bytes(), value.as_bytes()])
+            .await?;
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        let key = self.key(key);
+        self.command(&[b"DEL", key.as_bytes()]).await?;
+        Ok(())
+    }
+
+    async fn incr(&self, key: &str) -> Result<u64> {
+        let key = self.key(key);
+        match self.command(&[b"INCR", key.as_bytes()]).await? {
+            Reply::Integer(value) => Ok(value as u64),
+            reply => Err(ServerError::Backplane(format!(
+                "unexpected reply to INCR: {:?}",
+                reply
+            ))),
+        }
+    }
+
+    async fn ping(&self) -> Result<()> {
+        if !self.subscribed.load(Ordering::SeqCst) {
+            return Err(ServerError::Backplane(SUBSCRIPTION_LOST.to_owned()));
+        }
+        self.command(&[b"PING"]).await?;
+        Ok(())
+    }
+}
+
+async fn connect(addr: &str) -> Result<Connection> {
+    Ok(BufStream::new(TcpStream::connect(addr).await?))
+}
+
+/// Open a dedicated connection subscribed to a channel.
+async fn subscription(addr: &str, channel: &str) -> Result<Connection> {
+    let mut connection = connect(addr).await?;
+    write_command(&mut connection, &[b"SUBSCRIBE", channel.as_bytes()]).await?;
+    read_reply(&mut connection).await?;
+    Ok(connection)
+}
+
+/// Write a command as an array of bulk strings.
+async fn write_command<W>(connection: &mut W, args: &[&[u8]]) -> Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    let mut buffer = format!("*{}\r\n", args.len()).into_bytes();
+    for arg in args {
+        buffer.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+        buffer.extend_from_slice(arg);
+        buffer.extend_from_slice(b"\r\n");
+    }
+    connection.write_all(&buffer).await?;
+    connection.flush().await?;
+    Ok(())
+}
+
+/// Read a reply.
+fn read_reply<R>(reader: &mut R) -> BoxFuture<'_, Result<Reply>>
+where
+    R: AsyncBufRead + Unpin + Send,
+{
+    Box::pin(async move {
+        let mut line = String::new();
+        if reader.read_line(&mut line).await? == 0 {
+            return Err(ServerError::Io(std::io::Error::from(
+                std::io::ErrorKind::UnexpectedEof,
+            )));
+        }
+        let line = line.trim_end_matches("\r\n");
+        if line.is_empty() {
+            return Err(ServerError::Backplane("empty reply".to_owned()));
+        }
+        let (prefix, value) = line.split_at(1);
+        match prefix {
+            "+" => Ok(Reply::Status(value.to_owned())),
+            "-" => Err(ServerError::Backplane(value.to_owned())),
+            ":" => Ok(Reply::Integer(parse_length(value)?)),
+            "$" => {
+                let len = parse_length(value)?;
+                if len < 0 {
+                    return Ok(Reply::Bulk(None));
+                }
+                // Includes the trailing CRLF
+                let mut bytes = vec![0u8; len as usize + 2];
+                reader.read_exact(&mut bytes).await?;
+                bytes.truncate(len as usize);
+                Ok(Reply::Bulk(Some(bytes)))
+            }
+            "*" => {
+                let len = parse_length(value)?;
+                if len < 0 {
+                    return Ok(Reply::Array(None));
+                }
+                let mut items = Vec::with_capacity(len as usize);
+                for _ in 0..len {
+                    items.push(read_reply(reader).await?);
+                }
+                Ok(Reply::Array(Some(items)))
+            }
+            _ => Err(ServerError::Backplane(format!(
+                "unknown reply type {}",
+                prefix
+            ))),
+        }
+    })
+}
+
+fn parse_length(value: &str) -> Result<i64> {
+    value
+        .parse::<i64>()
+        .map_err(|_| ServerError::Backplane(format!("bad length {}", value)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+    use tokio::net::TcpListener;
+
+    /// State shared by the connections to a [RespServer].
+    #[derive(Default)]
+    struct RespState {
+        store: HashMap<String, String>,
+        subscribers: Vec<mpsc::UnboundedSender<(String, String)>>,
+        reject_subscribe: bool,
+    }
+
+    /// Minimal server that speaks the Redis protocol.
+    struct RespServer {
+        addr: String,
+        state: Arc<Mutex<RespState>>,
+        disconnect: broadcast::Sender<()>,
+    }
+
+    impl RespServer {
+        /// Start a server that waits for `ping_delay`
+        /// before replying to `PING`.
+        async fn start(ping_delay: Duration) -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap().to_string();
+            let state: Arc<Mutex<RespState>> = Default::default();
+            let (disconnect, _) = broadcast::channel(1);
+            let server = Self {
+                addr,
+                state: Arc::clone(&state),
+                disconnect: disconnect.clone(),
+            };
+            tokio::task::spawn(async move {
+                while let Ok((stream, _)) = listener.accept().await {
+                    tokio::task::spawn(Self::serve(
+                        BufStream::new(stream),
+                        Arc::clone(&state),
+                        ping_delay,
+                        disconnect.subscribe(),
+                    ));
+                }
+            });
+            server
+        }
+
+        /// Close every open connection.
+        fn disconnect(&self) {
+            let _ = self.disconnect.send(());
+        }
+
+        async fn serve(
+            mut stream: Connection,
+            state: Arc<Mutex<RespState>>,
+            ping_delay: Duration,
+            mut disconnect: broadcast::Receiver<()>,
+        ) {
+            loop {
+                let request = tokio::select! {
+                    request = read_reply(&mut stream) => request,
+                    _ = disconnect.recv() => return,
+                };
+                let args = match request {
+                    Ok(Reply::Array(Some(items))) => items
+                        .into_iter()
+                        .filter_map(Reply::into_bulk_string)
+                        .collect::<Vec<_>>(),
+                    _ => return,
+                };
+
+                if args.len() == 2 && args[0] == "SUBSCRIBE" {
+                    let mut writer = state.lock().await;
+                    if writer.reject_subscribe {
+                        drop(writer);
+                        let reply = "-ERR subscriptions disabled\r\n";
+                        if !Self::write(&mut stream, reply).await {
+                            return;
+                        }
+                        continue;
+                    }
+                    let (tx, mut rx) = mpsc::unbounded_channel();
+                    writer.subscribers.push(tx);
+                    drop(writer);
+
+                    let reply = format!(
+                        "*3\r\n{}{}:1\r\n",
+                        bulk("subscribe"),
+                        bulk(&args[1])
+                    );
+                    let mut open = Self::write(&mut stream, &reply).await;
+                    while open {
+                        tokio::select! {
+                            Some((channel, message)) = rx.recv() => {
+                                let reply = format!(
+                                    "*3\r\n{}{}{}",
+                                    bulk("message"),
+                                    bulk(&channel),
+                                    bulk(&message)
+                                );
+                                open = Self::write(&mut stream, &reply).await;
+                            }
+                            _ = disconnect.recv() => return,
+                        }
+                    }
+                    return;
+                }
+
+                let reply = Self::reply(&args, &state, ping_delay).await;
+                if !Self::write(&mut stream, &reply).await {
+                    return;
+                }
+            }
+        }
+
+        async fn write(stream: &mut Connection, reply: &str) -> bool {
+            stream.write_all(reply.as_bytes()).await.is_ok()
+                && stream.flush().await.is_ok()
+        }
+
+        async fn reply(
+            args: &[String],
+            state: &Mutex<RespState>,
+            ping_delay: Duration,
+        ) -> String {
+            let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+            match args[..] {
+                ["PING"] => {
+                    tokio::time::sleep(ping_delay).await;
+                    "+PONG\r\n".to_owned()
+                }
+                ["GET", key] => match state.lock().await.store.get(key) {
+                    Some(value) => bulk(value),
+                    None => "$-1\r\n".to_owned(),
+                },
+                ["SET", key, value] => {
+                    let mut state = state.lock().await;
+                    state.store.insert(key.to_owned(), value.to_owned());
+                    "+OK\r\n".to_owned()
+                }
+                ["INCR", key] => {
+                    let mut state = state.lock().await;
+                    let value = state.store.entry(key.to_owned()).or_default();
+                    let next = value.parse::<i64>().unwrap_or(0) + 1;
+                    *value = next.to_string();
+                    format!(":{}\r\n", next)
+                }
+                ["PUBLISH", channel, message] => {
+                    let mut state = state.lock().await;
+                    state.subscribers.retain(|tx| {
+                        tx.send((channel.to_owned(), message.to_owned()))
+                            .is_ok()
+                    });
+                    format!(":{}\r\n", state.subscribers.len())
+                }
+                _ => "-ERR unknown command\r\n".to_owned(),
+            }
+        }
+    }
+
+    fn bulk(value: &str) -> String {
+        format!("${}\r\n{}\r\n", value.len(), value)
+    }
+
+    /// Wait until the backplane reports the subscription
+    /// is connected or disconnected.
+    async fn wait_for_subscription(
+        backplane: &RedisBackplane,
+        connected: bool,
+    ) {
+        let wait = async {
+            loop {
+                match backplane.ping().await {
+                    Ok(_) if connected => break,
+                    Err(ServerError::Backplane(message))
+                        if !connected && message == SUBSCRIPTION_LOST =>
+                    {
+                        break
+                    }
+                    _ => tokio::time::sleep(Duration::from_millis(10)).await,
+                }
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(5), wait)
+            .await
+            .expect("subscription state did not change");
+    }
+
+    fn client_id(byte: u8) -> ClientId {
+        ClientId::from_key(&[byte; 32]).unwrap()
+    }
+
+    fn group(parties: u16) -> Group {
+        Group {
+            uuid: Uuid::new_v4(),
+            params: Parameters {
+                parties,
+                threshold: 1,
+            },
+            label: String::from("test"),
+            clients: Default::default(),
+            sessions: Default::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn in_process_store_and_counters() -> Result<()> {
+        let backplane = InProcessBackplane::default();
+        assert_eq!(None, backplane.get("key").await?);
+        backplane.set("key", String::from("value")).await?;
+        assert_eq!(Some(String::from("value")), backplane.get("key").await?);
+        backplane.delete("key").await?;
+        assert_eq!(None, backplane.get("key").await?);
+
+        assert_eq!(1, backplane.incr("counter").await?);
+        assert_eq!(2, backplane.incr("counter").await?);
+        backplane.delete("counter").await?;
+        assert_eq!(1, backplane.incr("counter").await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn in_process_publish_reaches_subscribers() -> Result<()> {
+        let backplane = InProcessBackplane::default();
+        let mut first = backplane.subscribe().await?;
+        let mut second = backplane.subscribe().await?;
+        backplane.publish(String::from("message")).await?;
+        assert_eq!(Some(String::from("message")), first.recv().await);
+        assert_eq!(Some(String::from("message")), second.recv().await);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn cluster_counts_per_session() -> Result<()> {
+        let cluster = Cluster::new(Arc::new(InProcessBackplane::default()));
+        let (first, second) = (Uuid::new_v4(), Uuid::new_v4());
+        assert_eq!(1, cluster.count(&first, "signup").await?);
+        assert_eq!(2, cluster.count(&first, "signup").await?);
+        assert_eq!(1, cluster.count(&first, "finish").await?);
+        assert_eq!(1, cluster.count(&second, "signup").await?);
+
+        cluster.remove_counters(&first).await?;
+        assert_eq!(1, cluster.count(&first, "signup").await?);
+        assert_eq!(1, cluster.count(&first, "finish").await?);
+        assert_eq!(2, cluster.count(&second, "signup").await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn cluster_ignores_own_messages() -> Result<()> {
+        let backplane: Arc<dyn Backplane> =
+            Arc::new(InProcessBackplane::default());
+        let (first, second) = (
+            Cluster::new(Arc::clone(&backplane)),
+            Cluster::new(backplane),
+        );
+        let mut first_rx = first.subscribe().await?;
+        let mut second_rx = second.subscribe().await?;
+
+        first.publish_disconnect(client_id(1)).await?;
+        match second_rx.recv().await {
+            Some(BackplaneMessage::Disconnect(id)) => {
+                assert_eq!(client_id(1), id)
+            }
+            message => panic!("unexpected message {:?}", message),
+        }
+        assert!(first_rx.try_recv().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn merge_group_only_adds() {
+        let mut local = group(3);
+        let mut session = Session::from((SessionKind::Keygen, None));
+        session.party_signups.push((1, client_id(1)));
+        session.finished.insert(1);
+        local.clients.push(client_id(1));
+        local.sessions.insert(session.uuid, session.clone());
+
+        let mut remote = local.clone();
+        remote.clients.push(client_id(2));
+        let remote_session = remote.sessions.get_mut(&session.uuid).unwrap();
+        remote_session.party_signups.push((2, client_id(2)));
+        remote_session.finished.clear();
+        remote_session.confirmations.insert(2, String::from("hash"));
+        let other = Session::from((SessionKind::Sign, None));
+        remote.sessions.insert(other.uuid, other.clone());
+
+        merge_group(&mut local, GroupSnapshot::from(&remote));
+
+        assert_eq!(vec![client_id(1), client_id(2)], local.clients);
+        let merged = local.sessions.get(&session.uuid).unwrap();
+        assert_eq!(
+            vec![(1, client_id(1)), (2, client_id(2))],
+            merged.party_signups
+        );
+        // Finished parties are not removed by an older snapshot
+        assert!(merged.finished.contains(&1));
+        assert_eq!(Some(&String::from("hash")), merged.confirmations.get(&2));
+        assert!(local.sessions.contains_key(&other.uuid));
+    }
+
+    #[test]
+    fn group_from_snapshot() {
+        let mut remote = group(2);
+        remote.clients.push(client_id(1));
+        let mut session = Session::from((SessionKind::Keygen, None));
+        session.party_signups.push((1, client_id(1)));
+        remote.sessions.insert(session.uuid, session.clone());
+
+        let snapshot: GroupSnapshot = serde_json::from_str(
+            &serde_json::to_string(&GroupSnapshot::from(&remote)).unwrap(),
+        )
+        .unwrap();
+        let group: Group = snapshot.into();
+        assert_eq!(remote.uuid, group.uuid);
+        assert_eq!(remote.clients, group.clients);
+        assert_eq!(
+            session.party_signups,
+            group.sessions.get(&session.uuid).unwrap().party_signups
+        );
+    }
+
+    #[tokio::test]
+    async fn redis_command_cancelled_before_reply() -> Result<()> {
+        let server = RespServer::start(Duration::from_millis(200)).await;
+        let backplane = RedisBackplane::new(server.addr.clone(), "test");
+        backplane.set("key", String::from("value")).await?;
+
+        // Cancel the command after it is written but before
+        // the reply has been read
+        let ping =
+            tokio::time::timeout(Duration::from_millis(20), backplane.ping());
+        assert!(ping.await.is_err());
+
+        assert_eq!(Some(String::from("value")), backplane.get("key").await?);
+        assert_eq!(1, backplane.incr("counter").await?);
+        assert_eq!(2, backplane.incr("counter").await?);
+        backplane.ping().await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn redis_publish_reaches_subscribers() -> Result<()> {
+        let server = RespServer::start(Duration::ZERO).await;
+        let first = RedisBackplane::new(server.addr.clone(), "test");
+        let second = RedisBackplane::new(server.addr.clone(), "test");
+        let mut first_rx = first.subscribe().await?;
+        let mut second_rx = second.subscribe().await?;
+
+        first.publish(String::from("message")).await?;
+        assert_eq!(Some(String::from("message")), first_rx.recv().await);
+        assert_eq!(Some(String::from("message")), second_rx.recv().await);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn redis_subscription_reconnects() -> Result<()> {
+        let server = RespServer::start(Duration::ZERO).await;
+        let backplane = RedisBackplane::new(server.addr.clone(), "test");
+        let mut incoming = backplane.subscribe().await?;
+        backplane.publish(String::from("first")).await?;
+        assert_eq!(Some(String::from("first")), incoming.recv().await);
+
+        // Not ready while the subscription cannot be re-established
+        server.state.lock().await.reject_subscribe = true;
+        server.disconnect();
+        wait_for_subscription(&backplane, false).await;
+
+        server.state.lock().await.reject_subscribe = false;
+        wait_for_subscription(&backplane, true).await;
+
+        backplane.publish(String::from("second")).await?;
+        let message =
+            tokio::time::timeout(Duration::from_secs(5), incoming.recv())
+                .await
+                .unwrap();
+        assert_eq!(Some(String::from("second")), message);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn write_command_frames_bulk_strings() -> Result<()> {
+        let mut buffer: Vec<u8> = Vec::new();
+        write_command(&mut buffer, &[b"SET", b"key", b"a\r\nb"]).await?;
+        assert_eq!(
+            b"*3\r\n$3\r\nSET\r\n$3\r\nkey\r\n$4\r\na\r\nb\r\n".to_vec(),
+            buffer
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn read_reply_parses_each_type() -> Result<()> {
+        let mut reader: &[u8] = b"+OK\r\n:42\r\n$4\r\na\r\nb\r\n$-1\r\n*-1\r\n";
+        assert!(matches!(
+            read_reply(&mut reader).await?,
+            Reply::Status(value) if value == "OK"
+        ));
+        assert!(matches!(read_reply(&mut reader).await?, Reply::Integer(42)));
+        assert_eq!(
+            Some(String::from("a\r\nb")),
+            read_reply(&mut reader).await?.into_bulk_string()
+        );
+        assert!(matches!(read_reply(&mut reader).await?, Reply::Bulk(None)));
+        assert!(matches!(read_reply(&mut reader).await?, Reply::Array(None)));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn read_reply_parses_nested_arrays() -> Result<()> {
+        let mut reader: &[u8] =
+            b"*3\r\n$7\r\nmessage\r\n$4\r\nchan\r\n*1\r\n:1\r\n";
+        match read_reply(&mut reader).await? {
+            Reply::Array(Some(mut items)) => {
+                assert_eq!(3, items.len());
+                assert!(matches!(
+                    items.pop().unwrap(),
+                    Reply::Array(Some(inner)) if inner.len() == 1
+                ));
+                assert_eq!(
+                    Some(String::from("chan")),
+                    items.pop().unwrap().into_bulk_string()
+                );
+            }
+            reply => panic!("unexpected reply {:?}", reply),
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn read_reply_errors() {
+        let mut reader: &[u8] = b"-ERR unknown command\r\n";
+        assert!(matches!(
+            read_reply(&mut reader).await,
+            Err(ServerError::Backplane(message))
+                if message == "ERR unknown command"
+        ));
+
+        let mut reader: &[u8] = b"?\r\n";
+        assert!(matches!(
+            read_reply(&mut reader).await,
+            Err(ServerError::Backplane(_))
+        ));
+
+        let mut reader: &[u8] = b":nan\r\n";
+        assert!(matches!(
+            read_reply(&mut reader).await,
+            Err(ServerError::Backplane(_))
+        ));
+
+        let mut reader: &[u8] = b"";
+        assert!(matches!(
+            read_reply(&mut reader).await,
+            Err(ServerError::Io(_))
+        ));
+    }
+}
@@ -0,0 +1,320 @@
+//! Compression for large websocket messages.
+//!
+//! The websocket implementation does not support the permessage-deflate
+//! extension so compression is applied by the application. A client
+//! negotiates compression when it connects by adding a `compression`
+//! query parameter to the websocket URL, for example:
+//! `/mpc?compression=deflate`.
+//!
+//! When compression is negotiated a framed message is a binary frame
+//! that starts with a single byte that indicates whether the remaining
+//! bytes are compressed (`1`) using raw DEFLATE ([RFC 1951]) or not
+//! (`0`). The uncompressed bytes are the message in the negotiated
+//! [encoding](crate::encoding); for JSON they are UTF-8 text.
+//!
+//! The server frames messages that are at least
+//! [COMPRESSION_THRESHOLD] bytes (typically the round messages that
+//! are relayed to other parties); smaller messages are sent unchanged
+//! for every encoding, JSON as text frames and the binary encodings
+//! as binary frames without the flag. Messages in a binary encoding
+//! always start with a map marker so a first byte of `0` or `1`
+//! identifies a framed message. Clients may send framed or unframed
+//! binary frames; text frames from clients are always uncompressed
+//! JSON.
+//!
+//! [RFC 1951]: https://www.rfc-editor.org/rfc/rfc1951
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::{Result, ServerError};
+
+/// Minimum size of a message before it is compressed.
+pub const COMPRESSION_THRESHOLD: usize = 1024;
+
+/// Maximum size of a decompressed message.
+pub const DECOMPRESSION_LIMIT: usize = 16 * 1024 * 1024;
+
+/// Compression level used for DEFLATE.
+const LEVEL: u8 = 6;
+
+/// Flag for uncompressed bytes.
+const UNCOMPRESSED: u8 = 0;
+
+/// Flag for compressed bytes.
+const DEFLATE: u8 = 1;
+
+/// Compression for messages on a connection.
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    /// Messages are not compressed.
+    #[default]
+    None,
+    /// Large messages are compressed using raw DEFLATE.
+    Deflate,
+}
+
+impl Compression {
+    /// Name of the compression.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Compression::None => "none",
+            Compression::Deflate => "deflate",
+        }
+    }
+
+    /// Frame bytes for a binary message, compressing when
+    /// the bytes are larger than the threshold.
+    pub(crate) fn compress(
+        &self,
+        bytes: Vec<u8>,
+        metrics: &CompressionMetrics,
+    ) -> Vec<u8> {
+        if *self == Compression::None {
+            return bytes;
+        }
+
+        if bytes.len() >= COMPRESSION_THRESHOLD {
+            let compressed =
+                miniz_oxide::deflate::compress_to_vec(&bytes, LEVEL);
+            metrics.record(bytes.len(), compressed.len());
+            if compressed.len() < bytes.len() {
+                let mut framed = Vec::with_capacity(compressed.len() + 1);
+                framed.push(DEFLATE);
+                framed.extend_from_slice(&compressed);
+                return framed;
+            }
+        }
+
+        let mut framed = Vec::with_capacity(bytes.len() + 1);
+        framed.push(UNCOMPRESSED);
+        framed.extend_from_slice(&bytes);
+        framed
+    }
+
+    /// Whether the bytes of a binary message start with a
+    /// compression flag.
+    pub(crate) fn is_framed(bytes: &[u8]) -> bool {
+        matches!(bytes.first(), Some(&UNCOMPRESSED) | Some(&DEFLATE))
+    }
+
+    /// Unframe the bytes of a binary message, decompressing
+    /// when necessary.
+    pub(crate) fn decompress(&self, bytes: &[u8]) -> Result<Vec<u8>> {
+        if *self == Compression::None {
+            return Ok(bytes.to_vec());
+        }
+
+        match bytes.split_first() {
+            Some((&UNCOMPRESSED, rest)) => Ok(rest.to_vec()),
+            Some((&DEFLATE, rest)) => {
+                miniz_oxide::inflate::decompress_to_vec_with_limit(
+                    rest,
+                    DECOMPRESSION_LIMIT,
+                )
+                .map_err(|e| ServerError::Encoding(format!("{:?}", e.status)))
+            }
+            _ => Err(ServerError::Encoding(
+                "invalid compression flag".to_owned(),
+            )),
+        }
+    }
+}
+
+/// Metrics for compressed messages.
+#[derive(Debug, Default)]
+pub struct CompressionMetrics {
+    messages: AtomicU64,
+    uncompressed_bytes: AtomicU64,
+    compressed_bytes: AtomicU64,
+}
+
+impl CompressionMetrics {
+    fn record(&self, uncompressed: usize, compressed: usize) {
+        self.messages.fetch_add(1, Ordering::Relaxed);
+        self.uncompressed_bytes
+            .fetch_add(uncompressed as u64, Ordering::Relaxed);
+        self.compressed_bytes
+            .fetch_add(compressed as u64, Ordering::Relaxed);
+    }
+
+    /// Snapshot of the metrics.
+    pub fn snapshot(&self) -> CompressionSnapshot {
+        let uncompressed_bytes =
+            self.uncompressed_bytes.load(Ordering::Relaxed);
+        let compressed_bytes = self.compressed_bytes.load(Ordering::Relaxed);
+        let ratio = if compressed_bytes > 0 {
+            uncompressed_bytes as f64 / compressed_bytes as f64
+        } else {
+            1.0
+        };
+        CompressionSnapshot {
+            messages: self.messages.load(Ordering::Relaxed),
+            uncompressed_bytes,
+            compressed_bytes,
+            ratio,
+        }
+    }
+}
+
+/// Snapshot of the compression metrics.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompressionSnapshot {
+    /// Number of messages that were compressed.
+    pub messages: u64,
+    /// Total size of the messages before compression.
+    pub uncompressed_bytes: u64,
+    /// Total size of the messages after compression.
+    pub compressed_bytes: u64,
+    /// Compression ratio (uncompressed / compressed).
+    pub ratio: f64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Bytes that do not compress.
+    fn noise(len: usize) -> Vec<u8> {
+        let mut state: u32 = 0x9e37_79b9;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn none_passes_bytes_through() {
+        let metrics = CompressionMetrics::default();
+        let bytes = vec![b'a'; COMPRESSION_THRESHOLD * 2];
+        let framed = Compression::None.compress(bytes.clone(), &metrics);
+        assert_eq!(bytes, framed);
+        assert_eq!(bytes, Compression::None.decompress(&framed).unwrap());
+        assert_eq!(0, metrics.snapshot().messages);
+    }
+
+    #[test]
+    fn small_messages_are_not_compressed() {
+        let metrics = CompressionMetrics::default();
+        let bytes = vec![b'a'; COMPRESSION_THRESHOLD - 1];
+        let framed = Compression::Deflate.compress(bytes.clone(), &metrics);
+        assert_eq!(UNCOMPRESSED, framed[0]);
+        assert_eq!(&bytes[..], &framed[1..]);
+        assert_eq!(bytes, Compression::Deflate.decompress(&framed).unwrap());
+        assert_eq!(0, metrics.snapshot().messages);
+    }
+
+    #[test]
+    fn large_messages_are_compressed() {
+        let metrics = CompressionMetrics::default();
+        let bytes = vec![b'a'; COMPRESSION_THRESHOLD];
+        let framed = Compression::Deflate.compress(bytes.clone(), &metrics);
+        assert_eq!(DEFLATE, framed[0]);
+        assert!(framed.len() < bytes.len());
+        assert_eq!(
+            bytes,
+            miniz_oxide::inflate::decompress_to_vec(&framed[1..]).unwrap()
+        );
+        assert_eq!(bytes, Compression::Deflate.decompress(&framed).unwrap());
+    }
+
+    #[test]
+    fn incompressible_messages_are_not_compressed() {
+        let metrics = CompressionMetrics::default();
+        let bytes = noise(COMPRESSION_THRESHOLD * 2);
+        let framed = Compression::Deflate.compress(bytes.clone(), &metrics);
+        assert_eq!(UNCOMPRESSED, framed[0]);
+        assert_eq!(&bytes[..], &framed[1..]);
+        // The attempt is still recorded
+        assert_eq!(1, metrics.snapshot().messages);
+    }
+
+    #[test]
+    fn decompress_rejects_invalid_frames() {
+        let compression = Compression::Deflate;
+        assert!(matches!(
+            compression.decompress(&[]),
+            Err(ServerError::Encoding(_))
+        ));
+        assert!(matches!(
+            compression.decompress(&[2, b'a']),
+            Err(ServerError::Encoding(_))
+        ));
+        assert!(matches!(
+            compression.decompress(&[DEFLATE, 0xff, 0xff]),
+            Err(ServerError::Encoding(_))
+        ));
+        assert_eq!(
+            Vec::<u8>::new(),
+            compression.decompress(&[UNCOMPRESSED]).unwrap()
+        );
+    }
+
+    #[test]
+    fn decompress_enforces_limit() {
+        let bytes = vec![0; DECOMPRESSION_LIMIT + 1];
+        let mut framed = vec![DEFLATE];
+        framed.extend(miniz_oxide::deflate::compress_to_vec(&bytes, LEVEL));
+        assert!(matches!(
+            Compression::Deflate.decompress(&framed),
+            Err(ServerError::Encoding(_))
+        ));
+    }
+
+    #[test]
+    fn metrics_snapshot_ratio() {
+        let metrics = CompressionMetrics::default();
+        let snapshot = metrics.snapshot();
+        assert_eq!(0, snapshot.messages);
+        assert_eq!(1.0, snapshot.ratio);
+
+        metrics.record(4000, 1000);
+        metrics.record(2000, 1000);
+        let snapshot = metrics.snapshot();
+        assert_eq!(2, snapshot.messages);
+        assert_eq!(6000, snapshot.uncompressed_bytes);
+        assert_eq!(2000, snapshot.compressed_bytes);
+        assert_eq!(3.0, snapshot.ratio);
+
+        let value = serde_json::to_value(&snapshot).unwrap();
+        assert_eq!(
+            serde_json::json!({
+                "messages": 2,
+                "uncompressedBytes": 6000,
+                "compressedBytes": 2000,
+                "ratio": 3.0,
+            }),
+            value
+        );
+    }
+
+    #[test]
+    fn compress_records_metrics() {
+        let metrics = CompressionMetrics::default();
+        let bytes = vec![b'a'; COMPRESSION_THRESHOLD * 4];
+        let framed = Compression::Deflate.compress(bytes.clone(), &metrics);
+        let snapshot = metrics.snapshot();
+        assert_eq!(1, snapshot.messages);
+        assert_eq!(bytes.len() as u64, snapshot.uncompressed_bytes);
+        assert_eq!((framed.len() - 1) as u64, snapshot.compressed_bytes);
+        assert!(snapshot.ratio > 1.0);
+    }
+
+    #[test]
+    fn compression_names() {
+        assert_eq!("none", Compression::None.name());
+        assert_eq!("deflate", Compression::Deflate.name());
+        assert_eq!(
+            Compression::Deflate,
+            serde_json::from_str("\"deflate\"").unwrap()
+        );
+        assert_eq!(Compression::None, Compression::default());
+    }
+}
@@ -0,0 +1,163 @@
+//! Origin allow-list for websocket upgrades and CORS for HTTP routes.
+//!
+//! Browsers send an `Origin` header with websocket upgrade requests
+//! but do not apply the same-origin policy to websockets so without
+//! an allow-list any web page could open a connection to the server.
+//!
+//! When allowed origins are configured upgrade requests from other
+//! origins are rejected with `403 Forbidden`; requests without an
+//! `Origin` header (non-browser clients) are accepted.
+//!
+//! The same list is used for CORS on the HTTP routes so when the
+//! dapp is served by this server its own origin should be included.
+use warp::cors::Builder;
+use warp::http::uri::Authority;
+use warp::http::{Method, Uri};
+
+use crate::{Result, ServerError};
+
+/// Normalize an origin for comparison.
+fn normalize(origin: &str) -> String {
+    origin.trim().trim_end_matches('/').to_ascii_lowercase()
+}
+
+/// Parse an origin that has a scheme and authority but no path
+/// (other than `/`) in the same way as the warp CORS filter.
+fn parse(origin: &str) -> Result<String> {
+    let invalid = || ServerError::InvalidOrigin(origin.to_owned());
+    let parts = origin
+        .trim()
+        .parse::<Uri>()
+        .map_err(|_| invalid())?
+        .into_parts();
+    match (parts.scheme, parts.authority, parts.path_and_query) {
+        (Some(scheme), Some(authority), path)
+            if !scheme.as_str().is_empty()
+                && host_and_port(&authority) == authority.as_str()
+                && path.as_ref().map_or("/", |p| p.as_str()) == "/" =>
+        {
+            Ok(normalize(&format!("{}://{}", scheme, authority)))
+        }
+        _ => Err(invalid()),
+    }
+}
+
+/// Authority without user information and with a numeric port.
+fn host_and_port(authority: &Authority) -> String {
+    match authority.port_u16() {
+        Some(port) => format!("{}:{}", authority.host(), port),
+        None => authority.host().to_owned(),
+    }
+}
+
+/// Origins allowed to connect to the server.
+#[derive(Debug, Clone, Default)]
+pub struct AllowedOrigins {
+    origins: Option<Vec<String>>,
+}
+
+impl AllowedOrigins {
+    /// Allow any origin.
+    pub fn any() -> Self {
+        Self { origins: None }
+    }
+
+    /// Allow a list of origins, eg: `https://example.com`.
+    pub fn list(origins: Vec<String>) -> Result<Self> {
+        let origins = origins
+            .iter()
+            .map(|o| parse(o))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            origins: Some(origins),
+        })
+    }
+
+    /// Determine if an origin is allowed.
+    pub fn is_allowed(&self, origin: Option<&str>) -> bool {
+        match (&self.origins, origin) {
+            (None, _) => true,
+            (Some(_), None) => true,
+            (Some(origins), Some(origin)) => {
+                let origin = normalize(origin);
+                origins.iter().any(|o| o == &origin)
+            }
+        }
+    }
+
+    /// CORS configuration for the HTTP routes.
+    pub(crate) fn cors(&self) -> Builder {
+        let cors = warp::cors()
+            .allow_methods(vec![
+                Method::GET,
+                Method::POST,
+                Method::DELETE,
+                Method::OPTIONS,
+            ])
+            .allow_headers(vec!["authorization", "content-type"]);
+        if let Some(origins) = &self.origins {
+            cors.allow_origins(origins.iter().map(|o| o.as_str()))
+        } else {
+            cors.allow_any_origin()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn list(origins: &[&str]) -> Result<AllowedOrigins> {
+        AllowedOrigins::list(origins.iter().map(|o| o.to_string()).collect())
+    }
+
+    #[test]
+    fn list_accepts_origins() -> Result<()> {
+        let origins = list(&[
+            "https://example.com",
+            "HTTP://LOCALHOST:8080/",
+            " https://[::1]:3000 ",
+        ])?;
+        assert!(origins.is_allowed(Some("https://example.com")));
+        assert!(origins.is_allowed(Some("http://localhost:8080")));
+        assert!(origins.is_allowed(Some("https://[::1]:3000")));
+        assert!(!origins.is_allowed(Some("https://example.org")));
+        assert!(!origins.is_allowed(Some("http://example.com")));
+        assert!(origins.is_allowed(None));
+        // Building the CORS filter panics for invalid origins
+        let _ = origins.cors().build();
+        Ok(())
+    }
+
+    #[test]
+    fn list_rejects_invalid_origins() {
+        for origin in [
+            "",
+            "example.com",
+            "localhost:8080",
+            "https://",
+            "://example.com",
+            "https://example.com/path",
+            "https://example.com?query",
+            "https://exa mple.com",
+            "https://example.com:port",
+            "https://user@example.com",
+        ] {
+            assert!(
+                matches!(
+                    list(&[origin]),
+                    Err(ServerError::InvalidOrigin(o)) if o == origin
+                ),
+                "{} should be invalid",
+                origin
+            );
+        }
+    }
+
+    #[test]
+    fn any_allows_every_origin() {
+        let origins = AllowedOrigins::any();
+        assert!(origins.is_allowed(Some("https://example.com")));
+        assert!(origins.is_allowed(None));
+    }
+}
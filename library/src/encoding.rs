@@ -0,0 +1,309 @@
+//! Encodings for JSON-RPC messages sent over a websocket.
+//!
+//! By default requests and responses are JSON in text frames. A client
+//! may negotiate a compact binary encoding when it connects by adding
+//! an `encoding` query parameter to the websocket URL, for example:
+//! `/mpc?encoding=msgpack`.
+//!
+//! When a binary encoding is negotiated the server sends responses
+//! as binary frames using the encoding and decodes binary frames from
+//! the client using the same encoding; text frames are still accepted
+//! as JSON so clients may upgrade gradually.
+//!
+//! MessagePack payloads must use maps for structs (named fields)
+//! rather than the array representation.
+//!
+//! Large messages may also be [compressed](crate::compression).
+use json_rpc2::{Request, Response};
+use serde::{Deserialize, Serialize};
+use warp::ws::Message;
+
+use crate::compression::{
+    Compression, CompressionMetrics, COMPRESSION_THRESHOLD,
+};
+use crate::{Result, ServerError};
+
+/// Encoding for messages on a connection.
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Encoding {
+    /// JSON in text frames.
+    #[default]
+    Json,
+    /// MessagePack in binary frames.
+    #[serde(rename = "msgpack")]
+    MessagePack,
+    /// CBOR in binary frames.
+    Cbor,
+}
+
+impl Encoding {
+    /// All the supported encodings.
+    pub const ALL: [Encoding; 3] =
+        [Encoding::Json, Encoding::MessagePack, Encoding::Cbor];
+
+    /// Name of the encoding.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encoding::Json => "json",
+            Encoding::MessagePack => "msgpack",
+            Encoding::Cbor => "cbor",
+        }
+    }
+
+    /// Encode a response into a websocket message.
+    pub fn encode(&self, response: &Response) -> Result<Message> {
+        Ok(match self {
+            Encoding::Json => Message::text(serde_json::to_string(response)?),
+            Encoding::MessagePack => Message::binary(
+                rmp_serde::to_vec_named(response)
+                    .map_err(|e| ServerError::Encoding(e.to_string()))?,
+            ),
+            Encoding::Cbor => {
+                let mut buffer = Vec::new();
+                ciborium::ser::into_writer(response, &mut buffer)
+                    .map_err(|e| ServerError::Encoding(e.to_string()))?;
+                Message::binary(buffer)
+            }
+        })
+    }
+
+    /// Decode a request from a websocket message.
+    ///
+    /// Returns `None` for control frames.
+    pub fn decode(&self, message: &Message) -> Result<Option<Request>> {
+        if let Ok(text) = message.to_str() {
+            return Ok(Some(json_rpc2::from_str(text)?));
+        }
+
+        if !message.is_binary() {
+            return Ok(None);
+        }
+
+        let bytes = message.as_bytes();
+        Ok(Some(match self {
+            Encoding::Json => {
+                return Err(ServerError::Encoding(
+                    "binary frame without a binary encoding".to_owned(),
+                ))
+            }
+            Encoding::MessagePack => rmp_serde::from_slice(bytes)
+                .map_err(|e| ServerError::Encoding(e.to_string()))?,
+            Encoding::Cbor => ciborium::de::from_reader(bytes)
+                .map_err(|e| ServerError::Encoding(e.to_string()))?,
+        }))
+    }
+}
+
+/// Encoding and compression negotiated by a connection.
+///
+/// Parsed from the query string of the websocket route.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub struct Codec {
+    /// Encoding for messages.
+    #[serde(default)]
+    pub encoding: Encoding,
+    /// Compression for large messages.
+    #[serde(default)]
+    pub compression: Compression,
+}
+
+impl Codec {
+    /// Encode a response into a websocket message.
+    pub fn encode(
+        &self,
+        response: &Response,
+        metrics: &CompressionMetrics,
+    ) -> Result<Message> {
+        let message = self.encoding.encode(response)?;
+        if self.compression == Compression::None
+            || message.as_bytes().len() < COMPRESSION_THRESHOLD
+        {
+            return Ok(message);
+        }
+        Ok(Message::binary(
+            self.compression.compress(message.into_bytes(), metrics),
+        ))
+    }
+
+    /// Decode a request from a websocket message.
+    ///
+    /// Returns `None` for control frames.
+    pub fn decode(&self, message: &Message) -> Result<Option<Request>> {
+        if message.is_binary()
+            && self.compression != Compression::None
+            && Compression::is_framed(message.as_bytes())
+        {
+            let bytes = self.compression.decompress(message.as_bytes())?;
+            let message = match self.encoding {
+                Encoding::Json => Message::text(
+                    String::from_utf8(bytes)
+                        .map_err(|e| ServerError::Encoding(e.to_string()))?,
+                ),
+                _ => Message::binary(bytes),
+            };
+            return self.encoding.decode(&message);
+        }
+        self.encoding.decode(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compression::Compression;
+    use serde_json::{json, Value};
+
+    fn request() -> Request {
+        Request::new(
+            Some(json!(1)),
+            String::from("Session.message"),
+            Some(json!(["group", "session", "keygen", { "round": 1 }])),
+        )
+    }
+
+    fn response(len: usize) -> Response {
+        json!({ "body": "x".repeat(len), "round": 2 }).into()
+    }
+
+    /// Serialize a request as a client would for an encoding.
+    fn client_encode(encoding: Encoding, request: &Request) -> Message {
+        match encoding {
+            Encoding::Json => {
+                Message::text(serde_json::to_string(request).unwrap())
+            }
+            Encoding::MessagePack => {
+                Message::binary(rmp_serde::to_vec_named(request).unwrap())
+            }
+            Encoding::Cbor => {
+                let mut buffer = Vec::new();
+                ciborium::ser::into_writer(request, &mut buffer).unwrap();
+                Message::binary(buffer)
+            }
+        }
+    }
+
+    /// Deserialize a response as a client would for an encoding.
+    fn client_decode(encoding: Encoding, bytes: &[u8]) -> Response {
+        match encoding {
+            Encoding::Json => serde_json::from_slice(bytes).unwrap(),
+            Encoding::MessagePack => rmp_serde::from_slice(bytes).unwrap(),
+            Encoding::Cbor => ciborium::de::from_reader(bytes).unwrap(),
+        }
+    }
+
+    fn to_value(request: &Request) -> Value {
+        serde_json::to_value(request).unwrap()
+    }
+
+    #[tokio::test]
+    async fn codec_from_query() {
+        for encoding in Encoding::ALL {
+            let codec = warp::test::request()
+                .path(&format!("/?encoding={}", encoding.name()))
+                .filter(&warp::query::<Codec>())
+                .await
+                .unwrap();
+            assert_eq!(encoding, codec.encoding);
+        }
+        let codec = warp::test::request()
+            .path("/")
+            .filter(&warp::query::<Codec>())
+            .await
+            .unwrap();
+        assert_eq!(Codec::default(), codec);
+    }
+
+    #[test]
+    fn encode_decode_round_trip() -> Result<()> {
+        for encoding in Encoding::ALL {
+            let message = encoding.encode(&response(16))?;
+            assert_eq!(encoding == Encoding::Json, message.is_text());
+            assert_eq!(
+                response(16),
+                client_decode(encoding, message.as_bytes())
+            );
+
+            let message = client_encode(encoding, &request());
+            let decoded = encoding.decode(&message)?.unwrap();
+            assert_eq!(to_value(&request()), to_value(&decoded));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn decode_accepts_text_frames() -> Result<()> {
+        for encoding in Encoding::ALL {
+            let message = client_encode(Encoding::Json, &request());
+            let decoded = encoding.decode(&message)?.unwrap();
+            assert_eq!(to_value(&request()), to_value(&decoded));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn decode_rejects_invalid_frames() -> Result<()> {
+        let message = client_encode(Encoding::Cbor, &request());
+        assert!(matches!(
+            Encoding::Json.decode(&message),
+            Err(ServerError::Encoding(_))
+        ));
+        assert!(matches!(
+            Encoding::MessagePack.decode(&Message::binary(vec![0xc1])),
+            Err(ServerError::Encoding(_))
+        ));
+        assert!(Encoding::Cbor.decode(&Message::ping(vec![]))?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn codec_round_trip_with_compression() -> Result<()> {
+        let metrics = CompressionMetrics::default();
+        for encoding in Encoding::ALL {
+            let codec = Codec {
+                encoding,
+                compression: Compression::Deflate,
+            };
+            let message =
+                codec.encode(&response(COMPRESSION_THRESHOLD * 2), &metrics)?;
+            assert!(message.is_binary());
+            let bytes = Compression::Deflate.decompress(message.as_bytes())?;
+            assert_eq!(
+                response(COMPRESSION_THRESHOLD * 2),
+                client_decode(encoding, &bytes)
+            );
+
+            let bytes = client_encode(encoding, &request()).into_bytes();
+            let framed = Compression::Deflate.compress(bytes, &metrics);
+            let decoded = codec.decode(&Message::binary(framed))?.unwrap();
+            assert_eq!(to_value(&request()), to_value(&decoded));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn codec_does_not_frame_small_messages() -> Result<()> {
+        let metrics = CompressionMetrics::default();
+        for encoding in Encoding::ALL {
+            let codec = Codec {
+                encoding,
+                compression: Compression::Deflate,
+            };
+            let message = codec.encode(&response(16), &metrics)?;
+            assert_eq!(encoding.encode(&response(16))?, message);
+            assert!(!Compression::is_framed(message.as_bytes()));
+            assert_eq!(
+                response(16),
+                client_decode(encoding, message.as_bytes())
+            );
+
+            let message = client_encode(encoding, &request());
+            let decoded = codec.decode(&message)?.unwrap();
+            assert_eq!(to_value(&request()), to_value(&decoded));
+        }
+        assert_eq!(0, metrics.snapshot().messages);
+        Ok(())
+    }
+}
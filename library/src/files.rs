@@ -0,0 +1,352 @@
+//! Static file serving.
+//!
+//! Static files are optional; when no [StaticFiles] are assigned to
+//! the server options the server only handles websocket connections
+//! and the HTTP endpoints.
+//!
+//! Files may be served from a directory or from a collection of files
+//! embedded in the binary, optionally below a mount prefix. Single page
+//! applications can enable a fallback so that requests that do not
+//! match a file are served the `index.html` file.
+//!
+//! HTML files are always sent with `Cache-Control: no-cache` so that
+//! clients pick up new builds; the configured cache control is applied
+//! to all other files.
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use warp::filters::BoxedFilter;
+use warp::http::header::{HeaderValue, CACHE_CONTROL, CONTENT_TYPE};
+use warp::hyper::Body;
+use warp::path::{FullPath, Tail};
+use warp::reply::Reply;
+use warp::Filter;
+
+use crate::{Result, ServerError};
+
+/// Name of the index file.
+const INDEX: &str = "index.html";
+
+/// Cache control for HTML files.
+const NO_CACHE: &str = "no-cache";
+
+/// Source for static files.
+#[derive(Debug, Clone)]
+pub enum StaticSource {
+    /// Serve files from a directory.
+    Directory(PathBuf),
+    /// Serve files embedded in the binary keyed by relative path.
+    Embedded(Arc<HashMap<String, &'static [u8]>>),
+}
+
+/// Options for serving static files.
+#[derive(Debug, Clone)]
+pub struct StaticFiles {
+    /// Source for the files.
+    pub source: StaticSource,
+    /// Mount prefix for the files, eg: `app` or `app/v1`.
+    pub prefix: Option<String>,
+    /// Serve the index file when a request does not match a file.
+    pub spa_fallback: bool,
+    /// Value for the `Cache-Control` header.
+    pub cache_control: Option<String>,
+}
+
+impl StaticFiles {
+    /// Create options for serving static files from a source.
+    pub fn new(source: StaticSource) -> Self {
+        Self {
+            source,
+            prefix: None,
+            spa_fallback: false,
+            cache_control: None,
+        }
+    }
+}
+
+impl From<PathBuf> for StaticFiles {
+    fn from(path: PathBuf) -> Self {
+        StaticFiles::new(StaticSource::Directory(path))
+    }
+}
+
+/// Routes for static files.
+pub(crate) fn routes(
+    options: Option<StaticFiles>,
+) -> Result<BoxedFilter<(warp::reply::Response,)>> {
+    let options = if let Some(options) = options {
+        options
+    } else {
+        tracing::info!("static files disabled");
+        return Ok(warp::any()
+            .and_then(|| async {
+                Err::<warp::reply::Response, _>(warp::reject::not_found())
+            })
+            .boxed());
+    };
+
+    let mut mount = warp::any().boxed();
+    if let Some(prefix) = &options.prefix {
+        tracing::info!(%prefix, "static files mount");
+        for segment in prefix.split('/').filter(|s| !s.is_empty()) {
+            mount = mount.and(warp::path(segment.to_owned())).boxed();
+        }
+    }
+
+    let files = match options.source {
+        StaticSource::Directory(path) => {
+            if !path.is_dir() {
+                return Err(ServerError::NotDirectory(path));
+            }
+            let path = path.canonicalize()?;
+            let static_path = path.to_string_lossy().into_owned();
+            tracing::info!(%static_path);
+
+            let files = warp::fs::dir(path.clone()).map(Reply::into_response);
+            if options.spa_fallback {
+                files
+                    .or(warp::get()
+                        .and(warp::fs::file(path.join(INDEX)))
+                        .map(Reply::into_response))
+                    .unify()
+                    .boxed()
+            } else {
+                files.boxed()
+            }
+        }
+        StaticSource::Embedded(embedded) => {
+            tracing::info!(files = embedded.len(), "static files embedded");
+            let spa_fallback = options.spa_fallback;
+            warp::get()
+                .and(warp::path::tail())
+                .and_then(move |tail: Tail| {
+                    let embedded = Arc::clone(&embedded);
+                    async move {
+                        embedded_file(&embedded, tail.as_str(), spa_fallback)
+                            .ok_or_else(warp::reject::not_found)
+                    }
+                })
+                .boxed()
+        }
+    };
+
+    let cache_control = options.cache_control;
+    Ok(mount
+        .and(warp::path::full())
+        .and(files)
+        .map(move |path: FullPath, mut response: warp::reply::Response| {
+            let value = if is_html(path.as_str(), &response) {
+                Some(NO_CACHE)
+            } else {
+                cache_control.as_deref()
+            };
+            if let Some(value) = value.and_then(|v| v.parse().ok()) {
+                response.headers_mut().insert(CACHE_CONTROL, value);
+            }
+            response
+        })
+        .boxed())
+}
+
+/// Find an embedded file for a request path.
+fn embedded_file(
+    embedded: &HashMap<String, &'static [u8]>,
+    path: &str,
+    spa_fallback: bool,
+) -> Option<warp::reply::Response> {
+    let path = if path.is_empty() || path.ends_with('/') {
+        format!("{}{}", path, INDEX)
+    } else {
+        path.to_owned()
+    };
+
+    let (path, contents) = if let Some(contents) = embedded.get(&path) {
+        (path, *contents)
+    } else if spa_fallback {
+        (INDEX.to_owned(), *embedded.get(INDEX)?)
+    } else {
+        return None;
+    };
+
+    let mime = mime_guess::from_path(&path).first_or_octet_stream();
+    let mut response = warp::reply::Response::new(Body::from(contents));
+    if let Ok(value) = HeaderValue::from_str(mime.as_ref()) {
+        response.headers_mut().insert(CONTENT_TYPE, value);
+    }
+    Some(response)
+}
+
+/// Determine if a response is for an HTML document.
+fn is_html(path: &str, response: &warp::reply::Response) -> bool {
+    path.ends_with('/')
+        || path.ends_with(".html")
+        || response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.starts_with("text/html"))
+            .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use warp::http::StatusCode;
+
+    fn embedded() -> StaticSource {
+        let mut files: HashMap<String, &'static [u8]> = HashMap::new();
+        files.insert("index.html".to_owned(), b"<html>index</html>");
+        files.insert("app.js".to_owned(), b"console.log('app')");
+        files.insert("docs/index.html".to_owned(), b"<html>docs</html>");
+        StaticSource::Embedded(Arc::new(files))
+    }
+
+    fn filter(options: StaticFiles) -> BoxedFilter<(warp::reply::Response,)> {
+        routes(Some(options)).unwrap()
+    }
+
+    async fn get(
+        filter: &BoxedFilter<(warp::reply::Response,)>,
+        path: &str,
+    ) -> warp::http::Response<warp::hyper::body::Bytes> {
+        warp::test::request().path(path).reply(filter).await
+    }
+
+    fn header(
+        response: &warp::http::Response<warp::hyper::body::Bytes>,
+        name: warp::http::header::HeaderName,
+    ) -> Option<&str> {
+        response.headers().get(name).and_then(|v| v.to_str().ok())
+    }
+
+    /// Temporary directory with an index file and a script.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!(
+                "mpc-websocket-files-{}-{}",
+                name,
+                std::process::id()
+            ));
+            std::fs::create_dir_all(&path).unwrap();
+            std::fs::write(path.join(INDEX), "<html>index</html>").unwrap();
+            std::fs::write(path.join("app.js"), "console.log('app')").unwrap();
+            Self(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[tokio::test]
+    async fn disabled_rejects_requests() {
+        let filter = routes(None).unwrap();
+        let response = warp::test::request().path("/").reply(&filter).await;
+        assert_eq!(StatusCode::NOT_FOUND, response.status());
+    }
+
+    #[test]
+    fn rejects_missing_directory() {
+        let path = std::env::temp_dir().join("mpc-websocket-files-missing");
+        let result = routes(Some(StaticFiles::from(path)));
+        assert!(matches!(result, Err(ServerError::NotDirectory(_))));
+    }
+
+    #[tokio::test]
+    async fn embedded_files() {
+        let mut options = StaticFiles::new(embedded());
+        options.cache_control = Some("max-age=60".to_owned());
+        let filter = filter(options);
+
+        let response = get(&filter, "/app.js").await;
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!(&b"console.log('app')"[..], response.body());
+        assert_eq!(
+            Some("application/javascript"),
+            header(&response, CONTENT_TYPE)
+        );
+        assert_eq!(Some("max-age=60"), header(&response, CACHE_CONTROL));
+
+        let response = get(&filter, "/").await;
+        assert_eq!(&b"<html>index</html>"[..], response.body());
+        assert_eq!(Some(NO_CACHE), header(&response, CACHE_CONTROL));
+
+        let response = get(&filter, "/docs/").await;
+        assert_eq!(&b"<html>docs</html>"[..], response.body());
+        assert_eq!(Some(NO_CACHE), header(&response, CACHE_CONTROL));
+
+        let response = get(&filter, "/missing.js").await;
+        assert_eq!(StatusCode::NOT_FOUND, response.status());
+    }
+
+    #[tokio::test]
+    async fn embedded_spa_fallback() {
+        let mut options = StaticFiles::new(embedded());
+        options.spa_fallback = true;
+        options.cache_control = Some("max-age=60".to_owned());
+        let filter = filter(options);
+
+        let response = get(&filter, "/accounts/1").await;
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!(&b"<html>index</html>"[..], response.body());
+        assert_eq!(Some("text/html"), header(&response, CONTENT_TYPE));
+        assert_eq!(Some(NO_CACHE), header(&response, CACHE_CONTROL));
+
+        let response = get(&filter, "/app.js").await;
+        assert_eq!(&b"console.log('app')"[..], response.body());
+    }
+
+    #[tokio::test]
+    async fn embedded_mount_prefix() {
+        let mut options = StaticFiles::new(embedded());
+        options.prefix = Some("/app/v1/".to_owned());
+        let filter = filter(options);
+
+        let response = get(&filter, "/app/v1/app.js").await;
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!(&b"console.log('app')"[..], response.body());
+
+        let response = get(&filter, "/app.js").await;
+        assert_eq!(StatusCode::NOT_FOUND, response.status());
+    }
+
+    #[tokio::test]
+    async fn directory_files() {
+        let dir = TempDir::new("directory");
+        let mut options = StaticFiles::from(dir.0.clone());
+        options.cache_control = Some("max-age=60".to_owned());
+        let filter = filter(options);
+
+        let response = get(&filter, "/app.js").await;
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!(&b"console.log('app')"[..], response.body());
+        assert_eq!(Some("max-age=60"), header(&response, CACHE_CONTROL));
+
+        let response = get(&filter, "/").await;
+        assert_eq!(&b"<html>index</html>"[..], response.body());
+        assert_eq!(Some(NO_CACHE), header(&response, CACHE_CONTROL));
+
+        let response = get(&filter, "/accounts/1").await;
+        assert_eq!(StatusCode::NOT_FOUND, response.status());
+    }
+
+    #[tokio::test]
+    async fn directory_spa_fallback() {
+        let dir = TempDir::new("fallback");
+        let mut options = StaticFiles::from(dir.0.clone());
+        options.spa_fallback = true;
+        let filter = filter(options);
+
+        let response = get(&filter, "/accounts/1").await;
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!(&b"<html>index</html>"[..], response.body());
+        assert_eq!(Some(NO_CACHE), header(&response, CACHE_CONTROL));
+
+        let response = get(&filter, "/app.js").await;
+        assert_eq!(&b"console.log('app')"[..], response.body());
+    }
+}
@@ -0,0 +1,235 @@
+//! Health, readiness and build information endpoints.
+//!
+//! * `GET /healthz` responds when the server process is running.
+//! * `GET /readyz` responds with `503 Service Unavailable` while the
+//!   server is shutting down or when the backplane is unavailable.
+//! * `GET /buildinfo` responds with the crate version and the
+//!   features enabled on the server.
+use serde::Serialize;
+use std::convert::Infallible;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+use warp::http::StatusCode;
+use warp::reply::Reply;
+use warp::{filters::BoxedFilter, Filter};
+
+use crate::services::PROTOCOL_VERSION;
+use crate::{Features, State};
+
+/// Maximum time to wait for the backplane to respond.
+const PING_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Readiness of the server to accept connections.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Readiness {
+    /// Whether the server is ready.
+    pub ready: bool,
+    /// Reason the server is not ready.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// Information about the server build.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildInfo {
+    /// Name of the crate.
+    pub name: &'static str,
+    /// Version of the crate.
+    pub version: &'static str,
+    /// Version of the JSON-RPC protocol.
+    pub protocol_version: u16,
+    /// Features enabled on the server.
+    pub features: Features,
+    /// Whether a backplane is configured.
+    pub backplane: bool,
+}
+
+/// Routes for the health, readiness and build information endpoints.
+pub(crate) fn routes(
+    state: impl Filter<Extract = (Arc<RwLock<State>>,), Error = Infallible>
+        + Clone
+        + Send
+        + Sync
+        + 'static,
+) -> BoxedFilter<(warp::reply::Response,)> {
+    let healthz = warp::path("healthz")
+        .and(warp::path::end())
+        .and(warp::get())
+        .map(|| warp::reply::json(&"ok").into_response());
+
+    let readyz = warp::path("readyz")
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(state.clone())
+        .then(|state: Arc<RwLock<State>>| async move {
+            let readiness = readiness(&state).await;
+            let status = if readiness.ready {
+                StatusCode::OK
+            } else {
+                StatusCode::SERVICE_UNAVAILABLE
+            };
+            warp::reply::with_status(warp::reply::json(&readiness), status)
+                .into_response()
+        });
+
+    let buildinfo = warp::path("buildinfo")
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(state)
+        .then(|state: Arc<RwLock<State>>| async move {
+            let reader = state.read().await;
+            let info = BuildInfo {
+                name: env!("CARGO_PKG_NAME"),
+                version: env!("CARGO_PKG_VERSION"),
+                protocol_version: PROTOCOL_VERSION,
+                features: reader.features.clone(),
+                backplane: reader.cluster.is_some(),
+            };
+            warp::reply::json(&info).into_response()
+        });
+
+    healthz.or(readyz).unify().or(buildinfo).unify().boxed()
+}
+
+/// Determine whether the server is ready.
+async fn readiness(state: &Arc<RwLock<State>>) -> Readiness {
+    let cluster = {
+        let reader = state.read().await;
+        if reader.shutting_down {
+            return Readiness {
+                ready: false,
+                reason: Some("shutting down".to_owned()),
+            };
+        }
+        reader.cluster.clone()
+    };
+
+    if let Some(cluster) = cluster {
+        let reason = match tokio::time::timeout(
+            PING_TIMEOUT,
+            cluster.backplane().ping(),
+        )
+        .await
+        {
+            Ok(Ok(_)) => None,
+            Ok(Err(e)) => Some(format!("backplane unavailable: {}", e)),
+            Err(_) => Some("backplane timed out".to_owned()),
+        };
+        if reason.is_some() {
+            tracing::warn!(?reason, "not ready");
+            return Readiness {
+                ready: false,
+                reason,
+            };
+        }
+    }
+
+    Readiness {
+        ready: true,
+        reason: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backplane::{
+        Backplane, Cluster, InProcessBackplane, RedisBackplane,
+    };
+    use serde_json::{json, Value};
+
+    fn state(cluster: Option<Cluster>) -> Arc<RwLock<State>> {
+        Arc::new(RwLock::new(State::new(Default::default(), cluster)))
+    }
+
+    async fn get(
+        state: &Arc<RwLock<State>>,
+        path: &str,
+    ) -> (StatusCode, Value) {
+        let state = Arc::clone(state);
+        let filter = routes(warp::any().map(move || Arc::clone(&state)));
+        let response = warp::test::request().path(path).reply(&filter).await;
+        let body = serde_json::from_slice(response.body()).unwrap_or_default();
+        (response.status(), body)
+    }
+
+    #[tokio::test]
+    async fn healthz() {
+        let state = self::state(None);
+        assert_eq!(
+            (StatusCode::OK, json!("ok")),
+            get(&state, "/healthz").await
+        );
+    }
+
+    #[tokio::test]
+    async fn readyz_while_shutting_down() {
+        let state = self::state(None);
+        assert_eq!(
+            (StatusCode::OK, json!({ "ready": true })),
+            get(&state, "/readyz").await
+        );
+
+        state.write().await.shutting_down = true;
+        assert_eq!(
+            (
+                StatusCode::SERVICE_UNAVAILABLE,
+                json!({ "ready": false, "reason": "shutting down" })
+            ),
+            get(&state, "/readyz").await
+        );
+    }
+
+    #[tokio::test]
+    async fn readyz_checks_backplane() {
+        let backplane: Arc<dyn Backplane> =
+            Arc::new(InProcessBackplane::default());
+        let state = self::state(Some(Cluster::new(backplane)));
+        assert_eq!(
+            (StatusCode::OK, json!({ "ready": true })),
+            get(&state, "/readyz").await
+        );
+
+        // Nothing listens on the discard port
+        let backplane: Arc<dyn Backplane> =
+            Arc::new(RedisBackplane::new("127.0.0.1:9", "test"));
+        let state = self::state(Some(Cluster::new(backplane)));
+        let (status, readiness) = get(&state, "/readyz").await;
+        assert_eq!(StatusCode::SERVICE_UNAVAILABLE, status);
+        assert_eq!(json!(false), readiness["ready"]);
+        assert!(readiness["reason"]
+            .as_str()
+            .unwrap()
+            .starts_with("backplane"));
+    }
+
+    #[tokio::test]
+    async fn buildinfo() {
+        let state = self::state(None);
+        let (status, info) = get(&state, "/buildinfo").await;
+        assert_eq!(StatusCode::OK, status);
+        assert_eq!(json!(env!("CARGO_PKG_NAME")), info["name"]);
+        assert_eq!(json!(env!("CARGO_PKG_VERSION")), info["version"]);
+        assert_eq!(json!(PROTOCOL_VERSION), info["protocolVersion"]);
+        assert_eq!(json!(false), info["backplane"]);
+        assert_eq!(
+            serde_json::to_value(Features::default()).unwrap(),
+            info["features"]
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_other_methods() {
+        let state = self::state(None);
+        let filter = routes(warp::any().map(move || Arc::clone(&state)));
+        let response = warp::test::request()
+            .method("POST")
+            .path("/healthz")
+            .reply(&filter)
+            .await;
+        assert_eq!(StatusCode::METHOD_NOT_ALLOWED, response.status());
+    }
+}
@@ -0,0 +1,473 @@
+//! HTTP transport for clients that cannot use websockets.
+//!
+//! The HTTP transport is only mounted when [HttpOptions] are assigned
+//! to the server options. Requests are JSON-RPC messages sent using
+//! `POST`; responses and notifications are queued for the connection
+//! and delivered as Server-Sent Events or by long-polling.
+//!
+//! | Method | Path                 | Description                              |
+//! |--------|----------------------|------------------------------------------|
+//! | `POST` | `/{path}/connect`    | Open a connection.                       |
+//! | `POST` | `/{path}/rpc`        | Send a JSON-RPC request.                 |
+//! | `GET`  | `/{path}/events`     | Receive messages as Server-Sent Events.  |
+//! | `GET`  | `/{path}/poll`       | Receive queued messages by long-polling. |
+//! | `POST` | `/{path}/disconnect` | Close the connection.                    |
+//!
+//! Opening a connection responds with the connection identifier, the
+//! [client identity](crate::identity) (the `identity` query parameter
+//! may be given to restore an identity) and a token that must be included in all other requests either as a
+//! bearer token in the `Authorization` header or as the `token`
+//! query parameter (`EventSource` cannot set headers).
+//!
+//! Sending a request responds with `202 Accepted` and the JSON-RPC
+//! response is delivered in order with the notifications so clients
+//! should match responses using the request `id`. Each event in the
+//! event stream is a single JSON message whilst polling responds with
+//! a JSON array of the queued messages (which may be empty when
+//! `poll_timeout` elapses).
+//!
+//! When more than `max_queued` messages are waiting the connection
+//! is closed rather than dropping a message; the client receives the
+//! queued messages and should reconnect and use `Session.resume` to
+//! receive the messages it missed.
+//!
+//! Connections that are not receiving messages are subject to the
+//! [Heartbeat](crate::Heartbeat); other members of it's groups are
+//! notified when the client is offline and the connection is closed
+//! when the timeout is reached.
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+use std::convert::Infallible;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{Notify, RwLock};
+use tokio::time::Instant;
+use uuid::Uuid;
+use warp::http::StatusCode;
+use warp::reply::Reply;
+use warp::sse::Event;
+use warp::{filters::BoxedFilter, Filter};
+
+use json_rpc2::{Request, Response};
+
+use crate::identity::{ClientId, IdentityQuery};
+use crate::server::{
+    bind_client, client_disconnected, next_connection_id, notify_party_status,
+    rpc_request,
+};
+use crate::services::{PARTY_OFFLINE_EVENT, PARTY_ONLINE_EVENT};
+use crate::transport::Connection;
+use crate::{Heartbeat, Result, ServerError, State};
+
+/// Options for the HTTP transport.
+#[derive(Debug, Clone, Copy)]
+pub struct HttpOptions {
+    /// Maximum time a poll request waits for messages.
+    pub poll_timeout: Duration,
+    /// Maximum number of messages queued for a connection before
+    /// the connection is closed.
+    pub max_queued: usize,
+}
+
+impl Default for HttpOptions {
+    fn default() -> Self {
+        Self {
+            poll_timeout: Duration::from_secs(25),
+            max_queued: 1024,
+        }
+    }
+}
+
+/// Response when a connection is opened.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HttpConnect {
+    /// Connection identifier.
+    pub connection_id: usize,
+    /// Identity of the client.
+    pub client_id: ClientId,
+    /// Token that authorizes requests for the connection.
+    pub token: Uuid,
+}
+
+/// Connection to a client over HTTP.
+///
+/// Messages are serialized once when they are queued.
+#[derive(Debug)]
+pub(crate) struct HttpConnection {
+    queue: Mutex<VecDeque<Value>>,
+    ready: Notify,
+    closed: AtomicBool,
+    streams: AtomicUsize,
+    last_seen: Mutex<Instant>,
+    max_queued: usize,
+}
+
+impl HttpConnection {
+    fn new(max_queued: usize) -> Self {
+        Self {
+            queue: Mutex::new(VecDeque::new()),
+            ready: Notify::new(),
+            closed: AtomicBool::new(false),
+            streams: AtomicUsize::new(0),
+            last_seen: Mutex::new(Instant::now()),
+            max_queued,
+        }
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+    }
+
+    /// Record activity from the client.
+    fn touch(&self) {
+        *self.last_seen.lock().unwrap() = Instant::now();
+    }
+
+    /// Time since the client was last seen.
+    ///
+    /// A client with an open event stream is always seen.
+    fn idle(&self) -> Duration {
+        if self.streams.load(Ordering::SeqCst) > 0 {
+            Duration::ZERO
+        } else {
+            self.last_seen.lock().unwrap().elapsed()
+        }
+    }
+
+    /// Take all the queued messages.
+    fn drain(&self) -> Vec<Value> {
+        self.queue.lock().unwrap().drain(..).collect()
+    }
+
+    /// Wait for the next message.
+    ///
+    /// Returns `None` once the connection is closed and
+    /// all the queued messages have been taken.
+    async fn next(&self) -> Option<Value> {
+        loop {
+            if let Some(response) = self.queue.lock().unwrap().pop_front() {
+                return Some(response);
+            }
+            if self.is_closed() {
+                return None;
+            }
+            self.ready.notified().await;
+        }
+    }
+
+    /// Wait for queued messages or the timeout.
+    async fn poll(&self, timeout: Duration) -> Vec<Value> {
+        let wait = async {
+            loop {
+                let messages = self.drain();
+                if !messages.is_empty() || self.is_closed() {
+                    return messages;
+                }
+                self.ready.notified().await;
+            }
+        };
+        tokio::time::timeout(timeout, wait)
+            .await
+            .unwrap_or_default()
+    }
+}
+
+impl Connection for HttpConnection {
+    fn transport(&self) -> &'static str {
+        "http"
+    }
+
+    /// Queue a message for the client.
+    ///
+    /// Protocol messages must not be lost so a full queue closes
+    /// the connection and the client resumes the session after
+    /// reconnecting.
+    fn send(&self, response: &Response) -> Result<()> {
+        if self.is_closed() {
+            return Err(ServerError::ConnectionClosed);
+        }
+        let message = serde_json::to_value(response)?;
+        {
+            let mut queue = self.queue.lock().unwrap();
+            if queue.len() >= self.max_queued {
+                drop(queue);
+                tracing::warn!("http queue full, closing connection");
+                self.close();
+                return Err(ServerError::ConnectionClosed);
+            }
+            queue.push_back(message);
+        }
+        self.ready.notify_one();
+        Ok(())
+    }
+
+    fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        self.ready.notify_waiters();
+        self.ready.notify_one();
+    }
+}
+
+/// Decrements the event stream count for a connection when
+/// the event stream is dropped.
+struct StreamGuard(Arc<HttpConnection>);
+
+impl StreamGuard {
+    fn new(conn: Arc<HttpConnection>) -> Self {
+        conn.streams.fetch_add(1, Ordering::SeqCst);
+        Self(conn)
+    }
+}
+
+impl Drop for StreamGuard {
+    fn drop(&mut self) {
+        self.0.touch();
+        self.0.streams.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Connection identifier, client identity and connection for a token.
+type Binding = (usize, ClientId, Arc<HttpConnection>);
+
+/// Connections keyed by token.
+type Connections = Arc<RwLock<HashMap<Uuid, Binding>>>;
+
+/// Routes for the HTTP transport.
+pub(crate) fn routes(
+    path: &'static str,
+    options: Option<HttpOptions>,
+    heartbeat: Heartbeat,
+    state: impl Filter<Extract = (Arc<RwLock<State>>,), Error = Infallible>
+        + Clone
+        + Send
+        + Sync
+        + 'static,
+) -> BoxedFilter<(warp::reply::Response,)> {
+    let options = if let Some(options) = options {
+        options
+    } else {
+        return warp::any()
+            .and_then(|| async {
+                Err::<warp::reply::Response, _>(warp::reject::not_found())
+            })
+            .boxed();
+    };
+
+    tracing::info!(?options, "http transport");
+
+    let connections: Connections = Arc::new(RwLock::new(HashMap::new()));
+    let registry = {
+        let connections = Arc::clone(&connections);
+        warp::any().map(move || Arc::clone(&connections))
+    };
+
+    let connect =
+        warp::path!("connect")
+            .and(warp::post())
+            .and(warp::query::<IdentityQuery>())
+            .and(registry.clone())
+            .and(state.clone())
+            .then(
+                move |identity: IdentityQuery,
+                      connections: Connections,
+                      state| async move {
+                    let client_id = match identity.client_id() {
+                        Ok(client_id) => client_id,
+                        Err(e) => {
+                            return warp::reply::with_status(
+                                e.to_string(),
+                                StatusCode::BAD_REQUEST,
+                            )
+                            .into_response();
+                        }
+                    };
+                    connect(options, heartbeat, client_id, connections, state)
+                        .await
+                },
+            );
+
+    let authorized = registry
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::query::<HashMap<String, String>>())
+        .then(
+            |connections: Connections,
+             authorization: Option<String>,
+             query: HashMap<String, String>| async move {
+                let token = authorization
+                    .as_deref()
+                    .and_then(|s| s.strip_prefix("Bearer "))
+                    .or_else(|| query.get("token").map(|s| s.as_str()))
+                    .and_then(|s| s.parse::<Uuid>().ok());
+                let reader = connections.read().await;
+                token.and_then(|token| reader.get(&token).cloned())
+            },
+        )
+        .and_then(|conn: Option<Binding>| async move {
+            conn.ok_or_else(warp::reject::not_found)
+        });
+
+    let rpc = warp::path!("rpc")
+        .and(warp::post())
+        .and(authorized.clone())
+        .and(warp::body::json::<Request>())
+        .and(state)
+        .then(
+            |(conn_id, client_id, conn): Binding,
+             request: Request,
+             state: Arc<RwLock<State>>| async move {
+                if conn.is_closed() {
+                    return StatusCode::GONE.into_response();
+                }
+                conn.touch();
+                rpc_request(conn_id, client_id, request, &state).await;
+                StatusCode::ACCEPTED.into_response()
+            },
+        );
+
+    let events = warp::path!("events")
+        .and(warp::get())
+        .and(authorized.clone())
+        .map(|(_, _, conn): Binding| {
+            let stream = futures_util::stream::unfold(
+                StreamGuard::new(conn),
+                |guard| async move {
+                    let response = guard.0.next().await?;
+                    let event = Event::default()
+                        .json_data(&response)
+                        .unwrap_or_else(|_| Event::default());
+                    Some((Ok::<_, Infallible>(event), guard))
+                },
+            );
+            warp::sse::reply(warp::sse::keep_alive().stream(stream))
+                .into_response()
+        });
+
+    let poll = warp::path!("poll")
+        .and(warp::get())
+        .and(authorized.clone())
+        .then(move |(_, _, conn): Binding| async move {
+            if conn.is_closed() {
+                return StatusCode::GONE.into_response();
+            }
+            conn.touch();
+            let messages = conn.poll(options.poll_timeout).await;
+            conn.touch();
+            warp::reply::json(&messages).into_response()
+        });
+
+    let disconnect = warp::path!("disconnect")
+        .and(warp::post())
+        .and(authorized)
+        .map(|(_, _, conn): Binding| {
+            conn.close();
+            StatusCode::NO_CONTENT.into_response()
+        });
+
+    warp::path(path)
+        .and(
+            connect
+                .or(rpc)
+                .unify()
+                .or(events)
+                .unify()
+                .or(poll)
+                .unify()
+                .or(disconnect)
+                .unify(),
+        )
+        .boxed()
+}
+
+/// Open a connection and spawn a task to monitor it.
+async fn connect(
+    options: HttpOptions,
+    heartbeat: Heartbeat,
+    client_id: ClientId,
+    connections: Connections,
+    state: Arc<RwLock<State>>,
+) -> warp::reply::Response {
+    let conn_id = if let Some(conn_id) = next_connection_id(&state).await {
+        conn_id
+    } else {
+        return StatusCode::SERVICE_UNAVAILABLE.into_response();
+    };
+
+    let token = Uuid::new_v4();
+    let conn = Arc::new(HttpConnection::new(options.max_queued));
+    bind_client(conn_id, client_id, Arc::clone(&conn) as _, &state).await;
+    connections
+        .write()
+        .await
+        .insert(token, (conn_id, client_id, Arc::clone(&conn)));
+
+    tracing::info!(
+        conn_id,
+        %client_id,
+        transport = conn.transport(),
+        "connected"
+    );
+
+    tokio::task::spawn(async move {
+        let mut interval = tokio::time::interval(heartbeat.interval);
+        let mut offline = false;
+        loop {
+            interval.tick().await;
+            if conn.is_closed() {
+                break;
+            }
+
+            let idle = conn.idle();
+            if idle >= heartbeat.timeout {
+                tracing::warn!(conn_id, ?idle, "heartbeat timeout");
+                conn.close();
+                break;
+            }
+
+            if !offline && idle > heartbeat.interval {
+                offline = true;
+                tracing::info!(conn_id, ?idle, "offline");
+                notify_party_status(client_id, PARTY_OFFLINE_EVENT, &state)
+                    .await;
+            } else if offline && idle <= heartbeat.interval {
+                offline = false;
+                tracing::info!(conn_id, "online");
+                notify_party_status(client_id, PARTY_ONLINE_EVENT, &state)
+                    .await;
+            }
+        }
+
+        connections.write().await.remove(&token);
+        client_disconnected(conn_id, client_id, &state).await;
+    });
+
+    warp::reply::json(&HttpConnect {
+        connection_id: conn_id,
+        client_id,
+        token,
+    })
+    .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn full_queue_closes_connection() {
+        let conn = HttpConnection::new(2);
+        conn.send(&json!(1).into()).unwrap();
+        conn.send(&json!(2).into()).unwrap();
+        assert!(matches!(
+            conn.send(&json!(3).into()),
+            Err(ServerError::ConnectionClosed)
+        ));
+        assert!(conn.is_closed());
+
+        // Queued messages are still delivered
+        assert_eq!(2, conn.drain().len());
+    }
+}
@@ -0,0 +1,84 @@
+//! Stable client identities.
+//!
+//! Clients are identified by a [ClientId] that is independent of the
+//! transport connection so group membership, party signups and the
+//! routing of notifications survive a client reconnecting.
+//!
+//! A client may supply a secret identity key when it connects using
+//! the `identity` query parameter (eg: `/mpc?identity=...`); the
+//! [ClientId] is the fingerprint of the key so connecting again with
+//! the same key restores the same identity and closes any previous
+//! connection for the identity. Without a key the server assigns a
+//! random identity for the lifetime of the connection.
+//!
+//! The key must be kept secret and should be at least
+//! [MIN_KEY_LENGTH] bytes of random data; the [ClientId] is safe
+//! to share with other clients.
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use uuid::Uuid;
+
+use crate::{Result, ServerError};
+
+/// Minimum length of an identity key.
+pub const MIN_KEY_LENGTH: usize = 16;
+
+/// Namespace for identity key fingerprints.
+const NAMESPACE: Uuid = Uuid::from_bytes([
+    0x6d, 0x70, 0x63, 0x2d, 0x77, 0x65, 0x62, 0x73, 0x6f, 0x63, 0x6b, 0x65,
+    0x74, 0x2d, 0x69, 0x64,
+]);
+
+/// Durable identity for a client.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Serialize,
+    Deserialize,
+)]
+#[serde(transparent)]
+pub struct ClientId(Uuid);
+
+impl ClientId {
+    /// Random client identity.
+    pub fn random() -> Self {
+        Self(Uuid::new_v4())
+    }
+
+    /// Client identity for a secret identity key.
+    pub fn from_key(key: &[u8]) -> Result<Self> {
+        if key.len() < MIN_KEY_LENGTH {
+            return Err(ServerError::IdentityKeyLength(MIN_KEY_LENGTH));
+        }
+        Ok(Self(Uuid::new_v5(&NAMESPACE, key)))
+    }
+}
+
+impl fmt::Display for ClientId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// Query string for the identity of a new connection.
+#[derive(Debug, Default, Deserialize)]
+pub(crate) struct IdentityQuery {
+    identity: Option<String>,
+}
+
+impl IdentityQuery {
+    /// Client identity for the connection.
+    pub(crate) fn client_id(&self) -> Result<ClientId> {
+        if let Some(key) = &self.identity {
+            ClientId::from_key(key.as_bytes())
+        } else {
+            Ok(ClientId::random())
+        }
+    }
+}
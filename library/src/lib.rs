@@ -33,7 +33,19 @@
 //! The associated session data is typically used by signing sessions
 //! to indicate the message or transaction that will be signed.
 #![deny(missing_docs)]
+pub mod admin;
+pub mod backplane;
+pub mod compression;
+pub mod cors;
+pub mod encoding;
+pub mod files;
+pub mod health;
+pub mod http;
+pub mod identity;
 mod server;
 pub mod services;
+#[cfg(feature = "test-support")]
+pub mod testing;
+pub mod transport;
 
 pub use server::*;
@@ -1,23 +1,40 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::future::Future;
 use std::net::SocketAddr;
 use std::path::PathBuf;
 use std::sync::{
-    atomic::{AtomicUsize, Ordering},
+    atomic::{AtomicBool, AtomicUsize, Ordering},
     Arc,
 };
+use std::time::Duration;
 
 use futures_util::{SinkExt, StreamExt, TryFutureExt};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use thiserror::Error;
 use tokio::sync::{mpsc, Mutex, RwLock};
+use tokio::time::Instant;
 use tokio_stream::wrappers::UnboundedReceiverStream;
 use uuid::Uuid;
 use warp::http::header::{HeaderMap, HeaderValue};
+use warp::http::StatusCode;
+use warp::reply::Reply;
 use warp::ws::{Message, WebSocket};
 use warp::Filter;
 
+use crate::admin::{self, AdminOptions};
+use crate::backplane::{
+    merge_group, Backplane, BackplaneMessage, Cluster, GroupSnapshot,
+};
+use crate::compression::CompressionMetrics;
+use crate::cors::AllowedOrigins;
+use crate::encoding::Codec;
+use crate::files::{self, StaticFiles};
+use crate::health;
+use crate::http::{self, HttpOptions};
+use crate::identity::{ClientId, IdentityQuery};
 use crate::services::*;
+use crate::transport::{Connection, WebSocketConnection};
 use json_rpc2::{Request, Response};
 
 use tracing_subscriber::fmt::format::FmtSpan;
@@ -27,13 +44,26 @@ static CONNECTION_ID: AtomicUsize = AtomicUsize::new(1);
 
 type RpcService = Box<
     dyn json_rpc2::futures::Service<
-        Data = (usize, Arc<RwLock<State>>, Arc<Mutex<Option<Notification>>>),
+        Data = (
+            ClientId,
+            Arc<RwLock<State>>,
+            Arc<Mutex<Option<Notification>>>,
+        ),
     >,
 >;
 
 /// Error thrown by the server.
 #[derive(Debug, Error)]
 pub enum ServerError {
+    /// Error generated when an allowed origin is invalid.
+    #[error("invalid origin {0}, expected scheme://host")]
+    InvalidOrigin(String),
+
+    /// Error generated when the heartbeat interval is zero or
+    /// the timeout is not longer than the interval.
+    #[error("invalid heartbeat interval {0:?} for timeout {1:?}")]
+    InvalidHeartbeat(Duration, Duration),
+
     /// Error generated when a directory is expected.
     #[error("{0} is not a directory")]
     NotDirectory(PathBuf),
@@ -58,9 +88,43 @@ pub enum ServerError {
     #[error(transparent)]
     Io(#[from] std::io::Error),
 
+    /// Error generated encoding or decoding a message.
+    #[error("encoding: {0}")]
+    Encoding(String),
+
+    /// Error generated by the backplane.
+    #[error("backplane: {0}")]
+    Backplane(String),
+
+    /// Error generated when an identity key is too short.
+    #[error("identity key must be at least {0} bytes")]
+    IdentityKeyLength(usize),
+
+    /// Error generated sending to a closed connection.
+    #[error("connection is closed")]
+    ConnectionClosed,
+
     /// Error generated by the JSON-RPC services.
     #[error(transparent)]
     JsonRpcError(#[from] json_rpc2::Error),
+
+    /// Error generated by the JSON library.
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+}
+
+impl ServerError {
+    /// Stable error code for this error.
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::ZeroPartyNumber => ErrorCode::ZeroPartyNumber,
+            Self::PartyNumberOutOfRange => ErrorCode::PartyNumberOutOfRange,
+            Self::PartyNumberAlreadyExists(_) => {
+                ErrorCode::PartyNumberAlreadyExists
+            }
+            _ => ErrorCode::Internal,
+        }
+    }
 }
 
 /// Result type for server errors.
@@ -113,9 +177,9 @@ pub struct Group {
     pub params: Parameters,
     /// Human-readable label for the group.
     pub label: String,
-    /// Collection of client identifiers.
+    /// Collection of client identities.
     #[serde(skip)]
-    pub(crate) clients: Vec<usize>,
+    pub(crate) clients: Vec<ClientId>,
     /// Sessions belonging to this group.
     #[serde(skip)]
     pub(crate) sessions: HashMap<Uuid, Session>,
@@ -124,11 +188,11 @@ pub struct Group {
 impl Group {
     /// Create a new group.
     ///
-    /// The connection identifier `conn` becomes the initial client for the group.
-    pub fn new(conn: usize, params: Parameters, label: String) -> Self {
+    /// The client identity `client` becomes the initial client for the group.
+    pub fn new(client: ClientId, params: Parameters, label: String) -> Self {
         Self {
             uuid: Uuid::new_v4(),
-            clients: vec![conn],
+            clients: vec![client],
             sessions: Default::default(),
             params,
             label,
@@ -154,9 +218,9 @@ pub struct Session {
     /// a signing session.
     pub value: Option<Value>,
 
-    /// Map party number to connection identifier
+    /// Map party number to client identity
     #[serde(skip)]
-    pub(crate) party_signups: Vec<(u16, usize)>,
+    pub(crate) party_signups: Vec<(u16, ClientId)>,
 
     /// Party numbers for those that have
     /// marked the session as finished.
@@ -164,7 +228,7 @@ pub struct Session {
     pub(crate) finished: HashSet<u16>,
 
     /// Map receiver indices to server issued party numbers
-    /// which can then be used to resolve a connection identifier.
+    /// which can then be used to resolve a client identity.
     ///
     /// During keygen we don't have a pre-defined index so we use
     /// the server issued party number; whereas during signing it
@@ -172,6 +236,21 @@ pub struct Session {
     /// indices allocated during keygen.
     #[serde(skip)]
     pub(crate) participants: HashMap<u16, u16>,
+
+    /// Blame reported by parties that aborted the session
+    /// keyed by the party number of the reporting party.
+    #[serde(skip)]
+    pub(crate) aborted: HashMap<u16, Blame>,
+
+    /// Public key hashes reported by parties to confirm the key
+    /// generated by a session keyed by party number.
+    #[serde(skip)]
+    pub(crate) confirmations: HashMap<u16, String>,
+
+    /// Messages relayed to the parties in the session so a party
+    /// that reconnects can resume the protocol.
+    #[serde(skip)]
+    pub(crate) messages: RelayedMessages,
 }
 
 impl Default for Session {
@@ -183,6 +262,9 @@ impl Default for Session {
             finished: Default::default(),
             value: None,
             participants: Default::default(),
+            aborted: Default::default(),
+            confirmations: Default::default(),
+            messages: Default::default(),
         }
     }
 }
@@ -196,6 +278,9 @@ impl From<(SessionKind, Option<Value>)> for Session {
             finished: Default::default(),
             value: value.1,
             participants: Default::default(),
+            aborted: Default::default(),
+            confirmations: Default::default(),
+            messages: Default::default(),
         }
     }
 }
@@ -205,7 +290,7 @@ impl Session {
     ///
     /// This marks a connected client as actively participating in
     /// this session and issues them a unique party signup number.
-    pub fn signup(&mut self, conn: usize) -> u16 {
+    pub fn signup(&mut self, client: ClientId) -> u16 {
         let last = self.party_signups.last();
         let num = if let Some((num, _)) = last {
             num + 1
@@ -220,7 +305,7 @@ impl Session {
             num + 1
         };
         */
-        self.party_signups.push((num, conn));
+        self.party_signups.push((num, client));
         num
     }
 
@@ -231,7 +316,7 @@ impl Session {
     pub fn load(
         &mut self,
         parameters: &Parameters,
-        conn: usize,
+        client: ClientId,
         party_number: u16,
     ) -> Result<()> {
         if party_number == 0 {
@@ -247,13 +332,13 @@ impl Session {
         {
             return Err(ServerError::PartyNumberAlreadyExists(self.uuid));
         }
-        self.party_signups.push((party_number, conn));
+        self.party_signups.push((party_number, client));
         Ok(())
     }
 
     /// Resolve a receiver identifier for a peer to peer message
-    /// that is being related to a party signup and connection id.
-    pub fn resolve(&self, receiver: u16) -> Option<&(u16, usize)> {
+    /// that is being related to a party signup and client identity.
+    pub fn resolve(&self, receiver: u16) -> Option<&(u16, ClientId)> {
         if let SessionKind::Sign = self.kind {
             if let Some(party_signup) = self.participants.get(&receiver) {
                 return self
@@ -269,17 +354,256 @@ impl Session {
     }
 }
 
+/// Blame reported by a party when a protocol round fails.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Blame {
+    /// Round that failed.
+    pub round: u16,
+    /// Parties identified as misbehaving.
+    ///
+    /// These are the party indices used by the protocol; the party
+    /// signup number for a keygen session or the index into the
+    /// participants for a signing session.
+    pub parties: Vec<u16>,
+    /// Reason the round failed.
+    pub reason: String,
+}
+
+/// Number of protocol rounds of relayed messages kept for a session.
+///
+/// A round needs a message from every party so the other parties
+/// can never be more than one round ahead of a party that reconnects
+/// and older rounds are not needed to resume the protocol.
+pub(crate) const RESUME_ROUNDS: u16 = 2;
+
+/// Message relayed to the parties in a session.
+#[derive(Debug, Clone)]
+pub(crate) struct RelayedMessage {
+    /// Protocol round for the message.
+    pub round: u16,
+    /// Client that sent the message.
+    pub sender: ClientId,
+    /// Client the message was sent to or `None` for a broadcast.
+    pub receiver: Option<ClientId>,
+    /// The message.
+    pub message: Value,
+}
+
+impl RelayedMessage {
+    /// Whether the message was delivered to a client.
+    pub fn delivered_to(&self, client_id: &ClientId) -> bool {
+        match &self.receiver {
+            Some(receiver) => receiver == client_id,
+            None => &self.sender != client_id,
+        }
+    }
+}
+
+/// Messages relayed to the parties in a session for the latest
+/// [RESUME_ROUNDS] rounds.
+///
+/// Messages are relayed while holding a read lock on the server
+/// state so the buffer has its own lock.
+#[derive(Debug, Default)]
+pub(crate) struct RelayedMessages(std::sync::Mutex<VecDeque<RelayedMessage>>);
+
+impl RelayedMessages {
+    /// Add a message and discard the messages for expired rounds.
+    ///
+    /// Returns `false` and discards nothing when the message is more
+    /// than one round ahead of the latest round; no party can be
+    /// further ahead so the message would only evict the rounds
+    /// needed to resume.
+    pub fn push(&self, message: RelayedMessage) -> bool {
+        let mut messages = self.0.lock().unwrap();
+        let latest = messages.iter().map(|m| m.round).max().unwrap_or(0);
+        if message.round > latest.saturating_add(1) {
+            return false;
+        }
+        let oldest =
+            latest.max(message.round).saturating_sub(RESUME_ROUNDS - 1);
+        messages.retain(|m| m.round >= oldest);
+        messages.push_back(message);
+        true
+    }
+
+    /// Messages delivered to a client in the order they were sent.
+    pub fn delivered_to(&self, client_id: &ClientId) -> Vec<Value> {
+        self.0
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|m| m.delivered_to(client_id))
+            .map(|m| m.message.clone())
+            .collect()
+    }
+
+    /// Discard all the messages.
+    pub fn clear(&self) {
+        self.0.lock().unwrap().clear();
+    }
+}
+
+impl Clone for RelayedMessages {
+    fn clone(&self) -> Self {
+        Self(std::sync::Mutex::new(self.0.lock().unwrap().clone()))
+    }
+}
+
+/// Optional features enabled on the server.
+///
+/// Features are advertised to clients in the response
+/// to the `Server.hello` method.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Features {
+    /// Messages are end-to-end encrypted between clients.
+    pub encryption: bool,
+    /// Sessions may use the CGGMP protocol.
+    pub cggmp: bool,
+}
+
+/// Options for the websocket heartbeat.
+///
+/// The server pings every client at `interval`; when a client
+/// has not responded for longer than `interval` the other members
+/// of it's groups are notified that the party is offline and when
+/// a client has not responded within `timeout` it is disconnected.
+#[derive(Debug, Clone, Copy)]
+pub struct Heartbeat {
+    /// Interval between pings.
+    pub interval: Duration,
+    /// Disconnect clients that do not respond within this duration.
+    pub timeout: Duration,
+}
+
+impl Heartbeat {
+    /// Create heartbeat options.
+    ///
+    /// The interval must be non-zero and the timeout must be
+    /// longer than the interval otherwise responsive clients
+    /// would be disconnected.
+    pub fn new(interval: Duration, timeout: Duration) -> Result<Self> {
+        let heartbeat = Self { interval, timeout };
+        heartbeat.validate()?;
+        Ok(heartbeat)
+    }
+
+    /// Check the heartbeat options are valid.
+    pub fn validate(&self) -> Result<()> {
+        if self.interval.is_zero() || self.timeout <= self.interval {
+            return Err(ServerError::InvalidHeartbeat(
+                self.interval,
+                self.timeout,
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Default for Heartbeat {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(15),
+            timeout: Duration::from_secs(45),
+        }
+    }
+}
+
+/// Graceful shutdown configuration.
+///
+/// When the server receives `SIGTERM` (or `Ctrl+C`) it waits
+/// at most `timeout` for sessions in progress to finish before
+/// closing the client connections.
+#[derive(Debug, Clone, Copy)]
+pub struct Shutdown {
+    /// Maximum time to wait for sessions to finish.
+    pub timeout: Duration,
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Payload for the shutdown notification.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerShutdown {
+    /// Seconds the server will wait for sessions to finish.
+    pub timeout: u64,
+}
+
+/// Options for the server.
+#[derive(Debug, Default, Clone)]
+pub struct ServerOptions {
+    /// Features advertised to clients.
+    pub features: Features,
+    /// Heartbeat for connected clients.
+    pub heartbeat: Heartbeat,
+    /// Graceful shutdown configuration.
+    pub shutdown: Shutdown,
+    /// Backplane shared with other server instances.
+    pub backplane: Option<Arc<dyn Backplane>>,
+    /// Enable the admin API.
+    pub admin: Option<AdminOptions>,
+    /// Static files to serve.
+    pub static_files: Option<StaticFiles>,
+    /// Origins allowed to connect to the server.
+    pub allowed_origins: AllowedOrigins,
+    /// Enable the HTTP transport.
+    pub http: Option<HttpOptions>,
+}
+
 /// Collection of clients and groups managed by the server.
 #[derive(Debug)]
 pub struct State {
-    /// Connected clients.
-    pub clients: HashMap<usize, mpsc::UnboundedSender<Message>>,
+    /// Connections keyed by connection identifier.
+    pub clients: HashMap<usize, Arc<dyn Connection>>,
+    /// Connection identifier for each connected client identity.
+    pub identities: HashMap<ClientId, usize>,
     /// Groups keyed by unique identifier (UUID)
     pub groups: HashMap<Uuid, Group>,
+    /// Features enabled on the server.
+    pub features: Features,
+    /// Membership of a cluster when a backplane is configured.
+    pub cluster: Option<Cluster>,
+    /// Groups changed by the current request.
+    pub(crate) changed: HashSet<Uuid>,
+    /// Whether the server is shutting down.
+    pub(crate) shutting_down: bool,
+    /// Metrics for compressed messages.
+    pub(crate) compression_metrics: Arc<CompressionMetrics>,
+}
+
+impl State {
+    /// Create empty server state.
+    pub(crate) fn new(features: Features, cluster: Option<Cluster>) -> Self {
+        Self {
+            clients: HashMap::new(),
+            identities: HashMap::new(),
+            groups: Default::default(),
+            features,
+            cluster,
+            changed: Default::default(),
+            shutting_down: false,
+            compression_metrics: Default::default(),
+        }
+    }
+
+    /// Mark a group as changed so that it is replicated
+    /// to the other server instances.
+    pub(crate) fn touch(&mut self, group_id: Uuid) {
+        if self.cluster.is_some() {
+            self.changed.insert(group_id);
+        }
+    }
 }
 
 /// Notification sent by the server to multiple connected clients.
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub enum Notification {
     /// Indicates that the response should be ignored
     /// and no notification messages should be sent.
@@ -295,7 +619,7 @@ pub enum Notification {
         /// The group identifier.
         group_id: Uuid,
         /// Ignore these clients.
-        filter: Option<Vec<usize>>,
+        filter: Option<Vec<ClientId>>,
         /// Message to send to the clients.
         response: Response,
     },
@@ -307,7 +631,7 @@ pub enum Notification {
         /// The session identifier.
         session_id: Uuid,
         /// Ignore these clients.
-        filter: Option<Vec<usize>>,
+        filter: Option<Vec<ClientId>>,
         /// Message to send to the clients.
         response: Response,
     },
@@ -316,8 +640,8 @@ pub enum Notification {
     ///
     /// Used for relaying peer to peer messages.
     Relay {
-        /// Mapping of client connection identifiers to messages.
-        messages: Vec<(usize, Response)>,
+        /// Mapping of client identities to messages.
+        messages: Vec<(ClientId, Response)>,
     },
 }
 
@@ -327,6 +651,18 @@ impl Default for Notification {
     }
 }
 
+/// Payload for events indicating a party is offline or online.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PartyStatus {
+    /// The group identifier.
+    group_id: Uuid,
+    /// The client identity for the party.
+    client_id: ClientId,
+    /// Party signup numbers for the party keyed by session identifier.
+    sessions: HashMap<Uuid, u16>,
+}
+
 /// MPC websocket server handling JSON-RPC requests.
 pub struct Server;
 
@@ -344,6 +680,39 @@ impl Server {
         addr: impl Into<SocketAddr>,
         static_files: PathBuf,
     ) -> Result<()> {
+        let options = ServerOptions {
+            static_files: Some(static_files.into()),
+            ..Default::default()
+        };
+        Server::start_with_options(path, addr, options).await
+    }
+
+    /// Start the server with the given options.
+    ///
+    /// When no static files are configured the server runs in
+    /// websocket only mode.
+    ///
+    /// The server shuts down gracefully on ctrl-c or SIGTERM; use
+    /// [Server::start_with_shutdown] to handle signals elsewhere.
+    pub async fn start_with_options(
+        path: &'static str,
+        addr: impl Into<SocketAddr>,
+        options: ServerOptions,
+    ) -> Result<()> {
+        Server::start_with_shutdown(path, addr, options, shutdown_signal())
+            .await
+    }
+
+    /// Start the server and shut down gracefully when the
+    /// `signal` future completes.
+    pub async fn start_with_shutdown(
+        path: &'static str,
+        addr: impl Into<SocketAddr>,
+        options: ServerOptions,
+        signal: impl Future<Output = ()> + Send + 'static,
+    ) -> Result<()> {
+        options.heartbeat.validate()?;
+
         // Filter traces based on the RUST_LOG env var.
         let filter = std::env::var("RUST_LOG").unwrap_or_else(|_| {
             "tracing=info,warp=debug,mpc_websocket=info".to_owned()
@@ -362,22 +731,39 @@ impl Server {
                 .init();
         }
 
-        let state = Arc::new(RwLock::new(State {
-            clients: HashMap::new(),
-            groups: Default::default(),
-        }));
-        let state = warp::any().map(move || state.clone());
-
-        if !static_files.is_dir() {
-            return Err(ServerError::NotDirectory(static_files));
+        let cluster = options.backplane.map(Cluster::new);
+        let state = Arc::new(RwLock::new(State::new(
+            options.features,
+            cluster.clone(),
+        )));
+
+        if let Some(cluster) = cluster {
+            let instance_id = cluster.instance_id().to_string();
+            tracing::info!(%instance_id, "backplane");
+            let mut incoming = cluster.subscribe().await?;
+            let state = Arc::clone(&state);
+            tokio::task::spawn(async move {
+                while let Some(message) = incoming.recv().await {
+                    backplane_message(message, &state).await;
+                }
+                tracing::error!("backplane subscription closed");
+            });
         }
 
-        let static_files = static_files.canonicalize()?;
-        let static_path = static_files.to_string_lossy().into_owned();
-        tracing::info!(%static_path);
+        let shutdown = {
+            let state = Arc::clone(&state);
+            let options = options.shutdown;
+            async move {
+                signal.await;
+                graceful_shutdown(&state, options).await;
+            }
+        };
+
+        let state = warp::any().map(move || state.clone());
+
         tracing::info!(path);
 
-        let client = warp::any().and(warp::fs::dir(static_files));
+        let client = files::routes(options.static_files)?;
 
         let mut headers = HeaderMap::new();
         headers.insert(
@@ -389,26 +775,87 @@ impl Server {
             HeaderValue::from_static("same-origin"),
         );
 
-        let websocket = warp::path(path).and(warp::ws()).and(state).map(
-            |ws: warp::ws::Ws, state| {
-                ws.on_upgrade(move |socket| client_connected(socket, state))
-            },
-        );
+        let heartbeat = options.heartbeat;
+        let origins = options.allowed_origins;
+        let cors = origins.cors();
+        let websocket = warp::path(path)
+            .and(warp::ws())
+            .and(warp::header::optional::<String>("origin"))
+            .and(warp::query::<Codec>())
+            .and(warp::query::<IdentityQuery>())
+            .and(state.clone())
+            .map(
+                move |ws: warp::ws::Ws,
+                      origin: Option<String>,
+                      codec: Codec,
+                      identity: IdentityQuery,
+                      state| {
+                    if !origins.is_allowed(origin.as_deref()) {
+                        tracing::warn!(?origin, "websocket origin rejected");
+                        return warp::reply::with_status(
+                            "origin not allowed",
+                            StatusCode::FORBIDDEN,
+                        )
+                        .into_response();
+                    }
+                    let client_id = match identity.client_id() {
+                        Ok(client_id) => client_id,
+                        Err(e) => {
+                            return warp::reply::with_status(
+                                e.to_string(),
+                                StatusCode::BAD_REQUEST,
+                            )
+                            .into_response();
+                        }
+                    };
+                    ws.on_upgrade(move |socket| {
+                        client_connected(
+                            socket, state, heartbeat, codec, client_id,
+                        )
+                    })
+                    .into_response()
+                },
+            );
+
+        let transport =
+            http::routes(path, options.http, heartbeat, state.clone());
+        let health = health::routes(state.clone());
+        let admin = admin::routes(options.admin, state);
+        let http = transport.or(health).or(admin).or(client).with(cors);
 
         let routes = websocket
-            .or(client)
+            .or(http)
             .with(warp::reply::with::headers(headers))
             .with(warp::trace::request());
 
-        warp::serve(routes).run(addr).await;
+        let (_, server) = warp::serve(routes)
+            .bind_with_graceful_shutdown(addr.into(), shutdown);
+        server.await;
+        tracing::info!("shutdown complete");
         Ok(())
     }
 }
 
-async fn client_connected(ws: WebSocket, state: Arc<RwLock<State>>) {
-    let conn_id = CONNECTION_ID.fetch_add(1, Ordering::Relaxed);
+async fn client_connected(
+    ws: WebSocket,
+    state: Arc<RwLock<State>>,
+    heartbeat: Heartbeat,
+    codec: Codec,
+    client_id: ClientId,
+) {
+    let conn_id = if let Some(conn_id) = next_connection_id(&state).await {
+        conn_id
+    } else {
+        return;
+    };
 
-    tracing::info!(conn_id, "connected");
+    tracing::info!(
+        conn_id,
+        %client_id,
+        encoding = codec.encoding.name(),
+        compression = codec.compression.name(),
+        "connected"
+    );
 
     // Split the socket into a sender and receive of messages.
     let (mut user_ws_tx, mut user_ws_rx) = ws.split();
@@ -418,8 +865,8 @@ async fn client_connected(ws: WebSocket, state: Arc<RwLock<State>>) {
     let (tx, rx) = mpsc::unbounded_channel::<Message>();
     let mut rx = UnboundedReceiverStream::new(rx);
 
-    let mut close_flag = Arc::new(RwLock::new(false));
-    let should_close = Arc::clone(&close_flag);
+    let closing = Arc::new(AtomicBool::new(false));
+    let should_close = Arc::clone(&closing);
 
     tokio::task::spawn(async move {
         while let Some(message) = rx.next().await {
@@ -430,8 +877,7 @@ async fn client_connected(ws: WebSocket, state: Arc<RwLock<State>>) {
                 })
                 .await;
 
-            let reader = should_close.read().await;
-            if *reader {
+            if should_close.load(Ordering::SeqCst) {
                 if let Err(e) = user_ws_tx.close().await {
                     tracing::warn!(?e, "failed to close websocket");
                 }
@@ -441,48 +887,217 @@ async fn client_connected(ws: WebSocket, state: Arc<RwLock<State>>) {
     });
 
     // Save the sender in our list of connected clients.
-    state.write().await.clients.insert(conn_id, tx);
+    let metrics = Arc::clone(&state.read().await.compression_metrics);
+    let conn = WebSocketConnection::new(tx.clone(), codec, closing, metrics);
+    bind_client(conn_id, client_id, Arc::new(conn), &state).await;
+
+    let mut ping = tokio::time::interval(heartbeat.interval);
+    let mut last_seen = Instant::now();
+    let mut offline = false;
 
     // Handle incoming requests from clients
-    while let Some(result) = user_ws_rx.next().await {
-        let msg = match result {
-            Ok(msg) => msg,
-            Err(e) => {
-                tracing::error!(conn_id, ?e, "websocket rx error");
-                break;
+    loop {
+        tokio::select! {
+            result = user_ws_rx.next() => {
+                let msg = match result {
+                    Some(Ok(msg)) => msg,
+                    Some(Err(e)) => {
+                        tracing::error!(conn_id, ?e, "websocket rx error");
+                        break;
+                    }
+                    None => break,
+                };
+
+                // Any frame (including a pong) means the client is alive
+                last_seen = Instant::now();
+                if offline {
+                    offline = false;
+                    tracing::info!(conn_id, "online");
+                    notify_party_status(client_id, PARTY_ONLINE_EVENT, &state)
+                        .await;
+                }
+
+                client_incoming_message(
+                    conn_id, client_id, msg, codec, &state,
+                )
+                .await;
             }
-        };
+            _ = ping.tick() => {
+                let elapsed = last_seen.elapsed();
+                if elapsed >= heartbeat.timeout {
+                    tracing::warn!(conn_id, ?elapsed, "heartbeat timeout");
+                    break;
+                }
+
+                if !offline && elapsed > heartbeat.interval {
+                    offline = true;
+                    tracing::info!(conn_id, ?elapsed, "offline");
+                    notify_party_status(client_id, PARTY_OFFLINE_EVENT, &state)
+                        .await;
+                }
 
-        client_incoming_message(conn_id, &mut close_flag, msg, &state).await;
+                if tx.send(Message::ping(Vec::new())).is_err() {
+                    break;
+                }
+            }
+        }
     }
 
     // user_ws_rx stream will keep processing as long as the user stays
     // connected. Once they disconnect, then...
-    client_disconnected(conn_id, &state).await;
+    client_disconnected(conn_id, client_id, &state).await;
 }
 
-async fn client_incoming_message(
+/// Register the connection for a client identity.
+///
+/// When the identity is already bound to another connection
+/// the client has reconnected so the previous connection is
+/// closed without removing the client from it's groups.
+pub(crate) async fn bind_client(
     conn_id: usize,
-    close_flag: &mut Arc<RwLock<bool>>,
-    msg: Message,
+    client_id: ClientId,
+    conn: Arc<dyn Connection>,
     state: &Arc<RwLock<State>>,
 ) {
-    let msg = if let Ok(s) = msg.to_str() {
-        s
-    } else {
-        return;
+    let mut writer = state.write().await;
+    writer.clients.insert(conn_id, conn);
+    if let Some(previous) = writer.identities.insert(client_id, conn_id) {
+        if let Some(conn) = writer.clients.get(&previous) {
+            tracing::info!(%client_id, previous, conn_id, "reconnected");
+            conn.close();
+        }
+    }
+}
+
+/// Wait for a signal to shutdown the server.
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = terminate.recv() => {}
+                    _ = tokio::signal::ctrl_c() => {}
+                }
+            }
+            Err(e) => {
+                tracing::error!(?e, "failed to install SIGTERM handler");
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
+}
+
+/// Drain the server before shutting down.
+///
+/// Stops new groups and sessions from being created, notifies all
+/// the clients, waits for sessions in progress to finish and then
+/// closes the client connections.
+pub(crate) async fn graceful_shutdown(
+    state: &Arc<RwLock<State>>,
+    options: Shutdown,
+) {
+    tracing::info!(timeout = ?options.timeout, "shutting down");
+
+    let clients = {
+        let mut writer = state.write().await;
+        writer.shutting_down = true;
+        writer.clients.keys().copied().collect::<Vec<_>>()
+    };
+
+    let payload = ServerShutdown {
+        timeout: options.timeout.as_secs(),
+    };
+    let value = serde_json::to_value((SERVER_SHUTDOWN_EVENT, payload)).unwrap();
+    let response: Response = value.into();
+    for conn_id in clients {
+        rpc_response(conn_id, &response, state).await;
+    }
+
+    let wait = async {
+        let mut interval = tokio::time::interval(Duration::from_millis(250));
+        loop {
+            interval.tick().await;
+            let pending = sessions_in_progress(&*state.read().await);
+            if pending == 0 {
+                break;
+            }
+            tracing::debug!(pending, "waiting for sessions");
+        }
     };
 
-    match json_rpc2::from_str(msg) {
-        Ok(req) => rpc_request(conn_id, close_flag, req, state).await,
-        Err(e) => tracing::warn!(conn_id, ?e, "websocket rx JSON error"),
+    if tokio::time::timeout(options.timeout, wait).await.is_err() {
+        let pending = sessions_in_progress(&*state.read().await);
+        tracing::warn!(pending, "shutdown timed out with sessions in progress");
+    }
+
+    // Connections are closed after any pending messages
+    let reader = state.read().await;
+    for conn in reader.clients.values() {
+        conn.close();
+    }
+}
+
+/// Number of sessions that have participants that
+/// have not yet finished the session.
+fn sessions_in_progress(state: &State) -> usize {
+    state
+        .groups
+        .values()
+        .flat_map(|group| group.sessions.values())
+        .filter(|session| {
+            !session.party_signups.is_empty()
+                && session.finished.len() < session.party_signups.len()
+        })
+        .count()
+}
+
+/// Allocate a connection identifier.
+///
+/// When a backplane is configured connection identifiers are
+/// allocated from a shared counter so they are unique across all
+/// the server instances.
+pub(crate) async fn next_connection_id(
+    state: &Arc<RwLock<State>>,
+) -> Option<usize> {
+    let cluster = state.read().await.cluster.clone();
+    if let Some(cluster) = cluster {
+        match cluster.next_connection_id().await {
+            Ok(conn_id) => Some(conn_id),
+            Err(e) => {
+                tracing::error!(?e, "backplane connection id error");
+                None
+            }
+        }
+    } else {
+        Some(CONNECTION_ID.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+async fn client_incoming_message(
+    conn_id: usize,
+    client_id: ClientId,
+    msg: Message,
+    codec: Codec,
+    state: &Arc<RwLock<State>>,
+) {
+    match codec.decode(&msg) {
+        Ok(Some(req)) => rpc_request(conn_id, client_id, req, state).await,
+        Ok(None) => {}
+        Err(e) => tracing::warn!(conn_id, ?e, "websocket rx decode error"),
     }
 }
 
 /// Process a request message from a client.
-async fn rpc_request(
+pub(crate) async fn rpc_request(
     conn_id: usize,
-    close_flag: &mut Arc<RwLock<bool>>,
+    client_id: ClientId,
     request: Request,
     state: &Arc<RwLock<State>>,
 ) {
@@ -497,7 +1112,7 @@ async fn rpc_request(
     if let Some(response) = server
         .serve(
             &request,
-            &(conn_id, Arc::clone(state), Arc::clone(&notification)),
+            &(client_id, Arc::clone(state), Arc::clone(&notification)),
         )
         .await
     {
@@ -505,29 +1120,98 @@ async fn rpc_request(
 
         if let Some(error) = response.error() {
             if let Some(data) = &error.data {
-                if data == CLOSE_CONNECTION {
-                    let mut writer = close_flag.write().await;
-                    *writer = true;
+                if let Ok(data) = data.parse::<ErrorData>() {
+                    if data.close_connection {
+                        let reader = state.read().await;
+                        if let Some(conn) = reader.clients.get(&conn_id) {
+                            conn.close();
+                        }
+                    }
                 }
             }
         }
     }
 
+    publish_changes(state).await;
+
     let mut writer = notification.lock().await;
     if let Some(notification) = writer.take() {
         rpc_notify(state, notification).await;
     }
 }
 
+/// Publish snapshots of the groups changed by a request
+/// to the other server instances.
+async fn publish_changes(state: &Arc<RwLock<State>>) {
+    let (cluster, snapshots) = {
+        let mut writer = state.write().await;
+        let cluster = if let Some(cluster) = writer.cluster.clone() {
+            cluster
+        } else {
+            return;
+        };
+        let changed = writer.changed.drain().collect::<Vec<_>>();
+        let snapshots = changed
+            .iter()
+            .filter_map(|group_id| writer.groups.get(group_id))
+            .map(GroupSnapshot::from)
+            .collect::<Vec<_>>();
+        (cluster, snapshots)
+    };
+
+    for snapshot in snapshots {
+        if let Err(e) = cluster.publish_group(snapshot).await {
+            tracing::error!(?e, "backplane publish error");
+        }
+    }
+}
+
+/// Handle a message published by another server instance.
+async fn backplane_message(
+    message: BackplaneMessage,
+    state: &Arc<RwLock<State>>,
+) {
+    match message {
+        BackplaneMessage::Notify(notification) => {
+            deliver_notification(state, notification).await;
+        }
+        BackplaneMessage::Group(snapshot) => {
+            let mut writer = state.write().await;
+            if let Some(group) = writer.groups.get_mut(&snapshot.uuid) {
+                merge_group(group, snapshot);
+            } else {
+                writer.groups.insert(snapshot.uuid, snapshot.into());
+            }
+        }
+        BackplaneMessage::Disconnect(client_id) => {
+            // Client may have reconnected to this instance
+            let mut writer = state.write().await;
+            if !writer.identities.contains_key(&client_id) {
+                remove_client(client_id, &mut writer);
+            }
+        }
+        BackplaneMessage::RemoveGroup(group_id) => {
+            let mut writer = state.write().await;
+            writer.groups.remove(&group_id);
+        }
+        BackplaneMessage::RemoveSession(group_id, session_id) => {
+            let mut writer = state.write().await;
+            if let Some(group) = writer.groups.get_mut(&group_id) {
+                group.sessions.remove(&session_id);
+            }
+        }
+    }
+}
+
 /// Remove `filters` from a list of clients.
 fn filter_clients(
-    clients: Vec<usize>,
-    filter: Option<Vec<usize>>,
-) -> Vec<usize> {
+    clients: Vec<ClientId>,
+    filter: Option<Vec<ClientId>>,
+) -> Vec<ClientId> {
     if let Some(filter) = filter {
         clients
             .into_iter()
-            .filter(|conn| !filter.iter().any(|c| c == conn))
+            .filter(|client| !filter.iter().any(|c| c == client))
             .collect::<Vec<_>>()
     } else {
         clients
@@ -535,9 +1219,46 @@ fn filter_clients(
 }
 
 /// Send notification to connected client(s).
-async fn rpc_notify(state: &Arc<RwLock<State>>, notification: Notification) {
+///
+/// When a backplane is configured the notification is also
+/// published to the other server instances.
+pub(crate) async fn rpc_notify(
+    state: &Arc<RwLock<State>>,
+    notification: Notification,
+) {
+    let cluster = state.read().await.cluster.clone();
+    if let Some(cluster) = cluster {
+        if let Err(e) = cluster.publish_notification(&notification).await {
+            tracing::error!(?e, "backplane publish error");
+        }
+    }
+    deliver_notification(state, notification).await;
+}
+
+/// Remove clients connected to other server instances
+/// from a list of clients.
+fn local_clients(state: &State, clients: Vec<ClientId>) -> Vec<ClientId> {
+    if state.cluster.is_some() {
+        clients
+            .into_iter()
+            .filter(|client| state.identities.contains_key(client))
+            .collect()
+    } else {
+        clients
+    }
+}
+
+/// Send notification to the client(s) connected to this server.
+///
+/// The connections are collected under a single read lock which is
+/// released before sending; taking the lock again for each client
+/// could deadlock with a writer queued in between.
+async fn deliver_notification(
+    state: &Arc<RwLock<State>>,
+    notification: Notification,
+) {
     let reader = state.read().await;
-    match notification {
+    let deliveries = match notification {
         Notification::Group {
             group_id,
             filter,
@@ -546,13 +1267,15 @@ async fn rpc_notify(state: &Arc<RwLock<State>>, notification: Notification) {
             let clients = if let Some(group) = reader.groups.get(&group_id) {
                 group.clients.clone()
             } else {
-                vec![0usize]
+                Vec::new()
             };
 
             let clients = filter_clients(clients, filter);
-            for conn_id in clients {
-                rpc_response(conn_id, &response, state).await;
-            }
+            let connections = local_clients(&reader, clients)
+                .iter()
+                .filter_map(|client_id| client_connection(&reader, client_id))
+                .collect::<Vec<_>>();
+            vec![(connections, response)]
         }
         Notification::Session {
             group_id,
@@ -567,62 +1290,213 @@ async fn rpc_notify(state: &Arc<RwLock<State>>, notification: Notification) {
                     tracing::warn!(
                         %session_id,
                         "notification session does not exist");
-                    vec![0usize]
+                    Vec::new()
                 }
             } else {
-                vec![0usize]
+                Vec::new()
             };
 
             let clients = filter_clients(clients, filter);
-            for conn_id in clients {
-                rpc_response(conn_id, &response, state).await;
-            }
+            let connections = local_clients(&reader, clients)
+                .iter()
+                .filter_map(|client_id| client_connection(&reader, client_id))
+                .collect::<Vec<_>>();
+            vec![(connections, response)]
         }
-        Notification::Relay { messages } => {
-            for (conn_id, response) in messages {
-                rpc_response(conn_id, &response, state).await;
-            }
+        Notification::Relay { messages } => messages
+            .into_iter()
+            .filter(|(client_id, _)| {
+                reader.cluster.is_none()
+                    || reader.identities.contains_key(client_id)
+            })
+            .map(|(client_id, response)| {
+                let connection = client_connection(&reader, &client_id);
+                (connection.into_iter().collect(), response)
+            })
+            .collect(),
+        Notification::Noop => Vec::new(),
+    };
+    drop(reader);
+
+    for (connections, response) in deliveries {
+        for (conn_id, conn) in connections {
+            send_response(conn_id, conn.as_ref(), &response);
         }
-        Notification::Noop => {}
     }
 }
 
-/// Send a message to a single client.
+/// Find the connection for a client identity.
+fn client_connection(
+    state: &State,
+    client_id: &ClientId,
+) -> Option<(usize, Arc<dyn Connection>)> {
+    let conn_id = if let Some(conn_id) = state.identities.get(client_id) {
+        *conn_id
+    } else {
+        tracing::warn!(%client_id, "could not find client");
+        return None;
+    };
+    if let Some(conn) = state.clients.get(&conn_id) {
+        Some((conn_id, Arc::clone(conn)))
+    } else {
+        tracing::warn!(conn_id, "could not find connection");
+        None
+    }
+}
+
+/// Send a message to a single connection.
 async fn rpc_response(
     conn_id: usize,
     response: &json_rpc2::Response,
     state: &Arc<RwLock<State>>,
 ) {
     tracing::debug!(conn_id, "send message");
-    if let Some(tx) = state.read().await.clients.get(&conn_id) {
-        tracing::debug!(?response, "send response");
-        let msg = serde_json::to_string(response).unwrap();
-        if let Err(_disconnected) = tx.send(Message::text(msg)) {
-            // The tx is disconnected, our `client_disconnected` code
-            // should be happening in another task, nothing more to
-            // do here.
-        }
+    let conn = state.read().await.clients.get(&conn_id).cloned();
+    if let Some(conn) = conn {
+        send_response(conn_id, conn.as_ref(), response);
     } else {
-        tracing::warn!(conn_id, "could not find tx for websocket");
+        tracing::warn!(conn_id, "could not find connection");
+    }
+}
+
+/// Send a message using a connection.
+fn send_response(
+    conn_id: usize,
+    conn: &dyn Connection,
+    response: &json_rpc2::Response,
+) {
+    tracing::debug!(conn_id, ?response, "send response");
+    match conn.send(response) {
+        // The connection is closed, our `client_disconnected` code
+        // should be happening in another task, nothing more to
+        // do here.
+        Ok(_) | Err(ServerError::ConnectionClosed) => {}
+        Err(e) => {
+            tracing::error!(conn_id, ?e, "connection tx encode error");
+        }
+    }
+}
+
+/// Notify the other members of the groups a client belongs to
+/// that the client has gone offline or come back online.
+pub(crate) async fn notify_party_status(
+    client_id: ClientId,
+    event: &str,
+    state: &Arc<RwLock<State>>,
+) {
+    let notifications = {
+        let reader = state.read().await;
+        reader
+            .groups
+            .values()
+            .filter(|group| group.clients.contains(&client_id))
+            .map(|group| {
+                let status = PartyStatus {
+                    group_id: group.uuid,
+                    client_id,
+                    sessions: group
+                        .sessions
+                        .values()
+                        .filter_map(|session| {
+                            session
+                                .party_signups
+                                .iter()
+                                .find(|(_, client)| *client == client_id)
+                                .map(|(number, _)| (session.uuid, *number))
+                        })
+                        .collect(),
+                };
+                let value = serde_json::to_value((event, status)).unwrap();
+                Notification::Group {
+                    group_id: group.uuid,
+                    filter: Some(vec![client_id]),
+                    response: value.into(),
+                }
+            })
+            .collect::<Vec<_>>()
+    };
+
+    for notification in notifications {
+        rpc_notify(state, notification).await;
     }
 }
 
-async fn client_disconnected(conn_id: usize, state: &Arc<RwLock<State>>) {
-    tracing::info!(conn_id, "disconnected");
+pub(crate) async fn client_disconnected(
+    conn_id: usize,
+    client_id: ClientId,
+    state: &Arc<RwLock<State>>,
+) {
+    tracing::info!(conn_id, %client_id, "disconnected");
 
     // FIXME: prune session party signups for disconnected clients?
 
-    let mut empty_groups: Vec<Uuid> = Vec::new();
-    {
+    let (cluster, notifications, empty_groups) = {
         let mut writer = state.write().await;
         // Stream closed up, so remove from the client list
         writer.clients.remove(&conn_id);
-        // Remove the connection from any client groups
-        for (key, group) in writer.groups.iter_mut() {
+
+        // Client has reconnected using another connection
+        if writer.identities.get(&client_id) != Some(&conn_id) {
+            return;
+        }
+        writer.identities.remove(&client_id);
+
+        let (notifications, empty_groups) =
+            remove_client(client_id, &mut writer);
+        (writer.cluster.clone(), notifications, empty_groups)
+    };
+
+    if let Some(cluster) = &cluster {
+        if let Err(e) = cluster.publish_disconnect(client_id).await {
+            tracing::error!(?e, "backplane publish error");
+        }
+        for key in &empty_groups {
+            if let Err(e) = cluster.remove_group(key).await {
+                tracing::error!(?e, "backplane remove group error");
+            }
+        }
+    }
+
+    for notification in notifications {
+        rpc_notify(state, notification).await;
+    }
+}
+
+/// Remove a client from the groups it belongs to and prune
+/// the groups that no longer have any connected clients.
+///
+/// Returns notifications for the remaining members of the groups
+/// and the identifiers of the groups that were removed.
+fn remove_client(
+    client_id: ClientId,
+    state: &mut State,
+) -> (Vec<Notification>, Vec<Uuid>) {
+    let mut empty_groups: Vec<Uuid> = Vec::new();
+    let mut notifications: Vec<Notification> = Vec::new();
+    {
+        // Remove the client from any client groups
+        for (key, group) in state.groups.iter_mut() {
             if let Some(index) =
-                group.clients.iter().position(|x| *x == conn_id)
+                group.clients.iter().position(|x| *x == client_id)
             {
                 group.clients.remove(index);
+
+                // Let the remaining members know the client has left
+                if !group.clients.is_empty() {
+                    let member = GroupMember {
+                        group_id: *key,
+                        client_id,
+                        members: group.clients.len(),
+                    };
+                    let value =
+                        serde_json::to_value((GROUP_LEAVE_EVENT, member))
+                            .unwrap();
+                    notifications.push(Notification::Group {
+                        group_id: *key,
+                        filter: None,
+                        response: value.into(),
+                    });
+                }
             }
 
             // Group has no more connected clients so flag it for removal
@@ -633,9 +1507,53 @@ async fn client_disconnected(conn_id: usize, state: &Arc<RwLock<State>>) {
     }
 
     // Prune empty groups
-    let mut writer = state.write().await;
-    for key in empty_groups {
-        writer.groups.remove(&key);
+    for key in &empty_groups {
+        state.groups.remove(key);
         tracing::info!(%key, "removed group");
     }
+
+    (notifications, empty_groups)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn heartbeat_validation() {
+        let secs = Duration::from_secs;
+        assert!(Heartbeat::default().validate().is_ok());
+        assert!(Heartbeat::new(secs(1), secs(2)).is_ok());
+        assert!(matches!(
+            Heartbeat::new(Duration::ZERO, secs(2)),
+            Err(ServerError::InvalidHeartbeat(_, _))
+        ));
+        assert!(matches!(
+            Heartbeat::new(secs(2), secs(2)),
+            Err(ServerError::InvalidHeartbeat(_, _))
+        ));
+        assert!(matches!(
+            Heartbeat::new(secs(3), secs(2)),
+            Err(ServerError::InvalidHeartbeat(_, _))
+        ));
+    }
+
+    #[tokio::test]
+    async fn start_rejects_invalid_heartbeat() {
+        let options = ServerOptions {
+            heartbeat: Heartbeat {
+                interval: Duration::ZERO,
+                timeout: Duration::from_secs(1),
+            },
+            ..Default::default()
+        };
+        let result = Server::start_with_shutdown(
+            "mpc",
+            ([127, 0, 0, 1], 0),
+            options,
+            async {},
+        )
+        .await;
+        assert!(matches!(result, Err(ServerError::InvalidHeartbeat(_, _))));
+    }
 }
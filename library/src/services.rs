@@ -11,6 +11,16 @@
 //!
 //! These are the JSON-RPC methods clients may call; some methods will broadcast events to connected clients, see the documentation for each method for more information.
 //!
+//! ### Server.hello
+//!
+//! * `version`: The `u16` protocol version supported by the client.
+//!
+//! Negotiate the protocol version; clients should call this method before any other method.
+//!
+//! If the client version is not supported by the server an `IncompatibleVersion` error is returned and the connection is closed.
+//!
+//! Returns a [ServerHello](ServerHello) with the server protocol version, supported methods, enabled features and the [identity](crate::identity) of the client.
+//!
 //! ### Group.create
 //!
 //! * `label`: Human-friendly `String` label for the group.
@@ -26,6 +36,10 @@
 //!
 //! Register the calling client as a member of the group.
 //!
+//! A client that is already a member (for example one that has reconnected using the same identity) may join again, even when the group is full; no event is emitted in that case.
+//!
+//! When a client joins a group a `groupJoin` event is emitted to the other members of the group and when a member disconnects a `groupLeave` event is emitted to the remaining members; the payload for these events includes the `groupId`, the `clientId` for the member and the number of `members` in the group.
+//!
 //! Returns the group object.
 //!
 //! ### Session.create
@@ -56,7 +70,7 @@
 //!
 //! When the required number of parties have signed up to a session a `sessionSignup` event is emitted to all the clients in the session. For key generation there must be `parties` clients in the session and for signing there must be `threshold + 1` clients registered for the session.
 //!
-//! Returns the party signup number.
+//! Returns the party signup number; a client that signs up to the same session again is given the number it already has.
 //!
 //! ### Session.load
 //!
@@ -80,9 +94,9 @@
 //! * `index`: The `u16` party index.
 //! * `number`: The `u16` party signup number.
 //!
-//! Provide a mapping between receiver indices for a signing session so that the server can locate the connection identifier when relaying peer to peer messages.
+//! Provide a mapping between receiver indices for a signing session so that the server can locate the client identity when relaying peer to peer messages.
 //!
-//! When signing clients must provide an array of the indices used during DKG, the party index is the index (plus one) of each client's local key index in that array. This is the value that the server sees as the receiver when relaying peer to peer messages but the server has no knowledge of this index so client's must register a mapping from the party index to the server-issued party number so that the correct connection id can be resolved.
+//! When signing clients must provide an array of the indices used during DKG, the party index is the index (plus one) of each client's local key index in that array. This is the value that the server sees as the receiver when relaying peer to peer messages but the server has no knowledge of this index so client's must register a mapping from the party index to the server-issued party number so that the correct client can be resolved.
 //!
 //! Returns an empty response to the caller.
 //!
@@ -97,6 +111,8 @@
 //!
 //! A `message` is treated as peer to peer when the `receiver` field is present which should be the party signup `number` for the peer for a keygen session or the party index for a signing session.
 //!
+//! A message for a `round` more than one round ahead of the latest round relayed in the session is rejected with a `BadRound` error.
+//!
 //! This method is a notification and does not return anything to the caller.
 //!
 //! ### Session.finish
@@ -111,18 +127,105 @@
 //!
 //! This method is a notification and does not return anything to the caller.
 //!
+//! ### Session.abort
+//!
+//! * `group_id`: The `String` UUID for the group.
+//! * `session_id`: The `String` UUID for the session.
+//! * `number`: The `u16` party signup number.
+//! * `blame`: The [Blame](Blame) for the failed round.
+//!
+//! Report that a protocol round failed for the calling client and the parties identified as misbehaving.
+//!
+//! The blamed `parties` are the party indices used by the protocol and must be known to the session; for a signing session they are resolved to party signup numbers using the participants registered with `Session.participant`.
+//!
+//! A `sessionAbort` event is emitted to the other clients in the session with the `sessionId`, the `reportedBy` party number, the `round`, `parties` and `reason` from the blame and the `partyNumbers` for the blamed parties so honest parties can exclude them from a new session.
+//!
+//! Returns an empty response to the caller.
+//!
+//! ### Session.confirm
+//!
+//! * `group_id`: The `String` UUID for the group.
+//! * `session_id`: The `String` UUID for the session.
+//! * `number`: The `u16` party signup number.
+//! * `hash`: The `String` hash of the public key for the generated key share.
+//!
+//! Confirm the public key generated by a key generation session by reporting a hash of the public key; the key should not be used until every party has confirmed the same hash.
+//!
+//! When all the clients in a session have called this method the server will emit a `sessionConfirm` event to all the clients in the session with the `sessionId`, the `hashes` as an array of party number and hash pairs and whether the hashes were `confirmed` to be equal; clients should compare the hashes with their own public key rather than trust the `confirmed` flag.
+//!
+//! Returns an empty response to the caller.
+//!
+//! ### Session.resume
+//!
+//! * `group_id`: The `String` UUID for the group.
+//! * `session_id`: The `String` UUID for the session.
+//! * `number`: The `u16` party signup number.
+//!
+//! Resume a session after the calling client reconnects, for example when a party restores the state of a protocol in progress after a page reload.
+//!
+//! The client must reconnect with the same [identity](crate::identity) and join the group again before calling this method; the given `number` must belong to the caller.
+//!
+//! Returns the messages relayed to the caller during the session (broadcast messages from the other parties and peer to peer messages addressed to the caller) in the order they were sent so the client can deliver the messages it missed; protocols ignore the messages they have already received. Messages are kept by the server instance that relayed them for the latest two rounds of the protocol until the session is closed.
+//!
+//! ## Shutdown
+//!
+//! When the server begins a graceful shutdown it sends a `serverShutdown` event to all clients with the number of seconds it will wait for sessions in progress to finish; new groups and sessions are rejected with a `ShuttingDown` error. Once all sessions are closed (or the wait has elapsed) the server closes the connections.
+//!
+//! ## Errors
+//!
+//! Errors returned by the service methods carry a stable numeric `code`
+//! and the `data` field of the error is a JSON-encoded [ErrorData](ErrorData)
+//! object so that clients can branch on the kind of error without
+//! matching on the error `message`.
+//!
+//! The `kind` in the error data is the name of the [ErrorCode](ErrorCode)
+//! variant and the data may also include the `groupId`, `sessionId`,
+//! `partyNumber` and `connectionId` related to the error.
+//!
+//! When `closeConnection` is `true` the server will close the
+//! connection after sending the error.
+//!
+//! | Code     | Kind                       |
+//! |----------|----------------------------|
+//! | `-32001` | `PartiesTooSmall`          |
+//! | `-32002` | `ThresholdTooSmall`        |
+//! | `-32003` | `ThresholdRange`           |
+//! | `-32010` | `GroupFull`                |
+//! | `-32011` | `GroupDoesNotExist`        |
+//! | `-32012` | `BadConnection`            |
+//! | `-32020` | `SessionDoesNotExist`      |
+//! | `-32030` | `PartyDoesNotExist`        |
+//! | `-32031` | `BadParty`                 |
+//! | `-32032` | `BadPeerReceiver`          |
+//! | `-32033` | `BadRound`                 |
+//! | `-32040` | `ZeroPartyNumber`          |
+//! | `-32041` | `PartyNumberOutOfRange`    |
+//! | `-32042` | `PartyNumberAlreadyExists` |
+//! | `-32050` | `IncompatibleVersion`      |
+//! | `-32060` | `ShuttingDown`             |
+//! | `-32603` | `Internal`                 |
+//!
+//! Errors for malformed requests (such as invalid parameters) use the
+//! standard JSON-RPC error codes and do not include error data.
+//!
 use async_trait::async_trait;
-use json_rpc2::{futures::*, Error, Request, Response, Result, RpcError};
+use json_rpc2::{futures::*, Request, Response, RpcError};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::str::FromStr;
 use std::sync::Arc;
 use thiserror::Error;
 use tokio::sync::{Mutex, RwLock};
 use uuid::Uuid;
 
+use super::backplane::merge_group;
+use super::encoding::Encoding;
+use super::identity::ClientId;
 use super::server::{
-    Group, Notification, Parameters, Session, SessionKind, State,
+    Blame, Features, Group, Notification, Parameters, RelayedMessage,
+    ServerError, Session, SessionKind, State,
 };
 
 /// Error thrown by the JSON-RPC services.
@@ -144,7 +247,7 @@ pub enum ServiceError {
     #[error("group {0} does not exist")]
     GroupDoesNotExist(Uuid),
     /// Error generated when a session does not exist.
-    #[error("group {0} does not exist")]
+    #[error("session {0} does not exist")]
     SessionDoesNotExist(Uuid),
     /// Error generated when a party number does not exist.
     #[error("party {0} does not exist")]
@@ -156,15 +259,229 @@ pub enum ServiceError {
     /// does not exist.
     #[error("receiver {0} for peer to peer message does not exist")]
     BadPeerReceiver(u16),
+    /// Error generated when a message is for a round more than one
+    /// round ahead of the latest round relayed in the session.
+    #[error("message for round {0} is too far ahead of the session")]
+    BadRound(u16),
     /// Error generated when a client connection does not belong to
     /// the specified group.
     #[error("client {0} does not belong to the group {1}")]
-    BadConnection(usize, Uuid),
+    BadConnection(ClientId, Uuid),
+    /// Error generated when the protocol version of a client
+    /// is not supported.
+    #[error("protocol version {0} is not supported")]
+    IncompatibleVersion(u16),
+    /// Error generated when creating a group or session
+    /// while the server is shutting down.
+    #[error("server is shutting down")]
+    ShuttingDown,
+
+    /// Error generated by the server state.
+    #[error(transparent)]
+    Server(#[from] ServerError),
+
+    /// Error generated by the JSON-RPC library, typically
+    /// when the request parameters are invalid.
+    #[error(transparent)]
+    JsonRpc(#[from] json_rpc2::Error),
+}
+
+impl ServiceError {
+    /// Stable error code for this error.
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::PartiesTooSmall => ErrorCode::PartiesTooSmall,
+            Self::ThresholdTooSmall => ErrorCode::ThresholdTooSmall,
+            Self::ThresholdRange => ErrorCode::ThresholdRange,
+            Self::GroupFull(_) => ErrorCode::GroupFull,
+            Self::GroupDoesNotExist(_) => ErrorCode::GroupDoesNotExist,
+            Self::SessionDoesNotExist(_) => ErrorCode::SessionDoesNotExist,
+            Self::PartyDoesNotExist(_) => ErrorCode::PartyDoesNotExist,
+            Self::BadParty(_) => ErrorCode::BadParty,
+            Self::BadPeerReceiver(_) => ErrorCode::BadPeerReceiver,
+            Self::BadRound(_) => ErrorCode::BadRound,
+            Self::BadConnection(_, _) => ErrorCode::BadConnection,
+            Self::IncompatibleVersion(_) => ErrorCode::IncompatibleVersion,
+            Self::ShuttingDown => ErrorCode::ShuttingDown,
+            Self::Server(e) => e.code(),
+            Self::JsonRpc(_) => ErrorCode::Internal,
+        }
+    }
+
+    /// Machine-readable data for this error.
+    pub fn data(&self) -> ErrorData {
+        let mut data = ErrorData::new(self.code());
+        match self {
+            Self::GroupFull(group_id) => {
+                data.group_id = Some(*group_id);
+                data.close_connection = true;
+            }
+            Self::GroupDoesNotExist(group_id) => {
+                data.group_id = Some(*group_id);
+            }
+            Self::SessionDoesNotExist(session_id) => {
+                data.session_id = Some(*session_id);
+            }
+            Self::PartyDoesNotExist(party_number)
+            | Self::BadParty(party_number)
+            | Self::BadPeerReceiver(party_number) => {
+                data.party_number = Some(*party_number);
+            }
+            Self::BadConnection(client_id, group_id) => {
+                data.client_id = Some(*client_id);
+                data.group_id = Some(*group_id);
+            }
+            Self::IncompatibleVersion(_) => {
+                data.close_connection = true;
+            }
+            Self::Server(ServerError::PartyNumberAlreadyExists(session_id)) => {
+                data.session_id = Some(*session_id);
+            }
+            _ => {}
+        }
+        data
+    }
+}
+
+impl From<ServiceError> for RpcError {
+    fn from(error: ServiceError) -> Self {
+        let data = serde_json::to_string(&error.data()).ok();
+        let mut err = RpcError::new(error.to_string(), data);
+        err.code = error.code().into();
+        err
+    }
+}
+
+/// Result type for the JSON-RPC services.
+pub type Result<T> = std::result::Result<T, ServiceError>;
+
+/// Stable error codes for the `code` field of JSON-RPC errors.
+///
+/// Variants are serialized using the variant name which is
+/// the `kind` of the [error data](ErrorData).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ErrorCode {
+    /// Parties parameter is too small.
+    PartiesTooSmall = -32001,
+    /// Threshold parameter is too small.
+    ThresholdTooSmall = -32002,
+    /// Threshold parameter is out of range.
+    ThresholdRange = -32003,
+    /// Group is full.
+    GroupFull = -32010,
+    /// Group does not exist.
+    GroupDoesNotExist = -32011,
+    /// Connection does not belong to the group.
+    BadConnection = -32012,
+    /// Session does not exist.
+    SessionDoesNotExist = -32020,
+    /// Party number does not exist.
+    PartyDoesNotExist = -32030,
+    /// Party number does not belong to the caller.
+    BadParty = -32031,
+    /// Receiver for a peer to peer message does not exist.
+    BadPeerReceiver = -32032,
+    /// Message round is too far ahead of the session.
+    BadRound = -32033,
+    /// Party number is zero.
+    ZeroPartyNumber = -32040,
+    /// Party number is out of range.
+    PartyNumberOutOfRange = -32041,
+    /// Party number has already been allocated.
+    PartyNumberAlreadyExists = -32042,
+    /// Protocol version is not supported.
+    IncompatibleVersion = -32050,
+    /// Server is shutting down.
+    ShuttingDown = -32060,
+    /// Internal error.
+    Internal = -32603,
+}
+
+impl From<ErrorCode> for isize {
+    fn from(value: ErrorCode) -> Self {
+        value as isize
+    }
+}
+
+impl TryFrom<isize> for ErrorCode {
+    type Error = isize;
+
+    fn try_from(value: isize) -> std::result::Result<Self, Self::Error> {
+        Ok(match value {
+            -32001 => Self::PartiesTooSmall,
+            -32002 => Self::ThresholdTooSmall,
+            -32003 => Self::ThresholdRange,
+            -32010 => Self::GroupFull,
+            -32011 => Self::GroupDoesNotExist,
+            -32012 => Self::BadConnection,
+            -32020 => Self::SessionDoesNotExist,
+            -32030 => Self::PartyDoesNotExist,
+            -32031 => Self::BadParty,
+            -32032 => Self::BadPeerReceiver,
+            -32033 => Self::BadRound,
+            -32040 => Self::ZeroPartyNumber,
+            -32041 => Self::PartyNumberOutOfRange,
+            -32042 => Self::PartyNumberAlreadyExists,
+            -32050 => Self::IncompatibleVersion,
+            -32060 => Self::ShuttingDown,
+            -32603 => Self::Internal,
+            _ => return Err(value),
+        })
+    }
+}
+
+/// Data sent in the `data` field of JSON-RPC errors.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ErrorData {
+    /// Kind of error.
+    pub kind: ErrorCode,
+    /// Indicates the server will close the connection.
+    #[serde(default)]
+    pub close_connection: bool,
+    /// Group identifier related to the error.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group_id: Option<Uuid>,
+    /// Session identifier related to the error.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<Uuid>,
+    /// Party number related to the error.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub party_number: Option<u16>,
+    /// Client identity related to the error.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<ClientId>,
 }
 
-/// Error data indicating the connection should be closed.
-pub const CLOSE_CONNECTION: &str = "close-connection";
+impl ErrorData {
+    /// Create error data for a kind of error.
+    pub fn new(kind: ErrorCode) -> Self {
+        Self {
+            kind,
+            close_connection: false,
+            group_id: None,
+            session_id: None,
+            party_number: None,
+            client_id: None,
+        }
+    }
+}
+
+impl FromStr for ErrorData {
+    type Err = serde_json::Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        serde_json::from_str(s)
+    }
+}
+
+/// Current version of the protocol.
+pub const PROTOCOL_VERSION: u16 = 1;
+/// Oldest protocol version supported by the server.
+pub const MIN_PROTOCOL_VERSION: u16 = 1;
 
+/// Method to negotiate the protocol version.
+pub const SERVER_HELLO: &str = "Server.hello";
 /// Method to create a group.
 pub const GROUP_CREATE: &str = "Group.create";
 /// Method to join a group.
@@ -183,6 +500,29 @@ pub const SESSION_PARTICIPANT: &str = "Session.participant";
 pub const SESSION_MESSAGE: &str = "Session.message";
 /// Method to indicate a session is finished.
 pub const SESSION_FINISH: &str = "Session.finish";
+/// Method to report blame when a protocol round fails.
+pub const SESSION_ABORT: &str = "Session.abort";
+/// Method to confirm the public key generated by a session.
+pub const SESSION_CONFIRM: &str = "Session.confirm";
+/// Method to resume a session after reconnecting.
+pub const SESSION_RESUME: &str = "Session.resume";
+
+/// Methods supported by the server.
+pub const METHODS: &[&str] = &[
+    SERVER_HELLO,
+    GROUP_CREATE,
+    GROUP_JOIN,
+    SESSION_CREATE,
+    SESSION_JOIN,
+    SESSION_SIGNUP,
+    SESSION_LOAD,
+    SESSION_PARTICIPANT,
+    SESSION_MESSAGE,
+    SESSION_FINISH,
+    SESSION_ABORT,
+    SESSION_CONFIRM,
+    SESSION_RESUME,
+];
 
 /// Notification sent when a session has been created.
 ///
@@ -200,6 +540,30 @@ pub const SESSION_MESSAGE_EVENT: &str = "sessionMessage";
 /// Notification sent when a session has been marked as finished
 /// by all participating clients.
 pub const SESSION_CLOSED_EVENT: &str = "sessionClosed";
+/// Notification sent when a party reports blame for a failed round.
+pub const SESSION_ABORT_EVENT: &str = "sessionAbort";
+/// Notification sent when all parties have confirmed the public key
+/// generated by a session.
+pub const SESSION_CONFIRM_EVENT: &str = "sessionConfirm";
+/// Notification sent to the other members of a group when a client
+/// joins the group.
+pub const GROUP_JOIN_EVENT: &str = "groupJoin";
+/// Notification sent to the remaining members of a group when a
+/// client leaves the group.
+pub const GROUP_LEAVE_EVENT: &str = "groupLeave";
+/// Notification sent to the other members of a group when a client
+/// stops responding to the server heartbeat.
+pub const PARTY_OFFLINE_EVENT: &str = "partyOffline";
+/// Notification sent to the other members of a group when an offline
+/// client responds to the server heartbeat again.
+pub const PARTY_ONLINE_EVENT: &str = "partyOnline";
+/// Notification sent to all clients when the server begins
+/// a graceful shutdown.
+pub const SERVER_SHUTDOWN_EVENT: &str = "serverShutdown";
+/// Notification sent when an administrator removes a group.
+pub const GROUP_REMOVED_EVENT: &str = "groupRemoved";
+/// Notification sent when an administrator removes a session.
+pub const SESSION_REMOVED_EVENT: &str = "sessionRemoved";
 
 type GroupCreateParams = (String, Parameters);
 type SessionCreateParams = (Uuid, SessionKind, Option<Value>);
@@ -209,6 +573,67 @@ type SessionLoadParams = (Uuid, Uuid, SessionKind, u16);
 type SessionParticipantParams = (Uuid, Uuid, u16, u16);
 type SessionMessageParams = (Uuid, Uuid, SessionKind, Message);
 type SessionFinishParams = (Uuid, Uuid, u16);
+type SessionAbortParams = (Uuid, Uuid, u16, Blame);
+type SessionConfirmParams = (Uuid, Uuid, u16, String);
+type SessionResumeParams = (Uuid, Uuid, u16);
+
+/// Response to the `Server.hello` method.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerHello {
+    /// Protocol version of the server.
+    pub version: u16,
+    /// Oldest protocol version supported by the server.
+    pub min_version: u16,
+    /// Methods supported by the server.
+    pub methods: Vec<String>,
+    /// Features enabled on the server.
+    pub features: Features,
+    /// Encodings supported for websocket messages.
+    pub encodings: Vec<Encoding>,
+    /// Identity of the client.
+    pub client_id: ClientId,
+}
+
+/// Payload for events sent when a member joins or leaves a group.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct GroupMember {
+    /// The group identifier.
+    pub group_id: Uuid,
+    /// The client identity for the member.
+    pub client_id: ClientId,
+    /// Number of members in the group.
+    pub members: usize,
+}
+
+/// Payload for the event sent when a party aborts a session.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SessionAbort {
+    /// The session identifier.
+    pub session_id: Uuid,
+    /// Party number of the party that reported the blame.
+    pub reported_by: u16,
+    /// Blame reported by the party.
+    #[serde(flatten)]
+    pub blame: Blame,
+    /// Party signup numbers for the blamed parties.
+    pub party_numbers: Vec<u16>,
+}
+
+/// Payload for the event sent when all parties have confirmed
+/// the public key generated by a session.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SessionConfirm {
+    /// The session identifier.
+    pub session_id: Uuid,
+    /// Party number and public key hash for each party.
+    pub hashes: Vec<(u16, String)>,
+    /// Whether every party reported the same hash.
+    pub confirmed: bool,
+}
 
 // Mimics the `Msg` struct
 // from `round-based` but doesn't care
@@ -236,78 +661,160 @@ pub struct ServiceHandler;
 
 #[async_trait]
 impl Service for ServiceHandler {
-    type Data = (usize, Arc<RwLock<State>>, Arc<Mutex<Option<Notification>>>);
+    type Data = (
+        ClientId,
+        Arc<RwLock<State>>,
+        Arc<Mutex<Option<Notification>>>,
+    );
 
     async fn handle(
         &self,
         req: &Request,
         ctx: &Self::Data,
+    ) -> json_rpc2::Result<Option<Response>> {
+        match self.dispatch(req, ctx).await {
+            Ok(response) => Ok(response),
+            // Keep the standard error codes for malformed requests
+            Err(ServiceError::JsonRpc(e)) => Err(e),
+            Err(e) => {
+                let err: RpcError = e.into();
+                Ok(Some((req, err).into()))
+            }
+        }
+    }
+}
+
+impl ServiceHandler {
+    async fn dispatch(
+        &self,
+        req: &Request,
+        ctx: &<Self as Service>::Data,
     ) -> Result<Option<Response>> {
         let response = match req.method() {
+            SERVER_HELLO => {
+                let (client_id, state, _) = ctx;
+                let version: u16 = req.deserialize()?;
+                if !(MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION).contains(&version)
+                {
+                    return Err(ServiceError::IncompatibleVersion(version));
+                }
+                let reader = state.read().await;
+                let hello = ServerHello {
+                    version: PROTOCOL_VERSION,
+                    min_version: MIN_PROTOCOL_VERSION,
+                    methods: METHODS.iter().map(|m| m.to_string()).collect(),
+                    features: reader.features.clone(),
+                    encodings: Encoding::ALL.to_vec(),
+                    client_id: *client_id,
+                };
+                let res = serde_json::to_value(&hello).unwrap();
+                Some((req, res).into())
+            }
             GROUP_CREATE => {
-                let (conn_id, state, _) = ctx;
+                let (client_id, state, _) = ctx;
                 let params: GroupCreateParams = req.deserialize()?;
                 let (label, parameters) = params;
 
                 // If parties is less than two then may as well
                 // use a standard single-party ECDSA private key
                 if parameters.parties <= 1 {
-                    return Err(Error::from(Box::from(
-                        ServiceError::PartiesTooSmall,
-                    )));
+                    return Err(ServiceError::PartiesTooSmall);
                 // If threshold is zero then it only
                 // takes a single party to sign a request which
                 // defeats the point of MPC
                 } else if parameters.threshold == 0 {
-                    return Err(Error::from(Box::from(
-                        ServiceError::ThresholdTooSmall,
-                    )));
+                    return Err(ServiceError::ThresholdTooSmall);
                 // Threshold must be in range `(t + 1) <= n`
                 } else if parameters.threshold >= parameters.parties {
-                    return Err(Error::from(Box::from(
-                        ServiceError::ThresholdRange,
-                    )));
+                    return Err(ServiceError::ThresholdRange);
                 }
 
                 let group =
-                    Group::new(*conn_id, parameters.clone(), label.clone());
+                    Group::new(*client_id, parameters.clone(), label.clone());
                 let res = serde_json::to_value(group.uuid).unwrap();
                 let mut writer = state.write().await;
+                if writer.shutting_down {
+                    return Err(ServiceError::ShuttingDown);
+                }
+                writer.touch(group.uuid);
                 writer.groups.insert(group.uuid, group);
                 Some((req, res).into())
             }
             GROUP_JOIN => {
-                let (conn_id, state, _) = ctx;
+                let (client_id, state, notification) = ctx;
                 let group_id: Uuid = req.deserialize()?;
+
+                // Group may have been created on another server instance
+                // so load it before taking the lock on the server state
+                let cluster = {
+                    let reader = state.read().await;
+                    if reader.groups.contains_key(&group_id) {
+                        None
+                    } else {
+                        reader.cluster.clone()
+                    }
+                };
+                let snapshot = if let Some(cluster) = cluster {
+                    cluster.load_group(&group_id).await?
+                } else {
+                    None
+                };
+
                 let mut writer = state.write().await;
-                if let Some(group) = writer.groups.get_mut(&group_id) {
-                    if group.clients.len() == group.params.parties as usize {
-                        let error = ServiceError::GroupFull(group_id);
-                        let err = RpcError::new(
-                            error.to_string(),
-                            Some(CLOSE_CONNECTION.to_string()),
-                        );
-                        Some((req, err).into())
+                if let Some(snapshot) = snapshot {
+                    if let Some(group) = writer.groups.get_mut(&group_id) {
+                        merge_group(group, snapshot);
                     } else {
-                        if !group.clients.iter().any(|c| c == conn_id) {
-                            group.clients.push(*conn_id);
+                        writer.groups.insert(group_id, snapshot.into());
+                    }
+                }
+
+                writer.touch(group_id);
+                if let Some(group) = writer.groups.get_mut(&group_id) {
+                    // Rejoining under the same identity (for example
+                    // after reconnecting) is a no-op even when the
+                    // group is full
+                    if !group.clients.iter().any(|c| c == client_id) {
+                        if group.clients.len() == group.params.parties as usize
+                        {
+                            return Err(ServiceError::GroupFull(group_id));
                         }
-                        let res = serde_json::to_value(group).unwrap();
-                        Some((req, res).into())
+                        group.clients.push(*client_id);
+
+                        // Notify everyone else in the group
+                        let member = GroupMember {
+                            group_id,
+                            client_id: *client_id,
+                            members: group.clients.len(),
+                        };
+                        let value =
+                            serde_json::to_value((GROUP_JOIN_EVENT, member))
+                                .unwrap();
+                        let ctx = Notification::Group {
+                            group_id,
+                            filter: Some(vec![*client_id]),
+                            response: value.into(),
+                        };
+                        let mut writer = notification.lock().await;
+                        *writer = Some(ctx);
                     }
+                    let res = serde_json::to_value(group).unwrap();
+                    Some((req, res).into())
                 } else {
-                    return Err(Error::from(Box::from(
-                        ServiceError::GroupDoesNotExist(group_id),
-                    )));
+                    return Err(ServiceError::GroupDoesNotExist(group_id));
                 }
             }
             SESSION_CREATE => {
-                let (conn_id, state, notification) = ctx;
+                let (client_id, state, notification) = ctx;
                 let params: SessionCreateParams = req.deserialize()?;
                 let (group_id, kind, value) = params;
                 let mut writer = state.write().await;
+                if writer.shutting_down {
+                    return Err(ServiceError::ShuttingDown);
+                }
+                writer.touch(group_id);
                 let group =
-                    get_group_mut(conn_id, &group_id, &mut writer.groups)?;
+                    get_group_mut(client_id, &group_id, &mut writer.groups)?;
                 let session = Session::from((kind.clone(), value));
                 let key = session.uuid;
                 group.sessions.insert(key, session.clone());
@@ -321,7 +828,7 @@ impl Service for ServiceHandler {
                     // Notify everyone else in the group a session was created
                     let ctx = Notification::Group {
                         group_id,
-                        filter: Some(vec![*conn_id]),
+                        filter: Some(vec![*client_id]),
                         response,
                     };
                     let mut writer = notification.lock().await;
@@ -332,41 +839,87 @@ impl Service for ServiceHandler {
                 Some((req, res).into())
             }
             SESSION_JOIN => {
-                let (conn_id, state, _) = ctx;
+                let (client_id, state, _) = ctx;
                 let params: SessionJoinParams = req.deserialize()?;
                 let (group_id, session_id, _kind) = params;
 
                 let mut writer = state.write().await;
                 let group =
-                    get_group_mut(conn_id, &group_id, &mut writer.groups)?;
+                    get_group_mut(client_id, &group_id, &mut writer.groups)?;
                 if let Some(session) = group.sessions.get_mut(&session_id) {
                     let res = serde_json::to_value(&session).unwrap();
                     Some((req, res).into())
                 } else {
-                    return Err(Error::from(Box::from(
-                        ServiceError::SessionDoesNotExist(session_id),
-                    )));
+                    return Err(ServiceError::SessionDoesNotExist(session_id));
                 }
             }
             SESSION_SIGNUP => {
-                let (conn_id, state, notification) = ctx;
+                let (client_id, state, notification) = ctx;
                 let params: SessionSignupParams = req.deserialize()?;
                 let (group_id, session_id, kind) = params;
 
+                let cluster = {
+                    let mut writer = state.write().await;
+                    writer.touch(group_id);
+                    let cluster = writer.cluster.clone();
+                    let group = get_group_mut(
+                        client_id,
+                        &group_id,
+                        &mut writer.groups,
+                    )?;
+                    let session = group
+                        .sessions
+                        .get(&session_id)
+                        .ok_or(ServiceError::SessionDoesNotExist(session_id))?;
+
+                    // A client that retries a signup keeps its party number
+                    if let Some((party_number, _)) = session
+                        .party_signups
+                        .iter()
+                        .find(|(_, c)| c == client_id)
+                    {
+                        let res = serde_json::to_value(party_number).unwrap();
+                        return Ok(Some((req, res).into()));
+                    }
+                    cluster
+                };
+
+                // Party numbers must be allocated from a shared
+                // counter when clients are connected to different
+                // server instances
+                let counted = if let Some(cluster) = &cluster {
+                    Some(cluster.count(&session_id, "signup").await?)
+                } else {
+                    None
+                };
+
                 let mut writer = state.write().await;
                 let group =
-                    get_group_mut(conn_id, &group_id, &mut writer.groups)?;
+                    get_group_mut(client_id, &group_id, &mut writer.groups)?;
                 if let Some(session) = group.sessions.get_mut(&session_id) {
-                    let party_number = session.signup(*conn_id);
+                    let (party_number, signups) = if let Some(signups) = counted
+                    {
+                        if signups > group.params.parties as usize {
+                            return Err(
+                                ServerError::PartyNumberOutOfRange.into()
+                            );
+                        }
+                        let party_number = signups as u16;
+                        session.party_signups.push((party_number, *client_id));
+                        (party_number, signups)
+                    } else {
+                        let party_number = session.signup(*client_id);
+                        (party_number, session.party_signups.len())
+                    };
 
-                    tracing::info!(party_number, "session signup {}", conn_id);
+                    tracing::info!(
+                        party_number,
+                        "session signup {}",
+                        client_id
+                    );
 
                     // Enough parties are signed up to the session
-                    if threshold(
-                        &kind,
-                        &group.params,
-                        session.party_signups.len(),
-                    ) {
+                    if threshold(&kind, &group.params, signups) {
                         let value = serde_json::to_value((
                             SESSION_SIGNUP_EVENT,
                             &session_id,
@@ -387,175 +940,439 @@ impl Service for ServiceHandler {
                     let res = serde_json::to_value(party_number).unwrap();
                     Some((req, res).into())
                 } else {
-                    return Err(Error::from(Box::from(
-                        ServiceError::SessionDoesNotExist(session_id),
-                    )));
+                    return Err(ServiceError::SessionDoesNotExist(session_id));
                 }
             }
             // Load an existing party signup into the session
             // this is used to support loading existing key shares.
             SESSION_LOAD => {
-                let (conn_id, state, notification) = ctx;
+                let (client_id, state, notification) = ctx;
                 let params: SessionLoadParams = req.deserialize()?;
                 let (group_id, session_id, kind, party_number) = params;
 
-                let mut writer = state.write().await;
-                let group =
-                    get_group_mut(conn_id, &group_id, &mut writer.groups)?;
-                if let Some(session) = group.sessions.get_mut(&session_id) {
-                    let res = serde_json::to_value(party_number).unwrap();
-                    match session.load(&group.params, *conn_id, party_number) {
-                        Ok(_) => {
-                            // Enough parties are loaded into the session
-                            if threshold(
-                                &kind,
-                                &group.params,
-                                session.party_signups.len(),
-                            ) {
-                                let value = serde_json::to_value((
-                                    SESSION_LOAD_EVENT,
-                                    &session_id,
-                                ))
-                                .unwrap();
-                                let response: Response = value.into();
-                                let ctx = Notification::Session {
-                                    group_id,
-                                    session_id,
-                                    filter: None,
-                                    response,
-                                };
-                                let mut writer = notification.lock().await;
-                                *writer = Some(ctx);
-                            }
-
-                            Some((req, res).into())
-                        }
-                        Err(err) => return Err(Error::from(Box::from(err))),
-                    }
+                let (cluster, params, loaded) = {
+                    let mut writer = state.write().await;
+                    writer.touch(group_id);
+                    let cluster = writer.cluster.clone();
+                    let group = get_group_mut(
+                        client_id,
+                        &group_id,
+                        &mut writer.groups,
+                    )?;
+                    let session = group
+                        .sessions
+                        .get_mut(&session_id)
+                        .ok_or(ServiceError::SessionDoesNotExist(session_id))?;
+                    session.load(&group.params, *client_id, party_number)?;
+                    (cluster, group.params.clone(), session.party_signups.len())
+                };
+
+                let loaded = if let Some(cluster) = &cluster {
+                    cluster.count(&session_id, "load").await?
                 } else {
-                    return Err(Error::from(Box::from(
-                        ServiceError::SessionDoesNotExist(session_id),
-                    )));
+                    loaded
+                };
+
+                // Enough parties are loaded into the session
+                if threshold(&kind, &params, loaded) {
+                    let value =
+                        serde_json::to_value((SESSION_LOAD_EVENT, &session_id))
+                            .unwrap();
+                    let response: Response = value.into();
+                    let ctx = Notification::Session {
+                        group_id,
+                        session_id,
+                        filter: None,
+                        response,
+                    };
+                    let mut writer = notification.lock().await;
+                    *writer = Some(ctx);
                 }
+
+                let res = serde_json::to_value(party_number).unwrap();
+                Some((req, res).into())
             }
             // Register a participant lookup for a signing session.
             //
             // This allows clients to map the receiver party index to a
             // server issued party number which in turn allows the server
-            // to correctly resolve the connection identifier that we need
+            // to correctly resolve the client identity that we need
             // to relay for peer to peer rounds.
             SESSION_PARTICIPANT => {
-                let (conn_id, state, _) = ctx;
+                let (client_id, state, _) = ctx;
                 let params: SessionParticipantParams = req.deserialize()?;
                 let (group_id, session_id, party_index, party_number) = params;
 
                 let mut writer = state.write().await;
+                writer.touch(group_id);
                 let group =
-                    get_group_mut(conn_id, &group_id, &mut writer.groups)?;
+                    get_group_mut(client_id, &group_id, &mut writer.groups)?;
                 if let Some(session) = group.sessions.get_mut(&session_id) {
                     session.participants.insert(party_index, party_number);
                     Some(req.into())
                 } else {
-                    return Err(Error::from(Box::from(
-                        ServiceError::SessionDoesNotExist(session_id),
-                    )));
+                    return Err(ServiceError::SessionDoesNotExist(session_id));
                 }
             }
             // Mark the session as finished for a party.
             SESSION_FINISH => {
-                let (conn_id, state, notification) = ctx;
+                let (client_id, state, notification) = ctx;
                 let params: SessionFinishParams = req.deserialize()?;
                 let (group_id, session_id, party_number) = params;
 
+                let (cluster, newly_finished, signups, completed) = {
+                    let mut writer = state.write().await;
+                    writer.touch(group_id);
+                    let cluster = writer.cluster.clone();
+                    let group = get_group_mut(
+                        client_id,
+                        &group_id,
+                        &mut writer.groups,
+                    )?;
+                    let session = group
+                        .sessions
+                        .get_mut(&session_id)
+                        .ok_or(ServiceError::SessionDoesNotExist(session_id))?;
+                    let existing_signup = session
+                        .party_signups
+                        .iter()
+                        .find(|(s, _)| s == &party_number);
+
+                    match existing_signup {
+                        // The party number must belong to the caller
+                        // which we check by comparing client identities
+                        Some((_, conn)) if conn != client_id => {
+                            return Err(ServiceError::BadParty(party_number));
+                        }
+                        Some(_) => {}
+                        None => {
+                            return Err(ServiceError::PartyDoesNotExist(
+                                party_number,
+                            ));
+                        }
+                    }
+
+                    let newly_finished = session.finished.insert(party_number);
+
+                    let mut signups = session
+                        .party_signups
+                        .iter()
+                        .map(|(n, _)| *n)
+                        .collect::<Vec<u16>>();
+                    let mut completed =
+                        session.finished.iter().cloned().collect::<Vec<u16>>();
+
+                    signups.sort();
+                    completed.sort();
+                    (cluster, newly_finished, signups, completed)
+                };
+
+                // Parties may finish on different server instances
+                // so count them using a shared counter
+                let closed = if let Some(cluster) = &cluster {
+                    newly_finished
+                        && cluster.count(&session_id, "finish").await?
+                            == signups.len()
+                } else {
+                    signups == completed
+                };
+
+                if closed {
+                    let reader = state.read().await;
+                    if let Some(session) = reader
+                        .groups
+                        .get(&group_id)
+                        .and_then(|group| group.sessions.get(&session_id))
+                    {
+                        session.messages.clear();
+                    }
+                    drop(reader);
+
+                    if let Some(cluster) = &cluster {
+                        if let Err(e) =
+                            cluster.remove_counters(&session_id).await
+                        {
+                            tracing::warn!(?e, "remove session counters");
+                        }
+                    }
+
+                    let value =
+                        serde_json::to_value((SESSION_CLOSED_EVENT, signups))
+                            .unwrap();
+                    let response: Response = value.into();
+
+                    let ctx = Notification::Session {
+                        group_id,
+                        session_id,
+                        filter: None,
+                        response,
+                    };
+
+                    let mut writer = notification.lock().await;
+                    *writer = Some(ctx);
+                }
+
+                Some(req.into())
+            }
+            // Report blame for a failed round to the session.
+            SESSION_ABORT => {
+                let (client_id, state, notification) = ctx;
+                let params: SessionAbortParams = req.deserialize()?;
+                let (group_id, session_id, party_number, blame) = params;
+
                 let mut writer = state.write().await;
+                writer.touch(group_id);
                 let group =
-                    get_group_mut(conn_id, &group_id, &mut writer.groups)?;
+                    get_group_mut(client_id, &group_id, &mut writer.groups)?;
                 if let Some(session) = group.sessions.get_mut(&session_id) {
                     let existing_signup = session
                         .party_signups
                         .iter()
                         .find(|(s, _)| s == &party_number);
 
-                    if let Some((_, conn)) = existing_signup {
+                    match existing_signup {
                         // The party number must belong to the caller
-                        // which we check by comparing connection identifiers
-                        if conn != conn_id {
-                            return Err(Error::from(Box::from(
-                                ServiceError::BadParty(party_number),
-                            )));
+                        Some((_, conn)) if conn != client_id => {
+                            return Err(ServiceError::BadParty(party_number));
+                        }
+                        Some(_) => {}
+                        None => {
+                            return Err(ServiceError::PartyDoesNotExist(
+                                party_number,
+                            ));
                         }
+                    }
+
+                    let party_numbers = blame
+                        .parties
+                        .iter()
+                        .map(|index| {
+                            session
+                                .resolve(*index)
+                                .map(|(number, _)| *number)
+                                .ok_or(ServiceError::PartyDoesNotExist(*index))
+                        })
+                        .collect::<Result<Vec<u16>>>()?;
+
+                    tracing::warn!(
+                        party_number,
+                        round = blame.round,
+                        blamed = ?party_numbers,
+                        "session abort {}",
+                        session_id
+                    );
+
+                    session.aborted.insert(party_number, blame.clone());
 
-                        session.finished.insert(party_number);
-
-                        let mut signups = session
-                            .party_signups
-                            .iter()
-                            .map(|(n, _)| *n)
-                            .collect::<Vec<u16>>();
-                        let mut completed = session
-                            .finished
-                            .iter()
-                            .cloned()
-                            .collect::<Vec<u16>>();
-
-                        signups.sort();
-                        completed.sort();
-
-                        if signups == completed {
-                            let value = serde_json::to_value((
-                                SESSION_CLOSED_EVENT,
-                                completed,
-                            ))
+                    let abort = SessionAbort {
+                        session_id,
+                        reported_by: party_number,
+                        blame,
+                        party_numbers,
+                    };
+                    let value =
+                        serde_json::to_value((SESSION_ABORT_EVENT, abort))
                             .unwrap();
-                            let response: Response = value.into();
+                    let response: Response = value.into();
+                    let ctx = Notification::Session {
+                        group_id,
+                        session_id,
+                        filter: Some(vec![*client_id]),
+                        response,
+                    };
+                    let mut writer = notification.lock().await;
+                    *writer = Some(ctx);
 
-                            let ctx = Notification::Session {
-                                group_id,
-                                session_id,
-                                filter: None,
-                                response,
-                            };
+                    Some(req.into())
+                } else {
+                    return Err(ServiceError::SessionDoesNotExist(session_id));
+                }
+            }
+            // Confirm the public key generated by a session.
+            SESSION_CONFIRM => {
+                let (client_id, state, notification) = ctx;
+                let params: SessionConfirmParams = req.deserialize()?;
+                let (group_id, session_id, party_number, hash) = params;
 
-                            let mut writer = notification.lock().await;
-                            *writer = Some(ctx);
+                let (cluster, newly_confirmed, signups, confirmations) = {
+                    let mut writer = state.write().await;
+                    writer.touch(group_id);
+                    let cluster = writer.cluster.clone();
+                    let group = get_group_mut(
+                        client_id,
+                        &group_id,
+                        &mut writer.groups,
+                    )?;
+                    let session = group
+                        .sessions
+                        .get_mut(&session_id)
+                        .ok_or(ServiceError::SessionDoesNotExist(session_id))?;
+                    let existing_signup = session
+                        .party_signups
+                        .iter()
+                        .find(|(s, _)| s == &party_number);
+
+                    match existing_signup {
+                        // The party number must belong to the caller
+                        Some((_, conn)) if conn != client_id => {
+                            return Err(ServiceError::BadParty(party_number));
                         }
+                        Some(_) => {}
+                        None => {
+                            return Err(ServiceError::PartyDoesNotExist(
+                                party_number,
+                            ));
+                        }
+                    }
 
-                        Some(req.into())
-                    } else {
-                        return Err(Error::from(Box::from(
-                            ServiceError::PartyDoesNotExist(party_number),
-                        )));
+                    let newly_confirmed = session
+                        .confirmations
+                        .insert(party_number, hash)
+                        .is_none();
+                    (
+                        cluster,
+                        newly_confirmed,
+                        session.party_signups.len(),
+                        session.confirmations.len(),
+                    )
+                };
+
+                // Parties may confirm on different server instances
+                // so count them using a shared counter
+                let complete = if let Some(cluster) = &cluster {
+                    newly_confirmed
+                        && cluster.count(&session_id, "confirm").await?
+                            == signups
+                } else {
+                    newly_confirmed && confirmations == signups
+                };
+
+                if complete {
+                    let reader = state.read().await;
+                    let (_, session) = get_group_session(
+                        client_id,
+                        &group_id,
+                        &session_id,
+                        &reader.groups,
+                    )?;
+                    let mut hashes = session
+                        .confirmations
+                        .iter()
+                        .map(|(n, h)| (*n, h.clone()))
+                        .collect::<Vec<_>>();
+                    hashes.sort();
+                    let confirmed = hashes.windows(2).all(|w| w[0].1 == w[1].1);
+                    if !confirmed {
+                        tracing::warn!(
+                            ?hashes,
+                            "session public key mismatch {}",
+                            session_id
+                        );
                     }
+
+                    let confirm = SessionConfirm {
+                        session_id,
+                        hashes,
+                        confirmed,
+                    };
+                    let value =
+                        serde_json::to_value((SESSION_CONFIRM_EVENT, confirm))
+                            .unwrap();
+                    let response: Response = value.into();
+                    let ctx = Notification::Session {
+                        group_id,
+                        session_id,
+                        filter: None,
+                        response,
+                    };
+                    let mut writer = notification.lock().await;
+                    *writer = Some(ctx);
+                }
+
+                Some(req.into())
+            }
+            // Send the messages relayed to a party that reconnects.
+            SESSION_RESUME => {
+                let (client_id, state, _) = ctx;
+                let params: SessionResumeParams = req.deserialize()?;
+                let (group_id, session_id, party_number) = params;
+
+                let mut writer = state.write().await;
+                writer.touch(group_id);
+                let group =
+                    get_group_mut(client_id, &group_id, &mut writer.groups)?;
+                if let Some(session) = group.sessions.get(&session_id) {
+                    let existing_signup = session
+                        .party_signups
+                        .iter()
+                        .find(|(s, _)| s == &party_number);
+
+                    match existing_signup {
+                        // The party number must belong to the caller
+                        Some((_, conn)) if conn != client_id => {
+                            return Err(ServiceError::BadParty(party_number));
+                        }
+                        Some(_) => {}
+                        None => {
+                            return Err(ServiceError::PartyDoesNotExist(
+                                party_number,
+                            ));
+                        }
+                    }
+
+                    let messages = session.messages.delivered_to(client_id);
+
+                    tracing::info!(
+                        party_number,
+                        messages = messages.len(),
+                        "session resume {}",
+                        session_id
+                    );
+
+                    let res = serde_json::to_value(messages).unwrap();
+                    Some((req, res).into())
                 } else {
-                    return Err(Error::from(Box::from(
-                        ServiceError::SessionDoesNotExist(session_id),
-                    )));
+                    return Err(ServiceError::SessionDoesNotExist(session_id));
                 }
             }
             SESSION_MESSAGE => {
-                let (conn_id, state, notification) = ctx;
+                let (client_id, state, notification) = ctx;
                 let params: SessionMessageParams = req.deserialize()?;
                 let (group_id, session_id, _kind, msg) = params;
 
                 let reader = state.read().await;
 
                 // Check we have valid group / session
-                let (_group, session) = get_group_session(
-                    conn_id,
+                let (_, session) = get_group_session(
+                    client_id,
                     &group_id,
                     &session_id,
                     &reader.groups,
                 )?;
 
+                let message = serde_json::to_value(&msg).unwrap();
+
                 // Send direct to peer
                 if let Some(receiver) = &msg.receiver {
-                    if let Some(s) = session.resolve(*receiver) {
-                        let value =
-                            serde_json::to_value((SESSION_MESSAGE_EVENT, msg))
-                                .unwrap();
+                    if let Some(&(_, peer)) = session.resolve(*receiver) {
+                        // Keep the message for the receiver to resume
+                        if !session.messages.push(RelayedMessage {
+                            round: msg.round,
+                            sender: *client_id,
+                            receiver: Some(peer),
+                            message: message.clone(),
+                        }) {
+                            return Err(ServiceError::BadRound(msg.round));
+                        }
+
+                        let value = serde_json::to_value((
+                            SESSION_MESSAGE_EVENT,
+                            message,
+                        ))
+                        .unwrap();
 
                         let response: Response = value.into();
-                        let message = (s.1, response);
+                        let message = (peer, response);
 
                         let ctx = Notification::Relay {
                             messages: vec![message],
@@ -564,21 +1381,28 @@ impl Service for ServiceHandler {
                         let mut writer = notification.lock().await;
                         *writer = Some(ctx);
                     } else {
-                        return Err(Error::from(Box::from(
-                            ServiceError::BadPeerReceiver(*receiver),
-                        )));
+                        return Err(ServiceError::BadPeerReceiver(*receiver));
                     }
                 // Handle broadcast round
                 } else {
+                    if !session.messages.push(RelayedMessage {
+                        round: msg.round,
+                        sender: *client_id,
+                        receiver: None,
+                        message: message.clone(),
+                    }) {
+                        return Err(ServiceError::BadRound(msg.round));
+                    }
+
                     let value =
-                        serde_json::to_value((SESSION_MESSAGE_EVENT, msg))
+                        serde_json::to_value((SESSION_MESSAGE_EVENT, message))
                             .unwrap();
                     let response: Response = value.clone().into();
 
                     let ctx = Notification::Session {
                         group_id,
                         session_id,
-                        filter: Some(vec![*conn_id]),
+                        filter: Some(vec![*client_id]),
                         response,
                     };
 
@@ -595,61 +1419,51 @@ impl Service for ServiceHandler {
     }
 }
 
-fn get_group_mut<'a>(
-    conn_id: &usize,
-    group_id: &Uuid,
-    groups: &'a mut HashMap<Uuid, Group>,
-) -> Result<&'a mut Group> {
-    if let Some(group) = groups.get_mut(group_id) {
-        // Verify connection is part of the group clients
-        if group.clients.iter().any(|c| c == conn_id) {
-            Ok(group)
-        } else {
-            Err(Error::from(Box::from(ServiceError::BadConnection(
-                *conn_id, *group_id,
-            ))))
-        }
-    } else {
-        Err(Error::from(Box::from(ServiceError::GroupDoesNotExist(
-            *group_id,
-        ))))
-    }
-}
-
 fn get_group<'a>(
-    conn_id: &usize,
+    client_id: &ClientId,
     group_id: &Uuid,
     groups: &'a HashMap<Uuid, Group>,
 ) -> Result<&'a Group> {
     if let Some(group) = groups.get(group_id) {
         // Verify connection is part of the group clients
-        if group.clients.iter().any(|c| c == conn_id) {
+        if group.clients.iter().any(|c| c == client_id) {
             Ok(group)
         } else {
-            Err(Error::from(Box::from(ServiceError::BadConnection(
-                *conn_id, *group_id,
-            ))))
+            Err(ServiceError::BadConnection(*client_id, *group_id))
         }
     } else {
-        Err(Error::from(Box::from(ServiceError::GroupDoesNotExist(
-            *group_id,
-        ))))
+        Err(ServiceError::GroupDoesNotExist(*group_id))
     }
 }
 
 fn get_group_session<'a>(
-    conn_id: &usize,
+    client_id: &ClientId,
     group_id: &Uuid,
     session_id: &Uuid,
     groups: &'a HashMap<Uuid, Group>,
 ) -> Result<(&'a Group, &'a Session)> {
-    let group = get_group(conn_id, group_id, groups)?;
+    let group = get_group(client_id, group_id, groups)?;
     if let Some(session) = group.sessions.get(session_id) {
         Ok((group, session))
     } else {
-        Err(Error::from(Box::from(ServiceError::SessionDoesNotExist(
-            *session_id,
-        ))))
+        Err(ServiceError::SessionDoesNotExist(*session_id))
+    }
+}
+
+fn get_group_mut<'a>(
+    client_id: &ClientId,
+    group_id: &Uuid,
+    groups: &'a mut HashMap<Uuid, Group>,
+) -> Result<&'a mut Group> {
+    if let Some(group) = groups.get_mut(group_id) {
+        // Verify connection is part of the group clients
+        if group.clients.iter().any(|c| c == client_id) {
+            Ok(group)
+        } else {
+            Err(ServiceError::BadConnection(*client_id, *group_id))
+        }
+    } else {
+        Err(ServiceError::GroupDoesNotExist(*group_id))
     }
 }
 
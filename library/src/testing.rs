@@ -0,0 +1,478 @@
+//! In-process test harness for multi-party flows.
+//!
+//! Requires the `test-support` feature.
+//!
+//! A [TestServer] drives the JSON-RPC services directly using the
+//! same request and notification routing as the transports but
+//! without any network so tests are deterministic; when a call
+//! returns every notification it caused has already been queued
+//! for the recipients.
+//!
+//! ```ignore
+//! let server = TestServer::new();
+//! let mut alice = server.connect().await;
+//! let mut bob = server.connect().await;
+//!
+//! let group_id = alice.create_group("test", 2, 1).await?;
+//! bob.join_group(group_id).await?;
+//! assert_eq!(alice.next_event().unwrap().0, GROUP_JOIN_EVENT);
+//! ```
+use json_rpc2::{Request, Response, RpcError};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::sync::{mpsc, RwLock};
+use uuid::Uuid;
+
+use crate::identity::ClientId;
+use crate::server::{
+    bind_client, client_disconnected, graceful_shutdown, rpc_request,
+};
+use crate::services::*;
+use crate::transport::Connection;
+use crate::{
+    backplane::{Backplane, Cluster},
+    Blame, Features, Result, ServerError, SessionKind, Shutdown, State,
+};
+
+/// Connection identifier counter for test clients.
+static CONNECTION_ID: AtomicUsize = AtomicUsize::new(1);
+
+/// Error returned by a call that responded with an error.
+#[derive(Debug)]
+pub struct CallError(pub RpcError);
+
+impl CallError {
+    /// Machine-readable data for the error.
+    pub fn data(&self) -> Option<ErrorData> {
+        self.0.data.as_ref().and_then(|data| data.parse().ok())
+    }
+}
+
+/// Round message sent by a party.
+///
+/// When `receiver` is given the message is sent peer to peer
+/// otherwise it is broadcast to the other parties.
+#[derive(Debug, Clone)]
+pub struct RoundMessage {
+    /// Round number.
+    pub round: u16,
+    /// Party number of the sender.
+    pub sender: u16,
+    /// Party number (or index when signing) of the receiver.
+    pub receiver: Option<u16>,
+    /// Message body.
+    pub body: Value,
+}
+
+/// Connection that queues serialized messages for a [TestClient].
+#[derive(Debug)]
+struct TestConnection {
+    tx: mpsc::UnboundedSender<Value>,
+    closed: Arc<AtomicBool>,
+}
+
+impl Connection for TestConnection {
+    fn transport(&self) -> &'static str {
+        "test"
+    }
+
+    fn send(&self, response: &Response) -> Result<()> {
+        let message = serde_json::to_value(response)?;
+        self.tx
+            .send(message)
+            .map_err(|_| ServerError::ConnectionClosed)
+    }
+
+    fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Server state shared by test clients.
+#[derive(Clone)]
+pub struct TestServer {
+    state: Arc<RwLock<State>>,
+}
+
+impl Default for TestServer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TestServer {
+    /// Create a test server with the default features.
+    pub fn new() -> Self {
+        Self::with_features(Default::default())
+    }
+
+    /// Create a test server with features.
+    pub fn with_features(features: Features) -> Self {
+        Self {
+            state: Arc::new(RwLock::new(State::new(features, None))),
+        }
+    }
+
+    /// Create a test server that is a member of a cluster.
+    ///
+    /// Servers sharing a backplane only deliver notifications to
+    /// their own clients; messages published by other servers are
+    /// not received by the test server.
+    pub fn with_backplane(backplane: Arc<dyn Backplane>) -> Self {
+        let cluster = Some(Cluster::new(backplane));
+        Self {
+            state: Arc::new(RwLock::new(State::new(
+                Default::default(),
+                cluster,
+            ))),
+        }
+    }
+
+    /// Server state.
+    pub fn state(&self) -> &Arc<RwLock<State>> {
+        &self.state
+    }
+
+    /// Drain the server as when a shutdown signal is received.
+    pub async fn shutdown(&self, options: Shutdown) {
+        graceful_shutdown(&self.state, options).await;
+    }
+
+    /// Connect a client with a random identity.
+    pub async fn connect(&self) -> TestClient {
+        self.connect_as(ClientId::random()).await
+    }
+
+    /// Connect a client with the identity for a secret key.
+    pub async fn connect_with_key(&self, key: &str) -> Result<TestClient> {
+        Ok(self.connect_as(ClientId::from_key(key.as_bytes())?).await)
+    }
+
+    async fn connect_as(&self, client_id: ClientId) -> TestClient {
+        let conn_id = CONNECTION_ID.fetch_add(1, Ordering::Relaxed);
+        let (tx, rx) = mpsc::unbounded_channel();
+        let closed = Arc::new(AtomicBool::new(false));
+        let conn = TestConnection {
+            tx,
+            closed: Arc::clone(&closed),
+        };
+        bind_client(conn_id, client_id, Arc::new(conn), &self.state).await;
+        TestClient {
+            conn_id,
+            client_id,
+            state: Arc::clone(&self.state),
+            rx,
+            closed,
+            responses: VecDeque::new(),
+            events: VecDeque::new(),
+            message_id: 0,
+        }
+    }
+}
+
+/// Client connected to a [TestServer].
+pub struct TestClient {
+    conn_id: usize,
+    client_id: ClientId,
+    state: Arc<RwLock<State>>,
+    rx: mpsc::UnboundedReceiver<Value>,
+    closed: Arc<AtomicBool>,
+    responses: VecDeque<Response>,
+    events: VecDeque<(String, Value)>,
+    message_id: u64,
+}
+
+impl TestClient {
+    /// Connection identifier.
+    pub fn connection_id(&self) -> usize {
+        self.conn_id
+    }
+
+    /// Client identity.
+    pub fn client_id(&self) -> ClientId {
+        self.client_id
+    }
+
+    /// Whether the server has closed the connection.
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+    }
+
+    /// Send a request without waiting for a response.
+    ///
+    /// Use this to send malformed requests.
+    pub async fn send(&mut self, request: Request) {
+        rpc_request(self.conn_id, self.client_id, request, &self.state).await;
+        self.receive();
+    }
+
+    /// Call a method and return the response.
+    pub async fn call_raw<P: Serialize>(
+        &mut self,
+        method: &str,
+        params: P,
+    ) -> Response {
+        self.message_id += 1;
+        let id = json!(self.message_id);
+        let params = serde_json::to_value(params).unwrap();
+        let params = if params.is_null() { None } else { Some(params) };
+        let request = Request::new(Some(id.clone()), method.to_owned(), params);
+        self.send(request).await;
+
+        let index = self
+            .responses
+            .iter()
+            .position(|response| response.id().as_ref() == Some(&id))
+            .unwrap_or_else(|| panic!("no response to {}", method));
+        self.responses.remove(index).unwrap()
+    }
+
+    /// Call a method and deserialize the result.
+    pub async fn call<P: Serialize, T: DeserializeOwned>(
+        &mut self,
+        method: &str,
+        params: P,
+    ) -> std::result::Result<T, CallError> {
+        let (_, error, result) = self.call_raw(method, params).await.into();
+        if let Some(error) = error {
+            return Err(CallError(error));
+        }
+        Ok(serde_json::from_value(result.unwrap_or(Value::Null)).unwrap())
+    }
+
+    /// Send a notification; the server acknowledges notifications
+    /// with an empty response unless there is an error.
+    pub async fn notify<P: Serialize>(
+        &mut self,
+        method: &str,
+        params: P,
+    ) -> std::result::Result<(), CallError> {
+        let params = serde_json::to_value(params).unwrap();
+        let request = Request::new(None, method.to_owned(), Some(params));
+        self.send(request).await;
+        let index = self
+            .responses
+            .iter()
+            .position(|response| response.id().is_none());
+        if let Some(index) = index {
+            let response = self.responses.remove(index).unwrap();
+            return expect_empty(response);
+        }
+        Ok(())
+    }
+
+    /// Call a method that responds with an empty result.
+    pub async fn call_empty<P: Serialize>(
+        &mut self,
+        method: &str,
+        params: P,
+    ) -> std::result::Result<(), CallError> {
+        let response = self.call_raw(method, params).await;
+        expect_empty(response)
+    }
+
+    /// Take the next event sent to the client.
+    pub fn next_event(&mut self) -> Option<(String, Value)> {
+        self.receive();
+        self.events.pop_front()
+    }
+
+    /// Take all the events sent to the client.
+    pub fn events(&mut self) -> Vec<(String, Value)> {
+        self.receive();
+        self.events.drain(..).collect()
+    }
+
+    /// Take the events with a name.
+    pub fn events_named(&mut self, name: &str) -> Vec<Value> {
+        self.receive();
+        let (matched, rest): (Vec<_>, Vec<_>) =
+            self.events.drain(..).partition(|(event, _)| event == name);
+        self.events = rest.into();
+        matched.into_iter().map(|(_, payload)| payload).collect()
+    }
+
+    /// Disconnect the client.
+    pub async fn disconnect(self) {
+        client_disconnected(self.conn_id, self.client_id, &self.state).await;
+    }
+
+    /// Move queued messages into responses and events.
+    fn receive(&mut self) {
+        while let Ok(message) = self.rx.try_recv() {
+            let response: Response = serde_json::from_value(message).unwrap();
+            // Notifications are sent with the default identifier
+            let notification =
+                response.id().as_ref().and_then(Value::as_u64).unwrap_or(0)
+                    == 0;
+            let event = if notification && response.error().is_none() {
+                response
+                    .result()
+                    .clone()
+                    .and_then(|value| serde_json::from_value(value).ok())
+            } else {
+                None
+            };
+            if let Some(event) = event {
+                self.events.push_back(event);
+            } else {
+                self.responses.push_back(response);
+            }
+        }
+    }
+
+    /// Negotiate the protocol version.
+    pub async fn hello(
+        &mut self,
+    ) -> std::result::Result<ServerHello, CallError> {
+        self.call(SERVER_HELLO, PROTOCOL_VERSION).await
+    }
+
+    /// Create a group and return the group identifier.
+    pub async fn create_group(
+        &mut self,
+        label: &str,
+        parties: u16,
+        threshold: u16,
+    ) -> std::result::Result<Uuid, CallError> {
+        self.call(
+            GROUP_CREATE,
+            (label, json!({"parties": parties, "threshold": threshold})),
+        )
+        .await
+    }
+
+    /// Join a group.
+    pub async fn join_group(
+        &mut self,
+        group_id: Uuid,
+    ) -> std::result::Result<Value, CallError> {
+        self.call(GROUP_JOIN, group_id).await
+    }
+
+    /// Create a session and return the session identifier.
+    pub async fn create_session(
+        &mut self,
+        group_id: Uuid,
+        kind: SessionKind,
+        value: Option<Value>,
+    ) -> std::result::Result<Uuid, CallError> {
+        let session: Value =
+            self.call(SESSION_CREATE, (group_id, kind, value)).await?;
+        Ok(serde_json::from_value(session["uuid"].clone()).unwrap())
+    }
+
+    /// Sign up to a session and return the party signup number.
+    pub async fn signup(
+        &mut self,
+        group_id: Uuid,
+        session_id: Uuid,
+        kind: SessionKind,
+    ) -> std::result::Result<u16, CallError> {
+        self.call(SESSION_SIGNUP, (group_id, session_id, kind))
+            .await
+    }
+
+    /// Load a party signup number into a session.
+    pub async fn load(
+        &mut self,
+        group_id: Uuid,
+        session_id: Uuid,
+        number: u16,
+    ) -> std::result::Result<u16, CallError> {
+        self.call(
+            SESSION_LOAD,
+            (group_id, session_id, SessionKind::Keygen, number),
+        )
+        .await
+    }
+
+    /// Map a party index to a party signup number for a signing session.
+    pub async fn participant(
+        &mut self,
+        group_id: Uuid,
+        session_id: Uuid,
+        index: u16,
+        number: u16,
+    ) -> std::result::Result<(), CallError> {
+        self.call_empty(
+            SESSION_PARTICIPANT,
+            (group_id, session_id, index, number),
+        )
+        .await
+    }
+
+    /// Send a round message.
+    pub async fn message(
+        &mut self,
+        group_id: Uuid,
+        session_id: Uuid,
+        kind: SessionKind,
+        message: RoundMessage,
+    ) -> std::result::Result<(), CallError> {
+        let message = json!({
+            "round": message.round,
+            "sender": message.sender,
+            "receiver": message.receiver,
+            "uuid": session_id.to_string(),
+            "body": message.body,
+        });
+        self.notify(SESSION_MESSAGE, (group_id, session_id, kind, message))
+            .await
+    }
+
+    /// Mark a session as finished.
+    pub async fn finish(
+        &mut self,
+        group_id: Uuid,
+        session_id: Uuid,
+        number: u16,
+    ) -> std::result::Result<(), CallError> {
+        self.call_empty(SESSION_FINISH, (group_id, session_id, number))
+            .await
+    }
+
+    /// Report blame for a failed round.
+    pub async fn abort(
+        &mut self,
+        group_id: Uuid,
+        session_id: Uuid,
+        number: u16,
+        blame: Blame,
+    ) -> std::result::Result<(), CallError> {
+        self.call_empty(SESSION_ABORT, (group_id, session_id, number, blame))
+            .await
+    }
+
+    /// Confirm the public key hash for a session.
+    pub async fn confirm(
+        &mut self,
+        group_id: Uuid,
+        session_id: Uuid,
+        number: u16,
+        hash: &str,
+    ) -> std::result::Result<(), CallError> {
+        self.call_empty(SESSION_CONFIRM, (group_id, session_id, number, hash))
+            .await
+    }
+
+    /// Resume a session and return the messages relayed to the client.
+    pub async fn resume(
+        &mut self,
+        group_id: Uuid,
+        session_id: Uuid,
+        number: u16,
+    ) -> std::result::Result<Vec<Value>, CallError> {
+        self.call(SESSION_RESUME, (group_id, session_id, number))
+            .await
+    }
+}
+
+/// Convert a response with an empty result to a call result.
+fn expect_empty(response: Response) -> std::result::Result<(), CallError> {
+    let error: Option<RpcError> = response.into();
+    error.map_or(Ok(()), |error| Err(CallError(error)))
+}
@@ -0,0 +1,88 @@
+//! Transport-agnostic connections to clients.
+//!
+//! Responses and notifications are routed to a client using the
+//! [Connection] registered in the server state for the connection
+//! identifier so the services do not need to know how a client
+//! is connected.
+//!
+//! | Transport | Requests                        | Responses and notifications         |
+//! |-----------|---------------------------------|-------------------------------------|
+//! | Websocket | Websocket frames                | Websocket frames                    |
+//! | HTTP      | [POST requests](crate::http)    | Server-Sent Events or long-polling  |
+use json_rpc2::Response;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use warp::ws::Message;
+
+use crate::compression::CompressionMetrics;
+use crate::encoding::Codec;
+use crate::{Result, ServerError};
+
+/// Connection to a client.
+pub trait Connection: fmt::Debug + Send + Sync {
+    /// Name of the transport for the connection.
+    fn transport(&self) -> &'static str;
+
+    /// Send a response or notification to the client.
+    fn send(&self, response: &Response) -> Result<()>;
+
+    /// Close the connection once pending messages have been sent.
+    fn close(&self);
+}
+
+/// Connection to a client over a websocket.
+pub(crate) struct WebSocketConnection {
+    tx: mpsc::UnboundedSender<Message>,
+    codec: Codec,
+    closing: Arc<AtomicBool>,
+    metrics: Arc<CompressionMetrics>,
+}
+
+impl WebSocketConnection {
+    /// Create a websocket connection.
+    ///
+    /// The task that writes to the websocket should close it after
+    /// sending a message once the `closing` flag has been set.
+    pub(crate) fn new(
+        tx: mpsc::UnboundedSender<Message>,
+        codec: Codec,
+        closing: Arc<AtomicBool>,
+        metrics: Arc<CompressionMetrics>,
+    ) -> Self {
+        Self {
+            tx,
+            codec,
+            closing,
+            metrics,
+        }
+    }
+}
+
+impl fmt::Debug for WebSocketConnection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WebSocketConnection")
+            .field("codec", &self.codec)
+            .field("closing", &self.closing)
+            .finish()
+    }
+}
+
+impl Connection for WebSocketConnection {
+    fn transport(&self) -> &'static str {
+        "websocket"
+    }
+
+    fn send(&self, response: &Response) -> Result<()> {
+        let message = self.codec.encode(response, &self.metrics)?;
+        self.tx
+            .send(message)
+            .map_err(|_| ServerError::ConnectionClosed)
+    }
+
+    fn close(&self) {
+        self.closing.store(true, Ordering::SeqCst);
+        let _ = self.tx.send(Message::close());
+    }
+}
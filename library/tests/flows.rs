@@ -0,0 +1,702 @@
+use mpc_websocket::backplane::{Backplane, InProcessBackplane};
+use mpc_websocket::services::*;
+use mpc_websocket::testing::{RoundMessage, TestClient, TestServer};
+use mpc_websocket::{Blame, SessionKind, Shutdown};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Create a group with `parties` members that have all joined.
+async fn group(
+    server: &TestServer,
+    parties: u16,
+    threshold: u16,
+) -> (Uuid, Vec<TestClient>) {
+    let mut clients = Vec::new();
+    let mut owner = server.connect().await;
+    let group_id = owner
+        .create_group("test", parties, threshold)
+        .await
+        .unwrap();
+    clients.push(owner);
+    for _ in 1..parties {
+        let mut client = server.connect().await;
+        client.join_group(group_id).await.unwrap();
+        clients.push(client);
+    }
+    for client in clients.iter_mut() {
+        client.events();
+    }
+    (group_id, clients)
+}
+
+/// Sign up the clients to a new session.
+async fn session(
+    group_id: Uuid,
+    clients: &mut [TestClient],
+    kind: SessionKind,
+) -> (Uuid, Vec<u16>) {
+    let session_id = clients[0]
+        .create_session(group_id, kind.clone(), None)
+        .await
+        .unwrap();
+    let mut numbers = Vec::new();
+    for client in clients.iter_mut() {
+        numbers.push(
+            client
+                .signup(group_id, session_id, kind.clone())
+                .await
+                .unwrap(),
+        );
+    }
+    (session_id, numbers)
+}
+
+fn broadcast(round: u16, sender: u16) -> RoundMessage {
+    RoundMessage {
+        round,
+        sender,
+        receiver: None,
+        body: json!({ "from": sender }),
+    }
+}
+
+fn direct(round: u16, sender: u16, receiver: u16) -> RoundMessage {
+    RoundMessage {
+        round,
+        sender,
+        receiver: Some(receiver),
+        body: json!({ "from": sender, "to": receiver }),
+    }
+}
+
+#[tokio::test]
+async fn hello_returns_client_identity() {
+    let server = TestServer::new();
+    let mut client = server.connect().await;
+    let hello = client.hello().await.unwrap();
+    assert_eq!(PROTOCOL_VERSION, hello.version);
+    assert_eq!(client.client_id(), hello.client_id);
+
+    let error = client
+        .call::<_, Value>(SERVER_HELLO, 0u16)
+        .await
+        .unwrap_err();
+    assert_eq!(isize::from(ErrorCode::IncompatibleVersion), error.0.code);
+    assert!(client.is_closed());
+}
+
+#[tokio::test]
+async fn keygen_flow() {
+    let server = TestServer::new();
+    let (group_id, mut clients) = group(&server, 3, 1).await;
+
+    let session_id = clients[0]
+        .create_session(group_id, SessionKind::Keygen, None)
+        .await
+        .unwrap();
+    for client in clients[1..].iter_mut() {
+        let created = client.events_named(SESSION_CREATE_EVENT);
+        assert_eq!(1, created.len());
+    }
+
+    let mut numbers = Vec::new();
+    for client in clients.iter_mut() {
+        numbers.push(
+            client
+                .signup(group_id, session_id, SessionKind::Keygen)
+                .await
+                .unwrap(),
+        );
+    }
+    assert_eq!(vec![1, 2, 3], numbers);
+    for client in clients.iter_mut() {
+        let signup = client.events_named(SESSION_SIGNUP_EVENT);
+        assert_eq!(vec![json!(session_id)], signup);
+    }
+
+    // Broadcast is relayed to everyone except the sender
+    clients[0]
+        .message(group_id, session_id, SessionKind::Keygen, broadcast(1, 1))
+        .await
+        .unwrap();
+    assert!(clients[0].events_named(SESSION_MESSAGE_EVENT).is_empty());
+    for client in clients[1..].iter_mut() {
+        let messages = client.events_named(SESSION_MESSAGE_EVENT);
+        assert_eq!(1, messages.len());
+        assert_eq!(json!(1), messages[0]["sender"]);
+    }
+
+    // Peer to peer is relayed to the receiver only
+    clients[1]
+        .message(group_id, session_id, SessionKind::Keygen, direct(2, 2, 3))
+        .await
+        .unwrap();
+    assert!(clients[0].events_named(SESSION_MESSAGE_EVENT).is_empty());
+    assert!(clients[1].events_named(SESSION_MESSAGE_EVENT).is_empty());
+    let messages = clients[2].events_named(SESSION_MESSAGE_EVENT);
+    assert_eq!(1, messages.len());
+    assert_eq!(json!({ "from": 2, "to": 3 }), messages[0]["body"]);
+
+    // Session is closed once every party has finished
+    for (client, number) in clients.iter_mut().zip(numbers.iter()) {
+        assert!(client.events_named(SESSION_CLOSED_EVENT).is_empty());
+        client.finish(group_id, session_id, *number).await.unwrap();
+    }
+    for client in clients.iter_mut() {
+        let closed = client.events_named(SESSION_CLOSED_EVENT);
+        assert_eq!(vec![json!([1, 2, 3])], closed);
+    }
+}
+
+#[tokio::test]
+async fn sign_flow_resolves_party_index() {
+    let server = TestServer::new();
+    let (group_id, mut clients) = group(&server, 3, 1).await;
+
+    // Parties 1 and 3 from keygen take part in signing
+    let mut signers = vec![clients.remove(0), clients.remove(1)];
+    let (session_id, numbers) =
+        session(group_id, &mut signers, SessionKind::Sign).await;
+    assert_eq!(vec![1, 2], numbers);
+    for signer in signers.iter_mut() {
+        assert_eq!(1, signer.events_named(SESSION_SIGNUP_EVENT).len());
+    }
+
+    // Party indices are positions in the keygen indices [1, 3] so
+    // index 1 is signup 2 and index 2 is signup 1
+    for signer in signers.iter_mut() {
+        signer
+            .participant(group_id, session_id, 1, 2)
+            .await
+            .unwrap();
+        signer
+            .participant(group_id, session_id, 2, 1)
+            .await
+            .unwrap();
+    }
+
+    signers[0]
+        .message(group_id, session_id, SessionKind::Sign, direct(1, 2, 1))
+        .await
+        .unwrap();
+    assert!(signers[0].events_named(SESSION_MESSAGE_EVENT).is_empty());
+    assert_eq!(1, signers[1].events_named(SESSION_MESSAGE_EVENT).len());
+
+    signers[1]
+        .message(group_id, session_id, SessionKind::Sign, direct(1, 1, 2))
+        .await
+        .unwrap();
+    assert_eq!(1, signers[0].events_named(SESSION_MESSAGE_EVENT).len());
+    assert!(signers[1].events_named(SESSION_MESSAGE_EVENT).is_empty());
+
+    // The client that is not signing never sees the messages
+    assert!(clients[0].events_named(SESSION_MESSAGE_EVENT).is_empty());
+
+    // Without a mapping the receiver cannot be resolved
+    let error = signers[0]
+        .message(group_id, session_id, SessionKind::Sign, direct(1, 2, 3))
+        .await
+        .unwrap_err();
+    assert_eq!(isize::from(ErrorCode::BadPeerReceiver), error.0.code);
+}
+
+#[tokio::test]
+async fn abort_flow_broadcasts_blame() {
+    let server = TestServer::new();
+    let (group_id, mut clients) = group(&server, 3, 1).await;
+    let (session_id, numbers) =
+        session(group_id, &mut clients, SessionKind::Keygen).await;
+    for client in clients.iter_mut() {
+        client.events();
+    }
+
+    let blame = Blame {
+        round: 2,
+        parties: vec![3],
+        reason: String::from("verify commitments"),
+    };
+    clients[0]
+        .abort(group_id, session_id, numbers[0], blame)
+        .await
+        .unwrap();
+    assert!(clients[0].events_named(SESSION_ABORT_EVENT).is_empty());
+    for client in clients[1..].iter_mut() {
+        let aborts = client.events_named(SESSION_ABORT_EVENT);
+        assert_eq!(
+            vec![json!({
+                "sessionId": session_id,
+                "reportedBy": 1,
+                "round": 2,
+                "parties": [3],
+                "reason": "verify commitments",
+                "partyNumbers": [3],
+            })],
+            aborts
+        );
+    }
+
+    // Party number must belong to the caller
+    let blame = Blame {
+        round: 2,
+        parties: vec![1],
+        reason: String::new(),
+    };
+    let error = clients[1]
+        .abort(group_id, session_id, numbers[0], blame.clone())
+        .await
+        .unwrap_err();
+    assert_eq!(isize::from(ErrorCode::BadParty), error.0.code);
+
+    // Blamed parties must be known to the session
+    let blame = Blame {
+        parties: vec![4],
+        ..blame
+    };
+    let error = clients[1]
+        .abort(group_id, session_id, numbers[1], blame)
+        .await
+        .unwrap_err();
+    assert_eq!(isize::from(ErrorCode::PartyDoesNotExist), error.0.code);
+}
+
+#[tokio::test]
+async fn confirm_flow_compares_public_key_hashes() {
+    let server = TestServer::new();
+    let (group_id, mut clients) = group(&server, 3, 1).await;
+    let (session_id, numbers) =
+        session(group_id, &mut clients, SessionKind::Keygen).await;
+    for client in clients.iter_mut() {
+        client.events();
+    }
+
+    // Event is sent once every party has confirmed
+    for (client, number) in clients.iter_mut().zip(numbers.iter()) {
+        assert!(client.events_named(SESSION_CONFIRM_EVENT).is_empty());
+        let hash = if *number == 3 { "bad" } else { "abc" };
+        client
+            .confirm(group_id, session_id, *number, hash)
+            .await
+            .unwrap();
+    }
+    for client in clients.iter_mut() {
+        let confirmed = client.events_named(SESSION_CONFIRM_EVENT);
+        assert_eq!(
+            vec![json!({
+                "sessionId": session_id,
+                "hashes": [[1, "abc"], [2, "abc"], [3, "bad"]],
+                "confirmed": false,
+            })],
+            confirmed
+        );
+    }
+
+    // Party number must belong to the caller
+    let error = clients[0]
+        .confirm(group_id, session_id, numbers[1], "abc")
+        .await
+        .unwrap_err();
+    assert_eq!(isize::from(ErrorCode::BadParty), error.0.code);
+}
+
+#[tokio::test]
+async fn resume_flow_replays_messages() {
+    let server = TestServer::new();
+    let key = "0123456789abcdef0123456789abcdef";
+    let mut clients = Vec::new();
+    let mut owner = server.connect().await;
+    let group_id = owner.create_group("test", 3, 1).await.unwrap();
+    clients.push(owner);
+    clients.push(server.connect().await);
+    clients.push(server.connect_with_key(key).await.unwrap());
+    for client in clients[1..].iter_mut() {
+        client.join_group(group_id).await.unwrap();
+    }
+    let (session_id, numbers) =
+        session(group_id, &mut clients, SessionKind::Keygen).await;
+
+    // Party reloads while the other parties send messages
+    clients.pop().unwrap().disconnect().await;
+    clients[0]
+        .message(group_id, session_id, SessionKind::Keygen, broadcast(1, 1))
+        .await
+        .unwrap();
+    clients[1]
+        .message(group_id, session_id, SessionKind::Keygen, direct(1, 2, 1))
+        .await
+        .unwrap();
+    clients[1]
+        .message(group_id, session_id, SessionKind::Keygen, direct(1, 2, 3))
+        .await
+        .unwrap();
+
+    let mut reconnected = server.connect_with_key(key).await.unwrap();
+    reconnected.join_group(group_id).await.unwrap();
+    let messages = reconnected
+        .resume(group_id, session_id, numbers[2])
+        .await
+        .unwrap();
+    assert_eq!(2, messages.len());
+    assert_eq!(json!({ "from": 1 }), messages[0]["body"]);
+    assert_eq!(json!({ "from": 2, "to": 3 }), messages[1]["body"]);
+
+    // Messages sent by the party are not replayed
+    reconnected
+        .message(group_id, session_id, SessionKind::Keygen, broadcast(2, 3))
+        .await
+        .unwrap();
+    let messages = reconnected
+        .resume(group_id, session_id, numbers[2])
+        .await
+        .unwrap();
+    assert_eq!(2, messages.len());
+
+    // Party number must belong to the caller
+    let error = clients[0]
+        .resume(group_id, session_id, numbers[2])
+        .await
+        .unwrap_err();
+    assert_eq!(isize::from(ErrorCode::BadParty), error.0.code);
+    let error = clients[0]
+        .resume(group_id, session_id, 4)
+        .await
+        .unwrap_err();
+    assert_eq!(isize::from(ErrorCode::PartyDoesNotExist), error.0.code);
+}
+
+#[tokio::test]
+async fn rejoin_full_group_with_same_identity() {
+    let server = TestServer::new();
+    let key = "0123456789abcdef0123456789abcdef";
+    let mut clients = Vec::new();
+    let mut owner = server.connect().await;
+    let group_id = owner.create_group("test", 2, 1).await.unwrap();
+    clients.push(owner);
+    let mut member = server.connect_with_key(key).await.unwrap();
+    member.join_group(group_id).await.unwrap();
+    clients.push(member);
+    clients[0].events();
+
+    // Reconnect while the old connection is still bound
+    let mut reconnected = server.connect_with_key(key).await.unwrap();
+    assert!(clients[1].is_closed());
+    let group = reconnected.join_group(group_id).await.unwrap();
+    assert_eq!(json!(group_id), group["uuid"]);
+    assert!(clients[0].events_named(GROUP_JOIN_EVENT).is_empty());
+
+    // Joining again on the same connection is also a no-op
+    reconnected.join_group(group_id).await.unwrap();
+
+    // Other identities are still refused
+    let mut other = server.connect().await;
+    let error = other.join_group(group_id).await.unwrap_err();
+    assert_eq!(isize::from(ErrorCode::GroupFull), error.0.code);
+}
+
+#[tokio::test]
+async fn resume_flow_expires_old_rounds() {
+    let server = TestServer::new();
+    let (group_id, mut clients) = group(&server, 2, 1).await;
+    let (session_id, numbers) =
+        session(group_id, &mut clients, SessionKind::Keygen).await;
+
+    for round in 1..=3 {
+        clients[0]
+            .message(
+                group_id,
+                session_id,
+                SessionKind::Keygen,
+                broadcast(round, 1),
+            )
+            .await
+            .unwrap();
+    }
+
+    // Only the latest two rounds are kept
+    let messages = clients[1]
+        .resume(group_id, session_id, numbers[1])
+        .await
+        .unwrap();
+    assert_eq!(2, messages.len());
+    assert_eq!(json!(2), messages[0]["round"]);
+    assert_eq!(json!(3), messages[1]["round"]);
+}
+
+#[tokio::test]
+async fn reject_message_for_distant_round() {
+    let server = TestServer::new();
+    let (group_id, mut clients) = group(&server, 2, 1).await;
+    let (session_id, numbers) =
+        session(group_id, &mut clients, SessionKind::Keygen).await;
+
+    clients[0]
+        .message(group_id, session_id, SessionKind::Keygen, broadcast(1, 1))
+        .await
+        .unwrap();
+
+    // A distant round would otherwise evict the resume buffer
+    let error = clients[0]
+        .message(
+            group_id,
+            session_id,
+            SessionKind::Keygen,
+            broadcast(u16::MAX, 1),
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(isize::from(ErrorCode::BadRound), error.0.code);
+    assert_eq!(1, clients[1].events_named(SESSION_MESSAGE_EVENT).len());
+
+    let messages = clients[1]
+        .resume(group_id, session_id, numbers[1])
+        .await
+        .unwrap();
+    assert_eq!(1, messages.len());
+    assert_eq!(json!(1), messages[0]["round"]);
+}
+
+#[tokio::test]
+async fn cluster_signup_flow() {
+    let backplane = Arc::new(InProcessBackplane::default());
+    let server = TestServer::with_backplane(backplane.clone());
+    let (group_id, mut clients) = group(&server, 2, 1).await;
+    let (session_id, numbers) =
+        session(group_id, &mut clients, SessionKind::Keygen).await;
+    assert_eq!(vec![1, 2], numbers);
+
+    // A client that retries a signup keeps its party number
+    let number = clients[1]
+        .signup(group_id, session_id, SessionKind::Keygen)
+        .await
+        .unwrap();
+    assert_eq!(2, number);
+
+    // Shared counters are removed when the session is closed
+    for (client, number) in clients.iter_mut().zip(numbers) {
+        client.finish(group_id, session_id, number).await.unwrap();
+    }
+    assert_eq!(1, clients[0].events_named(SESSION_CLOSED_EVENT).len());
+    let key = format!("session:{}:signup", session_id);
+    assert_eq!(1, backplane.incr(&key).await.unwrap());
+
+    // Party numbers from the shared counter must be in range
+    let session_id = clients[0]
+        .create_session(group_id, SessionKind::Keygen, None)
+        .await
+        .unwrap();
+    let key = format!("session:{}:signup", session_id);
+    backplane.incr(&key).await.unwrap();
+    backplane.incr(&key).await.unwrap();
+    let error = clients[0]
+        .signup(group_id, session_id, SessionKind::Keygen)
+        .await
+        .unwrap_err();
+    assert_eq!(isize::from(ErrorCode::PartyNumberOutOfRange), error.0.code);
+}
+
+#[tokio::test]
+async fn graceful_shutdown_waits_for_sessions() {
+    let server = TestServer::new();
+    let (group_id, mut clients) = group(&server, 2, 1).await;
+    let (session_id, numbers) =
+        session(group_id, &mut clients, SessionKind::Keygen).await;
+    let options = Shutdown {
+        timeout: Duration::from_secs(30),
+    };
+
+    let parties = async {
+        // Yield so the server has begun shutting down
+        tokio::task::yield_now().await;
+        for client in clients.iter_mut() {
+            let shutdown = client.events_named(SERVER_SHUTDOWN_EVENT);
+            assert_eq!(vec![json!({ "timeout": 30 })], shutdown);
+        }
+        let error = clients[0]
+            .create_session(group_id, SessionKind::Keygen, None)
+            .await
+            .unwrap_err();
+        assert_eq!(isize::from(ErrorCode::ShuttingDown), error.0.code);
+        assert!(clients.iter().all(|client| !client.is_closed()));
+
+        // Sessions in progress may still finish
+        for (client, number) in clients.iter_mut().zip(numbers) {
+            client.finish(group_id, session_id, number).await.unwrap();
+        }
+    };
+    tokio::join!(server.shutdown(options), parties);
+    assert!(clients.iter().all(|client| client.is_closed()));
+}
+
+#[tokio::test]
+async fn graceful_shutdown_times_out() {
+    let server = TestServer::new();
+    let (group_id, mut clients) = group(&server, 2, 1).await;
+    session(group_id, &mut clients, SessionKind::Keygen).await;
+    server
+        .shutdown(Shutdown {
+            timeout: Duration::from_millis(10),
+        })
+        .await;
+    assert!(clients.iter().all(|client| client.is_closed()));
+}
+
+#[tokio::test]
+async fn load_flow() {
+    let server = TestServer::new();
+    let (group_id, mut clients) = group(&server, 2, 1).await;
+    let session_id = clients[0]
+        .create_session(group_id, SessionKind::Keygen, None)
+        .await
+        .unwrap();
+    clients[0].load(group_id, session_id, 2).await.unwrap();
+    assert!(clients[0].events_named(SESSION_LOAD_EVENT).is_empty());
+
+    let error = clients[1].load(group_id, session_id, 2).await.unwrap_err();
+    assert_eq!(
+        isize::from(ErrorCode::PartyNumberAlreadyExists),
+        error.0.code
+    );
+    let error = clients[1].load(group_id, session_id, 0).await.unwrap_err();
+    assert_eq!(isize::from(ErrorCode::ZeroPartyNumber), error.0.code);
+    let error = clients[1].load(group_id, session_id, 3).await.unwrap_err();
+    assert_eq!(isize::from(ErrorCode::PartyNumberOutOfRange), error.0.code);
+
+    clients[1].load(group_id, session_id, 1).await.unwrap();
+    for client in clients.iter_mut() {
+        assert_eq!(1, client.events_named(SESSION_LOAD_EVENT).len());
+    }
+}
+
+#[tokio::test]
+async fn disconnect_leaves_group() {
+    let server = TestServer::new();
+    let (group_id, mut clients) = group(&server, 3, 1).await;
+
+    let leaving = clients.pop().unwrap();
+    let client_id = leaving.client_id();
+    leaving.disconnect().await;
+    for client in clients.iter_mut() {
+        let left = client.events_named(GROUP_LEAVE_EVENT);
+        assert_eq!(1, left.len());
+        assert_eq!(json!(client_id), left[0]["clientId"]);
+        assert_eq!(json!(2), left[0]["members"]);
+    }
+
+    for client in clients {
+        client.disconnect().await;
+    }
+    assert!(!server.state().read().await.groups.contains_key(&group_id));
+}
+
+#[tokio::test]
+async fn reconnect_keeps_identity() {
+    let server = TestServer::new();
+    let key = "0123456789abcdef0123456789abcdef";
+    let mut owner = server.connect().await;
+    let mut member = server.connect_with_key(key).await.unwrap();
+    let group_id = owner.create_group("test", 2, 1).await.unwrap();
+    member.join_group(group_id).await.unwrap();
+
+    // Reconnecting closes the previous connection
+    let mut reconnected = server.connect_with_key(key).await.unwrap();
+    assert_eq!(member.client_id(), reconnected.client_id());
+    assert!(member.is_closed());
+
+    // Dropping the previous connection does not leave the group
+    member.disconnect().await;
+    owner.events();
+    let session_id = reconnected
+        .create_session(group_id, SessionKind::Keygen, None)
+        .await
+        .unwrap();
+    assert_eq!(1, owner.events_named(SESSION_CREATE_EVENT).len());
+    assert!(owner.events_named(GROUP_LEAVE_EVENT).is_empty());
+    assert_ne!(Uuid::nil(), session_id);
+
+    assert!(server.connect_with_key("short").await.is_err());
+}
+
+#[tokio::test]
+async fn rejects_malicious_inputs() {
+    let server = TestServer::new();
+    let (group_id, mut clients) = group(&server, 2, 1).await;
+    let mut outsider = server.connect().await;
+
+    // Group is full and the connection is closed
+    let error = outsider.join_group(group_id).await.unwrap_err();
+    assert_eq!(isize::from(ErrorCode::GroupFull), error.0.code);
+    assert!(error.data().unwrap().close_connection);
+    assert!(outsider.is_closed());
+
+    // Not a member of the group
+    let mut outsider = server.connect().await;
+    let error = outsider
+        .create_session(group_id, SessionKind::Keygen, None)
+        .await
+        .unwrap_err();
+    assert_eq!(isize::from(ErrorCode::BadConnection), error.0.code);
+    assert_eq!(Some(outsider.client_id()), error.data().unwrap().client_id);
+
+    // Unknown group and session
+    let error = outsider.join_group(Uuid::new_v4()).await.unwrap_err();
+    assert_eq!(isize::from(ErrorCode::GroupDoesNotExist), error.0.code);
+    let error = clients[0]
+        .signup(group_id, Uuid::new_v4(), SessionKind::Keygen)
+        .await
+        .unwrap_err();
+    assert_eq!(isize::from(ErrorCode::SessionDoesNotExist), error.0.code);
+
+    // Invalid group parameters
+    for (parties, threshold, code) in [
+        (1, 0, ErrorCode::PartiesTooSmall),
+        (3, 0, ErrorCode::ThresholdTooSmall),
+        (3, 3, ErrorCode::ThresholdRange),
+    ] {
+        let error = outsider
+            .create_group("bad", parties, threshold)
+            .await
+            .unwrap_err();
+        assert_eq!(isize::from(code), error.0.code);
+    }
+
+    // Malformed parameters keep the standard error code
+    let error = clients[0]
+        .call::<_, Value>(GROUP_JOIN, json!({ "bad": true }))
+        .await
+        .unwrap_err();
+    assert_eq!(-32602, error.0.code);
+
+    let (session_id, numbers) =
+        session(group_id, &mut clients, SessionKind::Keygen).await;
+
+    // Finishing for another party
+    let error = clients[0]
+        .finish(group_id, session_id, numbers[1])
+        .await
+        .unwrap_err();
+    assert_eq!(isize::from(ErrorCode::BadParty), error.0.code);
+    let error = clients[0]
+        .finish(group_id, session_id, 99)
+        .await
+        .unwrap_err();
+    assert_eq!(isize::from(ErrorCode::PartyDoesNotExist), error.0.code);
+
+    // Peer to peer message to an unknown party
+    let error = clients[0]
+        .message(group_id, session_id, SessionKind::Keygen, direct(1, 1, 9))
+        .await
+        .unwrap_err();
+    assert_eq!(isize::from(ErrorCode::BadPeerReceiver), error.0.code);
+    assert!(clients[1].events_named(SESSION_MESSAGE_EVENT).is_empty());
+
+    // Outsider cannot inject messages into the session
+    let error = outsider
+        .message(group_id, session_id, SessionKind::Keygen, broadcast(1, 1))
+        .await
+        .unwrap_err();
+    assert_eq!(isize::from(ErrorCode::BadConnection), error.0.code);
+    for client in clients.iter_mut() {
+        assert!(client.events_named(SESSION_MESSAGE_EVENT).is_empty());
+    }
+}
@@ -22,10 +22,106 @@ export type RpcResponse = {
 export type RpcError = {
   code: number;
   message: string;
-  /* eslint-disable @typescript-eslint/no-explicit-any */
-  data?: any;
+  // JSON-encoded `ErrorData` for service errors.
+  data?: string;
+};
+
+// Stable error codes sent by the server.
+export enum ErrorCode {
+  PartiesTooSmall = -32001,
+  ThresholdTooSmall = -32002,
+  ThresholdRange = -32003,
+  GroupFull = -32010,
+  GroupDoesNotExist = -32011,
+  BadConnection = -32012,
+  SessionDoesNotExist = -32020,
+  PartyDoesNotExist = -32030,
+  BadParty = -32031,
+  BadPeerReceiver = -32032,
+  BadRound = -32033,
+  ZeroPartyNumber = -32040,
+  PartyNumberOutOfRange = -32041,
+  PartyNumberAlreadyExists = -32042,
+  IncompatibleVersion = -32050,
+  ShuttingDown = -32060,
+  Internal = -32603,
+}
+
+// Machine-readable data for service errors.
+export type ErrorData = {
+  // Name of the error kind, eg: `GroupFull`.
+  kind: string;
+  closeConnection: boolean;
+  groupId?: string;
+  sessionId?: string;
+  partyNumber?: number;
+  clientId?: string;
+};
+
+// Error returned by a JSON-RPC call.
+export class ServiceError extends Error {
+  code: number;
+
+  data?: ErrorData;
+
+  constructor(error: RpcError) {
+    super(error.message);
+    this.name = 'ServiceError';
+    this.code = error.code;
+    if (error.data) {
+      try {
+        this.data = JSON.parse(error.data);
+      } catch (e) {
+        // Data is not structured error data
+      }
+    }
+  }
+}
+
+// Protocol version implemented by this client.
+export const PROTOCOL_VERSION = 1;
+
+// Features enabled on the server.
+export type Features = {
+  encryption: boolean;
+  cggmp: boolean;
+};
+
+// Response to the `Server.hello` method.
+export type ServerHello = {
+  version: number;
+  minVersion: number;
+  methods: string[];
+  features: Features;
+  // Encodings supported for websocket messages.
+  encodings: string[];
+  // Identity of the client.
+  clientId: string;
+};
+
+// Binary encoding for websocket messages negotiated when
+// connecting, the `encode` and `decode` functions exported
+// by the webassembly module may be used to implement a codec.
+export type Codec = {
+  // Name of the encoding, eg: `msgpack` or `cbor`.
+  name: string;
+  encode(value: any): Uint8Array;
+  decode(bytes: Uint8Array): any;
+};
+
+// Compression for large websocket messages negotiated when
+// connecting, the `compress` and `decompress` functions exported
+// by the webassembly module implement `deflate` compression.
+export type Compressor = {
+  // Name of the compression, eg: `deflate`.
+  name: string;
+  compress(bytes: Uint8Array): Uint8Array;
+  decompress(bytes: Uint8Array): Uint8Array;
 };
 
+// Messages smaller than this are not compressed.
+const COMPRESSION_THRESHOLD = 1024;
+
 type PromiseCache = {
   resolve: (message: unknown) => void;
   reject: (reason: any) => void;
@@ -43,12 +139,23 @@ export class WebSocketClient extends EventEmitter {
 
   queue: RpcRequest[];
 
-  constructor() {
+  codec?: Codec;
+
+  compressor?: Compressor;
+
+  // Secret identity key used to restore the client identity
+  // when reconnecting.
+  identity?: string;
+
+  constructor(codec?: Codec, compressor?: Compressor, identity?: string) {
     super();
     this.messageId = 0;
     this.messageRequests = new Map();
     this.connected = false;
     this.queue = [];
+    this.codec = codec;
+    this.compressor = compressor;
+    this.identity = identity;
   }
 
   connect(url: string): boolean {
@@ -62,8 +169,21 @@ export class WebSocketClient extends EventEmitter {
       this.websocket.close();
     }
 
+    const location = new URL(url);
+    if (this.codec) {
+      location.searchParams.set('encoding', this.codec.name);
+    }
+    if (this.compressor) {
+      location.searchParams.set('compression', this.compressor.name);
+    }
+    if (this.identity) {
+      location.searchParams.set('identity', this.identity);
+    }
+    const target = location.toString();
+
     /* eslint-disable no-restricted-globals */
-    this.websocket = new WebSocket(url);
+    this.websocket = new WebSocket(target);
+    this.websocket.binaryType = 'arraybuffer';
     this.websocket.onopen = (/* event */) => {
       this.connected = true;
 
@@ -83,7 +203,10 @@ export class WebSocketClient extends EventEmitter {
     };
 
     this.websocket.onmessage = (messageEvent) => {
-      const msg = JSON.parse(messageEvent.data);
+      const msg =
+        typeof messageEvent.data === 'string'
+          ? JSON.parse(messageEvent.data)
+          : this.decodeBinary(new Uint8Array(messageEvent.data));
 
       // Got a promise to resolve
       if (msg.id > 0 && this.messageRequests.has(msg.id)) {
@@ -92,7 +215,7 @@ export class WebSocketClient extends EventEmitter {
         this.messageRequests.delete(msg.id);
         // Without an `id` we treat as a broadcast message
       } else if (msg.error) {
-        throw new Error(msg.error.message);
+        throw new ServiceError(msg.error);
       } else if (msg.result) {
         // Expects a tuple of (event, payload)
         if (Array.isArray(msg.result)) {
@@ -108,7 +231,7 @@ export class WebSocketClient extends EventEmitter {
   notify(message: RpcRequest): void {
     message.jsonrpc = '2.0';
     if (this.connected) {
-      this.websocket.send(JSON.stringify(message));
+      this.websocket.send(this.encode(message));
     } else {
       this.queue.push(message);
       // Try to reconnect
@@ -116,13 +239,66 @@ export class WebSocketClient extends EventEmitter {
     }
   }
 
+  // Encode a message for sending.
+  //
+  // When compression is enabled large messages are framed in binary
+  // frames that start with a flag indicating whether the remaining
+  // bytes are compressed; smaller messages are sent unchanged.
+  encode(message: RpcRequest): string | Uint8Array {
+    if (!this.codec) {
+      return JSON.stringify(message);
+    }
+    const bytes = this.codec.encode(message);
+    if (!this.compressor || bytes.length < COMPRESSION_THRESHOLD) {
+      return bytes;
+    }
+    let flag = 0;
+    let payload = bytes;
+    const compressed = this.compressor.compress(bytes);
+    if (compressed.length < bytes.length) {
+      flag = 1;
+      payload = compressed;
+    }
+    const framed = new Uint8Array(payload.length + 1);
+    framed[0] = flag;
+    framed.set(payload, 1);
+    return framed;
+  }
+
+  // Decode a binary message.
+  //
+  // Encoded messages start with a map marker so a first byte of
+  // 0 or 1 is the flag of a framed message.
+  decodeBinary(data: Uint8Array): any {
+    let bytes = data;
+    if (this.compressor && (data[0] === 0 || data[0] === 1)) {
+      const payload = data.subarray(1);
+      bytes = data[0] === 1 ? this.compressor.decompress(payload) : payload;
+    }
+    if (this.codec) {
+      return this.codec.decode(bytes);
+    }
+    return JSON.parse(new TextDecoder().decode(bytes));
+  }
+
+  // Negotiate the protocol version with the server.
+  //
+  // Rejects with an `IncompatibleVersion` error if the server
+  // does not support the protocol version of this client.
+  async hello(version: number = PROTOCOL_VERSION): Promise<ServerHello> {
+    return this.rpc({
+      method: 'Server.hello',
+      params: version,
+    });
+  }
+
   async rpc(message: RpcRequest): Promise<any> {
     this.messageId += 1;
     const id = this.messageId;
     const promise = new Promise((_resolve, reject) => {
       const resolve = (response: RpcResponse) => {
         if (response.error) {
-          return reject(new Error(response.error.message));
+          return reject(new ServiceError(response.error));
         }
         return _resolve(response.result);
       };
@@ -7,6 +7,7 @@ export * from './sign';
 export * from './transports/websocket';
 export * from './clients/websocket';
 export type { StreamTransport, SinkTransport } from './round-based';
+export { resumeProtocol } from './round-based';
 
 export enum SessionKind {
   KEYGEN = 'keygen',
@@ -29,6 +30,14 @@ export type EcdsaWorker = {
   // Value is a `Uint8Array` wrapped into a sequence
   // using `Array.from` so it is deserialized correctly.
   keccak256(value: number[]): Promise<Uint8Array>;
+
+  verifyKeyShare(keyShare: KeyShare): Promise<void>;
+  publicKeyHash(keyShare: KeyShare): Promise<string>;
+  // Hashes are party number and hash pairs.
+  confirmKeyShare(
+    keyShare: KeyShare,
+    hashes: [number, string][],
+  ): Promise<void>;
 };
 
 export type GroupInfo = {
@@ -37,6 +46,46 @@ export type GroupInfo = {
   params: Parameters;
 };
 
+// Payload for the `groupJoin` and `groupLeave` events.
+export type GroupMember = {
+  groupId: string;
+  clientId: string;
+  // Number of members in the group.
+  members: number;
+};
+
+// Payload for the `serverShutdown` event.
+export type ServerShutdown = {
+  // Seconds the server will wait for sessions to finish.
+  timeout: number;
+};
+
+// Blame for the parties responsible when a protocol round fails.
+export type Blame = {
+  round: number;
+  // Party indices identified as misbehaving.
+  parties: number[];
+  reason: string;
+};
+
+// Payload for the `sessionAbort` event.
+export type SessionAbort = Blame & {
+  sessionId: string;
+  // Party number of the party that reported the blame.
+  reportedBy: number;
+  // Party signup numbers for the blamed parties.
+  partyNumbers: number[];
+};
+
+// Payload for the `sessionConfirm` event.
+export type SessionConfirm = {
+  sessionId: string;
+  // Party number and public key hash pairs.
+  hashes: [number, string][];
+  // Whether the server found every hash to be equal.
+  confirmed: boolean;
+};
+
 // Message is sent by a client.
 //
 // When receiver is null then the message is a broadcast round
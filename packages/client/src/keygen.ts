@@ -1,7 +1,6 @@
-import { Message, KeyShare, SessionInfo, EcdsaWorker, KeyGenerator } from '.';
+import { KeyShare, SessionInfo, EcdsaWorker, KeyGenerator } from '.';
 import {
-  Round,
-  RoundBased,
+  runProtocol,
   StreamTransport,
   SinkTransport,
   onTransition as onTransitionLog,
@@ -10,6 +9,9 @@ import {
 /**
  * Starts the round-based processing to generate a key share.
  *
+ * Once generated the key share is verified and the parties confirm
+ * they derived the same public key before the key share is returned.
+ *
  * @param worker - The worker implementation.
  * @param stream - The stream for sending messages.
  * @param sink - The sink for receiving messages.
@@ -38,51 +40,22 @@ export async function generateKeyShare(
     }
   };
 
-  const standardTransition = async (
-    incoming: Message[],
-  ): Promise<[number, Message[]]> => {
-    for (const message of incoming) {
-      /* eslint-disable @typescript-eslint/await-thenable */
-      await keygen.handleIncoming(message);
-    }
-    return await keygen.proceed();
-  };
-
-  const rounds: Round[] = [
-    {
-      name: 'KEYGEN_ROUND_1',
-      transition: async (): Promise<[number, Message[]]> => {
-        return await keygen.proceed();
-      },
-    },
-    {
-      name: 'KEYGEN_ROUND_2',
-      transition: standardTransition,
-    },
-    {
-      name: 'KEYGEN_ROUND_3',
-      transition: standardTransition,
-    },
-    {
-      name: 'KEYGEN_ROUND_4',
-      transition: standardTransition,
-    },
-  ];
-
-  const finalizer = {
-    name: 'KEYGEN_FINALIZE',
-    finalize: async (incoming: Message[]) => {
-      await standardTransition(incoming);
-      return keygen.create();
-    },
-  };
-
-  const handler = new RoundBased<KeyShare>(
-    rounds,
-    finalizer,
-    doTransition,
+  const lastRound = await runProtocol(
+    keygen,
+    'KEYGEN',
+    info.partySignup.number,
     stream,
     sink,
+    doTransition,
   );
-  return handler.start();
+  doTransition(lastRound, 'KEYGEN_FINALIZE');
+  /* eslint-disable @typescript-eslint/await-thenable */
+  const keyShare: KeyShare = await keygen.output();
+
+  doTransition('KEYGEN_FINALIZE', 'KEYGEN_CONFIRM');
+  await worker.verifyKeyShare(keyShare);
+  const hash = await worker.publicKeyHash(keyShare);
+  const hashes = await stream.confirm(info.partySignup.number, hash);
+  await worker.confirmKeyShare(keyShare, hashes);
+  return keyShare;
 }
@@ -1,108 +1,155 @@
-import { Message } from '.';
-
-export type Round = {
-  name: string;
-  transition: (incoming?: Message[]) => Promise<[number, Message[]]>;
-};
-
-export type Finalizer<R> = {
-  name: string;
-  finalize: (incoming: Message[]) => Promise<R>;
+import { Blame, Message } from '.';
+
+/**
+ * Protocol state machine driven by the webassembly round driver.
+ *
+ * The driver buffers messages for future rounds and ignores
+ * duplicates so incoming messages may be handled in any order.
+ *
+ * A signer can be encrypted with a 32 byte key using `serialize()`
+ * once the offline stage has completed and restored with the static
+ * `deserialize()` after a page reload; the other protocols cannot be
+ * persisted while they are running.
+ */
+export type Protocol = {
+  handleIncoming(message: Message): Promise<void> | void;
+  proceed(): Promise<[number, Message[]]> | [number, Message[]];
+  wantsToProceed(): Promise<boolean> | boolean;
+  isFinished(): Promise<boolean> | boolean;
+  currentRound(): Promise<number> | number;
+  totalRounds(): Promise<number> | number;
+  serialize?(key: Uint8Array): Promise<Uint8Array> | Uint8Array;
 };
 
 export type StreamTransport = {
   sendMessage(message: Message): Promise<void>;
+  abort(partyNumber: number, blame: Blame): Promise<void>;
+  resume(partyNumber: number): Promise<Message[]>;
+  confirm(partyNumber: number, hash: string): Promise<[number, string][]>;
 };
 
 export type SinkTransport = {
   receiveMessage(message: Message): void;
   isReady(round: number): boolean;
   take(round: number): Message[];
+  drain(filter: (message: Message) => boolean): Message[];
 };
 
-export class RoundBased<R> {
-  rounds: Round[];
-
-  finalizer: Finalizer<R>;
-
-  onTransition: (previousRound: string, current: string) => void;
-
-  currentRound: number;
-
-  totalRounds: number;
-
-  stream: StreamTransport;
-
-  sink: SinkTransport;
-
-  constructor(
-    rounds: Round[],
-    finalizer: Finalizer<R>,
-    onTransition: (previousRound: string, current: string) => void,
-    stream: StreamTransport,
-    sink: SinkTransport,
-  ) {
-    this.rounds = rounds;
-    this.finalizer = finalizer;
-    this.onTransition = onTransition;
-    this.currentRound = 0;
-    this.totalRounds = rounds.length;
-    this.stream = stream;
-    this.sink = sink;
-  }
+const sleep = (ms: number) =>
+  new Promise((resolve) => {
+    setTimeout(resolve, ms);
+  });
+
+/**
+ * Run a protocol until the round driver reports it is finished.
+ *
+ * When a round fails and the error blames other parties the blame
+ * is reported to the session before the error is thrown.
+ *
+ * @param protocol - The protocol state machine.
+ * @param name - Prefix for round names passed to the transition handler.
+ * @param partyNumber - The party signup number for this party.
+ * @param stream - The stream for sending messages.
+ * @param sink - The sink for receiving messages.
+ * @param onTransition - Transition handler.
+ * @returns The name of the last round.
+ */
+export async function runProtocol(
+  protocol: Protocol,
+  name: string,
+  partyNumber: number,
+  stream: StreamTransport,
+  sink: SinkTransport,
+  onTransition: (previousRound: string, current: string) => void,
+): Promise<string> {
+  /* eslint-disable @typescript-eslint/await-thenable */
+  const totalRounds = await protocol.totalRounds();
+  let previousRound: string = null;
+
+  try {
+    for (;;) {
+      const incoming = sink.drain(
+        (message) => message.round >= 1 && message.round <= totalRounds,
+      );
+      for (const message of incoming) {
+        await protocol.handleIncoming(message);
+      }
 
-  async waitForRound(round: number): Promise<Message[]> {
-    return new Promise((resolve) => {
-      const interval = setInterval(() => {
-        if (this.sink.isReady(round)) {
-          clearInterval(interval);
-          resolve(this.sink.take(round));
+      while (await protocol.wantsToProceed()) {
+        const [round, messages] = await protocol.proceed();
+        const currentRound = `${name}_ROUND_${round}`;
+        if (currentRound !== previousRound) {
+          onTransition(previousRound, currentRound);
+          previousRound = currentRound;
         }
-      }, 50);
-    });
-  }
-
-  async nextRound(previousMessages: Message[]): Promise<Message[]> {
-    const currentRound = this.rounds[this.currentRound];
-
-    const previousRound = this.rounds[this.currentRound - 1];
-    this.onTransition(
-      previousRound ? previousRound.name : null,
-      currentRound.name,
-    );
-
-    if (currentRound) {
-      const result = await currentRound.transition(previousMessages);
-      if (result) {
-        const [round, messages] = result;
         for (const message of messages) {
-          await this.stream.sendMessage(message);
+          await stream.sendMessage(message);
         }
-        const nextMessages = await this.waitForRound(round);
-        this.currentRound += 1;
-        return nextMessages;
       }
-      throw new Error('Did not get result from round transition');
-    }
 
-    return null;
-  }
-
-  async start(): Promise<R> {
-    let nextMessages: Message[] = null;
+      if (await protocol.isFinished()) {
+        return previousRound;
+      }
 
-    while (this.currentRound < this.totalRounds) {
-      nextMessages = await this.nextRound(nextMessages);
+      await sleep(50);
+    }
+  } catch (error) {
+    const { blame } = error as { blame?: Blame };
+    if (blame) {
+      await stream.abort(partyNumber, blame);
     }
+    throw error;
+  }
+}
 
-    const previousRound = this.rounds[this.currentRound - 1];
-    this.onTransition(
-      previousRound ? previousRound.name : null,
-      this.finalizer.name,
-    );
+/**
+ * Deliver the messages relayed while this party was offline to
+ * a signer restored with `deserialize()`.
+ *
+ * Messages the protocol has already received are ignored so
+ * every message relayed during the session can be delivered.
+ *
+ * @param protocol - The restored protocol state machine.
+ * @param partyNumber - The party signup number for this party.
+ * @param stream - The stream for the session.
+ */
+export async function resumeProtocol(
+  protocol: Protocol,
+  partyNumber: number,
+  stream: StreamTransport,
+): Promise<void> {
+  /* eslint-disable @typescript-eslint/await-thenable */
+  const totalRounds = await protocol.totalRounds();
+  const messages = await stream.resume(partyNumber);
+  for (const message of messages) {
+    if (message.round >= 1 && message.round <= totalRounds) {
+      await protocol.handleIncoming(message);
+    }
+  }
+}
 
-    return this.finalizer.finalize(nextMessages);
+/**
+ * Send messages for a round outside of a protocol state machine
+ * and wait for the messages from the other parties.
+ *
+ * @param round - The round number.
+ * @param messages - The messages to send.
+ * @param stream - The stream for sending messages.
+ * @param sink - The sink for receiving messages.
+ */
+export async function exchange(
+  round: number,
+  messages: Message[],
+  stream: StreamTransport,
+  sink: SinkTransport,
+): Promise<Message[]> {
+  for (const message of messages) {
+    await stream.sendMessage(message);
+  }
+  while (!sink.isReady(round)) {
+    await sleep(50);
   }
+  return sink.take(round);
 }
 
 export const onTransition = (previousRound: string, current: string) => {
@@ -12,8 +12,8 @@ import {
 } from '.';
 import { WebSocketClient } from './clients/websocket';
 import {
-  Round,
-  RoundBased,
+  exchange,
+  runProtocol,
   StreamTransport,
   SinkTransport,
   onTransition as onTransitionLog,
@@ -39,64 +39,42 @@ async function getParticipants(
   sink: SinkTransport,
   onTransition: (previousRound: string, current: string) => void,
 ): Promise<number[]> {
-  const rounds: Round[] = [
-    {
-      name: 'SIGN_ROUND_0',
-      /* eslint-disable @typescript-eslint/require-await */
-      transition: async (): Promise<[number, Message[]]> => {
-        const index = keyShare.localKey.i;
-        const round = 0;
+  const index = keyShare.localKey.i;
+  const round = 0;
+  onTransition(null, 'SIGN_ROUND_0');
 
-        // Must share our key share index
-        // in order to initialize the state
-        // machine with list of participants.
-        const indexMessage: Message = {
-          round,
-          uuid: info.sessionId,
-          sender: index,
-          receiver: null,
-          body: info.partySignup.number,
-        };
-
-        return [round, [indexMessage]];
-      },
-    },
-  ];
-
-  const finalizer = {
-    name: 'SIGN_PARTICIPANTS',
-    /* eslint-disable @typescript-eslint/require-await */
-    finalize: async (incoming: Message[]) => {
-      const participants = incoming.map((msg) => [msg.sender, msg.body]);
-      participants.push([keyShare.localKey.i, info.partySignup.number]);
-      // NOTE: Must be sorted by party signup number to ensure
-      // NOTE: the party signup indices correspond to the correct
-      // NOTE: index for the local key. See `OfflineStage::new()` in
-      // NOTE: `multi-party-ecdsa` for more information.
-      participants.sort((a, b) => {
-        if (a[1] < b[1]) {
-          return -1;
-        }
+  // Must share our key share index
+  // in order to initialize the state
+  // machine with list of participants.
+  const indexMessage: Message = {
+    round,
+    uuid: info.sessionId,
+    sender: index,
+    receiver: null,
+    body: info.partySignup.number,
+  };
 
-        if (a[1] > b[1]) {
-          return 1;
-        }
-        return 0;
-      });
+  const incoming = await exchange(round, [indexMessage], stream, sink);
+  onTransition('SIGN_ROUND_0', 'SIGN_PARTICIPANTS');
 
-      return participants.map((item) => item[0]);
-    },
-  };
+  const participants = incoming.map((msg) => [msg.sender, msg.body]);
+  participants.push([keyShare.localKey.i, info.partySignup.number]);
+  // NOTE: Must be sorted by party signup number to ensure
+  // NOTE: the party signup indices correspond to the correct
+  // NOTE: index for the local key. See `OfflineStage::new()` in
+  // NOTE: `multi-party-ecdsa` for more information.
+  participants.sort((a, b) => {
+    if (a[1] < b[1]) {
+      return -1;
+    }
 
-  const handler = new RoundBased<number[]>(
-    rounds,
-    finalizer,
-    onTransition,
-    stream,
-    sink,
-  );
+    if (a[1] > b[1]) {
+      return 1;
+    }
+    return 0;
+  });
 
-  return handler.start();
+  return participants.map((item) => item[0]);
 }
 
 /**
@@ -104,72 +82,27 @@ async function getParticipants(
  * we have partial signatures.
  *
  * @param signer - The signer implementation.
+ * @param partyNumber - The party signup number for this party.
  * @param stream - The stream for sending messages.
  * @param sink - The sink for receiving messages.
  * @param onTransition - Transition handler.
  */
 async function offlineStage(
   signer: Signer,
+  partyNumber: number,
   stream: StreamTransport,
   sink: SinkTransport,
   onTransition: (previousRound: string, current: string) => void,
 ): Promise<void> {
-  const standardTransition = async (
-    incoming: Message[],
-  ): Promise<[number, Message[]]> => {
-    for (const message of incoming) {
-      /* eslint-disable @typescript-eslint/await-thenable */
-      await signer.handleIncoming(message);
-    }
-    return await signer.proceed();
-  };
-
-  const rounds: Round[] = [
-    {
-      name: 'SIGN_ROUND_1',
-      transition: async (): Promise<[number, Message[]]> => {
-        return await signer.proceed();
-      },
-    },
-    {
-      name: 'SIGN_ROUND_2',
-      transition: standardTransition,
-    },
-    {
-      name: 'SIGN_ROUND_3',
-      transition: standardTransition,
-    },
-    {
-      name: 'SIGN_ROUND_4',
-      transition: standardTransition,
-    },
-    {
-      name: 'SIGN_ROUND_5',
-      transition: standardTransition,
-    },
-    {
-      name: 'SIGN_ROUND_6',
-      transition: standardTransition,
-    },
-  ];
-
-  const finalizer = {
-    name: 'SIGN_OFFLINE_STAGE',
-    finalize: async (incoming: Message[]): Promise<void> => {
-      await standardTransition(incoming);
-      return null;
-    },
-  };
-
-  const handler = new RoundBased<void>(
-    rounds,
-    finalizer,
-    onTransition,
+  const lastRound = await runProtocol(
+    signer,
+    'SIGN',
+    partyNumber,
     stream,
     sink,
+    onTransition,
   );
-
-  return handler.start();
+  onTransition(lastRound, 'SIGN_OFFLINE_STAGE');
 }
 
 /**
@@ -190,44 +123,25 @@ async function partialSignature(
   sink: SinkTransport,
   onTransition: (previousRound: string, current: string) => void,
 ): Promise<SignMessage> {
-  const rounds: Round[] = [
-    {
-      name: 'SIGN_ROUND_8',
-      transition: async (): Promise<[number, Message[]]> => {
-        const partial = await signer.partial(Array.from(message));
-        const round = 8;
-        // Broadcast the partial signature
-        // to other clients
-        const partialMessage: Message = {
-          round,
-          uuid: info.sessionId,
-          sender: info.partySignup.number,
-          receiver: null,
-          body: partial,
-        };
-
-        return [round, [partialMessage]];
-      },
-    },
-  ];
-
-  const finalizer = {
-    name: 'SIGN_PARTIAL',
-    finalize: async (incoming: Message[]) => {
-      const partials = incoming.map((msg) => msg.body);
-      return await signer.create(partials);
-    },
+  onTransition('SIGN_OFFLINE_STAGE', 'SIGN_ROUND_8');
+  /* eslint-disable @typescript-eslint/await-thenable */
+  const partial = await signer.partial(Array.from(message));
+  const round = 8;
+  // Broadcast the partial signature
+  // to other clients
+  const partialMessage: Message = {
+    round,
+    uuid: info.sessionId,
+    sender: info.partySignup.number,
+    receiver: null,
+    body: partial,
   };
 
-  const handler = new RoundBased<SignMessage>(
-    rounds,
-    finalizer,
-    onTransition,
-    stream,
-    sink,
-  );
+  const incoming = await exchange(round, [partialMessage], stream, sink);
+  onTransition('SIGN_ROUND_8', 'SIGN_PARTIAL');
 
-  return handler.start();
+  const partials = incoming.map((msg) => msg.body);
+  return await signer.create(partials);
 }
 
 /**
@@ -283,7 +197,13 @@ async function signMessage(
     keyShare.localKey,
   );
 
-  await offlineStage(signer, stream, sink, onTransition);
+  await offlineStage(
+    signer,
+    info.partySignup.number,
+    stream,
+    sink,
+    onTransition,
+  );
 
   const signed = await partialSignature(
     signer,
@@ -1,4 +1,4 @@
-import { Message, SessionKind } from '..';
+import { Blame, Message, SessionConfirm, SessionKind } from '..';
 import { WebSocketClient } from '../clients/websocket';
 import { StreamTransport, SinkTransport } from '../round-based';
 
@@ -33,6 +33,42 @@ export class WebSocketStream implements StreamTransport {
       params: [this.groupId, this.sessionId, this.kind, message],
     });
   }
+
+  async abort(partyNumber: number, blame: Blame) {
+    return this.websocket.rpc({
+      method: 'Session.abort',
+      params: [this.groupId, this.sessionId, partyNumber, blame],
+    });
+  }
+
+  // Resolves with the hashes reported by every party
+  // once all the parties have confirmed.
+  async confirm(
+    partyNumber: number,
+    hash: string,
+  ): Promise<[number, string][]> {
+    const confirmed = new Promise<[number, string][]>((resolve) => {
+      const listener = (event: SessionConfirm) => {
+        if (event.sessionId === this.sessionId) {
+          this.websocket.removeListener('sessionConfirm', listener);
+          resolve(event.hashes);
+        }
+      };
+      this.websocket.on('sessionConfirm', listener);
+    });
+    await this.websocket.rpc({
+      method: 'Session.confirm',
+      params: [this.groupId, this.sessionId, partyNumber, hash],
+    });
+    return confirmed;
+  }
+
+  async resume(partyNumber: number): Promise<Message[]> {
+    return this.websocket.rpc({
+      method: 'Session.resume',
+      params: [this.groupId, this.sessionId, partyNumber],
+    });
+  }
 }
 
 // Sink for incoming messages that listens for events
@@ -73,6 +109,18 @@ export class WebSocketSink implements SinkTransport {
     return values;
   }
 
+  drain(filter: (message: Message) => boolean): Message[] {
+    const values: Message[] = [];
+    for (const [round, messages] of this.rounds) {
+      values.push(...messages.filter(filter));
+      this.rounds.set(
+        round,
+        messages.filter((message) => !filter(message)),
+      );
+    }
+    return values;
+  }
+
   receiveMessage(message: Message): void {
     const { round, uuid } = message;
 
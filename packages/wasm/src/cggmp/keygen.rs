@@ -0,0 +1,37 @@
+//! CGGMP key generation.
+use wasm_bindgen::prelude::*;
+
+use crate::native;
+use crate::{protocol, Parameters, PartySignup};
+
+/// Round-based CGGMP key share generator.
+///
+/// Generates a key share and the auxiliary information
+/// (Paillier keys and ring-Pedersen parameters) required
+/// for presigning.
+#[wasm_bindgen]
+pub struct CggmpKeyGenerator {
+    inner: native::CggmpKeyGenerator,
+}
+
+#[wasm_bindgen]
+impl CggmpKeyGenerator {
+    /// Create a key generator.
+    #[wasm_bindgen(constructor)]
+    pub fn new(
+        parameters: JsValue,
+        party_signup: JsValue,
+    ) -> Result<CggmpKeyGenerator, JsError> {
+        let params: Parameters = serde_wasm_bindgen::from_value(parameters)?;
+        let party_signup: PartySignup =
+            serde_wasm_bindgen::from_value(party_signup)?;
+        Ok(Self {
+            inner: native::CggmpKeyGenerator::new(params, party_signup)?,
+        })
+    }
+}
+
+protocol::bindings!(
+    CggmpKeyGenerator,
+    "Create the key share and auxiliary information."
+);
@@ -1,3 +1,17 @@
-mod presign;
-mod refresh;
-mod sign;
+//! CGGMP key generation, key refresh, presigning and signing.
+//!
+//! Key generation with [CggmpKeyGenerator](keygen::CggmpKeyGenerator)
+//! produces a key share and the auxiliary information needed to
+//! generate presignatures with [PreSigning](presign::PreSigning);
+//! a presignature is then used to sign a message in a single round
+//! with [SignPresignature](sign::SignPresignature).
+//!
+//! Presignatures can be generated in batches ahead of time with
+//! [PresigningBatch](pool::PresigningBatch) and kept in a
+//! [PresignaturePool](pool::PresignaturePool) which guarantees each
+//! presignature is only used once.
+pub mod keygen;
+pub mod pool;
+pub mod presign;
+pub mod refresh;
+pub mod sign;
@@ -0,0 +1,162 @@
+//! Presignature pools.
+use std::convert::TryInto;
+use wasm_bindgen::prelude::*;
+
+use curv::elliptic::curves::secp256_k1::Secp256k1;
+
+use cggmp_threshold_ecdsa::presign::SSID;
+
+use crate::native::{
+    self, BatchMessage, CggmpKeyShare, Presignature, RoundMsg,
+};
+
+/// Store of presignatures indexed by SSID.
+///
+/// Each presignature can only be taken once.
+#[wasm_bindgen]
+pub struct PresignaturePool {
+    pub(crate) inner: native::PresignaturePool,
+}
+
+#[wasm_bindgen]
+impl PresignaturePool {
+    /// Create an empty pool.
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> PresignaturePool {
+        Self {
+            inner: native::PresignaturePool::new(),
+        }
+    }
+
+    /// Decrypt a pool encrypted with a 32 byte key.
+    pub fn decrypt(
+        encrypted: Vec<u8>,
+        key: Vec<u8>,
+    ) -> Result<PresignaturePool, JsError> {
+        let key = key.as_slice().try_into()?;
+        Ok(Self {
+            inner: native::PresignaturePool::decrypt(&encrypted, key)?,
+        })
+    }
+
+    /// Encrypt the pool with a 32 byte key.
+    pub fn encrypt(&self, key: Vec<u8>) -> Result<Vec<u8>, JsError> {
+        let key = key.as_slice().try_into()?;
+        Ok(self.inner.encrypt(key)?)
+    }
+
+    /// Add a presignature and return the SSID identifier.
+    pub fn insert(
+        &mut self,
+        ssid: JsValue,
+        index: u16,
+        presignature: JsValue,
+    ) -> Result<String, JsError> {
+        let ssid: SSID<Secp256k1> = serde_wasm_bindgen::from_value(ssid)?;
+        let presignature: Presignature =
+            serde_wasm_bindgen::from_value(presignature)?;
+        Ok(self.inner.insert(ssid, index, presignature)?)
+    }
+
+    /// Identifiers for the SSIDs in the pool.
+    pub fn ssids(&self) -> Result<JsValue, JsError> {
+        Ok(serde_wasm_bindgen::to_value(&self.inner.ssids())?)
+    }
+
+    /// Number of unused presignatures for an SSID identifier.
+    pub fn available(&self, id: &str) -> usize {
+        self.inner.available(id)
+    }
+}
+
+impl Default for PresignaturePool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Generate a batch of presignatures.
+#[wasm_bindgen]
+pub struct PresigningBatch {
+    inner: Option<native::PresigningBatch>,
+}
+
+#[wasm_bindgen]
+impl PresigningBatch {
+    /// Create a batch for the presignature indices.
+    #[wasm_bindgen(constructor)]
+    pub fn new(
+        ssid: JsValue,
+        key_share: JsValue,
+        indices: Vec<u16>,
+    ) -> Result<PresigningBatch, JsError> {
+        let ssid: SSID<Secp256k1> = serde_wasm_bindgen::from_value(ssid)?;
+        let key_share: CggmpKeyShare =
+            serde_wasm_bindgen::from_value(key_share)?;
+        Ok(Self {
+            inner: Some(native::PresigningBatch::new(
+                ssid, &key_share, indices,
+            )?),
+        })
+    }
+
+    /// Handle an incoming message.
+    ///
+    /// Messages for future rounds are buffered and duplicate
+    /// messages are ignored.
+    #[wasm_bindgen(js_name = "handleIncoming")]
+    pub fn handle_incoming(
+        &mut self,
+        message: JsValue,
+    ) -> Result<(), JsError> {
+        let message: RoundMsg<BatchMessage> =
+            serde_wasm_bindgen::from_value(message)?;
+        self.batch()?.handle_incoming(message)?;
+        Ok(())
+    }
+
+    /// Whether there are messages to send or the current round
+    /// has received all the messages it needs to proceed.
+    #[wasm_bindgen(js_name = "wantsToProceed")]
+    pub fn wants_to_proceed(&self) -> bool {
+        self.inner
+            .as_ref()
+            .map(|batch| batch.wants_to_proceed())
+            .unwrap_or_default()
+    }
+
+    /// Whether every presignature in the batch has finished.
+    #[wasm_bindgen(js_name = "isFinished")]
+    pub fn is_finished(&self) -> bool {
+        self.inner
+            .as_ref()
+            .map(|batch| batch.is_finished())
+            .unwrap_or(true)
+    }
+
+    /// Proceed to the next round if ready and return the
+    /// current round and the messages to send.
+    pub fn proceed(&mut self) -> Result<JsValue, JsError> {
+        let result = self.batch()?.proceed()?;
+        Ok(serde_wasm_bindgen::to_value(&result)?)
+    }
+
+    /// Add the presignatures to a pool and return the
+    /// SSID identifier.
+    pub fn finish(
+        &mut self,
+        pool: &mut PresignaturePool,
+    ) -> Result<String, JsError> {
+        let batch = self
+            .inner
+            .take()
+            .ok_or_else(|| JsError::new("batch already finished"))?;
+        Ok(batch.finish(&mut pool.inner)?)
+    }
+
+    fn batch(&mut self) -> Result<&mut native::PresigningBatch, JsError> {
+        self.inner
+            .as_mut()
+            .ok_or_else(|| JsError::new("batch already finished"))
+    }
+}
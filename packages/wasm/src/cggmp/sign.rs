@@ -1,107 +1,119 @@
-use serde::Serialize;
+//! Sign a message using presignatures.
 use std::collections::HashMap;
 use wasm_bindgen::prelude::*;
 
-use curv::{elliptic::curves::secp256_k1::Secp256k1, BigInt};
-use round_based::{Msg, StateMachine};
-
-use cggmp_threshold_ecdsa::presign::{
-    PresigningOutput, PresigningTranscript, SSID,
+use curv::{
+    elliptic::curves::{secp256_k1::Secp256k1, Point},
+    BigInt,
 };
 
-use cggmp_threshold_ecdsa::sign::{
-    state_machine::{self, ProtocolMessage},
-    SigningOutput,
-};
+use cggmp_threshold_ecdsa::presign::SSID;
 
-/// Wrapper for a round `Msg` that includes the round
-/// number so that we can ensure round messages are grouped
-/// together and out of order messages can thus be handled correctly.
-#[derive(Serialize)]
-struct RoundMsg {
-    round: u16,
-    sender: u16,
-    receiver: Option<u16>,
-    body: ProtocolMessage,
-}
-
-impl RoundMsg {
-    fn from_round(
-        round: u16,
-        messages: Vec<
-            Msg<<state_machine::Signing as StateMachine>::MessageBody>,
-        >,
-    ) -> Vec<Self> {
-        messages
-            .into_iter()
-            .map(|m| RoundMsg {
-                round,
-                sender: m.sender,
-                receiver: m.receiver,
-                body: m.body,
-            })
-            .collect::<Vec<_>>()
-    }
-}
+use super::pool::PresignaturePool;
+use crate::native::{self, Payload, Presignature};
+use crate::protocol;
 
 /// Sign using a presignature.
 #[wasm_bindgen]
 pub struct SignPresignature {
-    inner: state_machine::Signing,
+    inner: native::SignPresignature,
 }
 
 #[wasm_bindgen]
 impl SignPresignature {
-    /// Create a presignature state machine.
+    /// Create a signing state machine.
+    ///
+    /// The `public_key` is the uncompressed public key of
+    /// the key share used to generate the presignatures.
     #[wasm_bindgen(constructor)]
     pub fn new(
         ssid: JsValue,
         l: usize,
         m: JsValue,
         presigning_data: JsValue,
+        public_key: JsValue,
     ) -> Result<SignPresignature, JsError> {
-        let ssid: SSID<Secp256k1> = serde_wasm_bindgen::from_value(ssid)?;
         let m: BigInt = serde_wasm_bindgen::from_value(m)?;
-        let presigning_data: HashMap<
-            u16,
-            (PresigningOutput<Secp256k1>, PresigningTranscript<Secp256k1>),
-        > = serde_wasm_bindgen::from_value(presigning_data)?;
-        Ok(Self {
-            inner: state_machine::Signing::new(ssid, l, m, presigning_data)?,
-        })
+        Self::create(ssid, l, m, presigning_data, public_key)
     }
 
-    /// Handle an incoming message.
-    #[wasm_bindgen(js_name = "handleIncoming")]
-    pub fn handle_incoming(
-        &mut self,
-        message: JsValue,
-    ) -> Result<(), JsError> {
-        let message: Msg<
-            <state_machine::Signing as StateMachine>::MessageBody,
-        > = serde_wasm_bindgen::from_value(message)?;
-        self.inner.handle_incoming(message)?;
-        Ok(())
+    /// Create a signing state machine for the digest of a payload.
+    ///
+    /// The payload is an object with a `kind` of `keccak256`,
+    /// `personal` (EIP-191) or `typedData` (EIP-712) and the
+    /// `value` to hash.
+    #[wasm_bindgen(js_name = "fromPayload")]
+    pub fn from_payload(
+        ssid: JsValue,
+        l: usize,
+        payload: JsValue,
+        presigning_data: JsValue,
+        public_key: JsValue,
+    ) -> Result<SignPresignature, JsError> {
+        let payload: Payload = serde_wasm_bindgen::from_value(payload)?;
+        Self::create(
+            ssid,
+            l,
+            payload.to_bigint()?,
+            presigning_data,
+            public_key,
+        )
     }
 
-    /// Proceed to the next round.
-    pub fn proceed(&mut self) -> Result<JsValue, JsError> {
-        self.inner.proceed()?;
-        let messages = self.inner.message_queue().drain(..).collect();
-        let round = self.inner.current_round();
-        let messages = RoundMsg::from_round(round, messages);
-        Ok(serde_wasm_bindgen::to_value(&(round, &messages))?)
+    /// Create a signing state machine for the digest of a payload
+    /// using the next unused presignature for an SSID identifier
+    /// in a pool.
+    ///
+    /// The presignature is removed from the pool so it can not
+    /// be used again.
+    #[wasm_bindgen(js_name = "fromPool")]
+    pub fn from_pool(
+        pool: &mut PresignaturePool,
+        id: &str,
+        payload: JsValue,
+        public_key: JsValue,
+    ) -> Result<SignPresignature, JsError> {
+        let payload: Payload = serde_wasm_bindgen::from_value(payload)?;
+        let m = payload.to_bigint()?;
+        let public_key: Vec<u8> = serde_wasm_bindgen::from_value(public_key)?;
+        let public_key = Point::<Secp256k1>::from_bytes(&public_key)?;
+        Ok(Self {
+            inner: native::SignPresignature::from_pool(
+                &mut pool.inner,
+                id,
+                m,
+                public_key,
+            )?,
+        })
     }
+}
 
-    /// Get the signature.
-    pub fn signature(&mut self) -> Result<JsValue, JsError> {
-        // Type declaration for clarity
-        let signature: Option<SigningOutput<Secp256k1>> =
-            self.inner.pick_output().unwrap()?;
-        if let Some(signature) = signature {
-            Ok(serde_wasm_bindgen::to_value(&signature)?)
-        } else {
-            Err(JsError::new("signature not available"))
-        }
+protocol::bindings!(
+    SignPresignature,
+    "Create and verify the signature with the recovery identifier."
+);
+
+impl SignPresignature {
+    fn create(
+        ssid: JsValue,
+        l: usize,
+        m: BigInt,
+        presigning_data: JsValue,
+        public_key: JsValue,
+    ) -> Result<SignPresignature, JsError> {
+        let ssid: SSID<Secp256k1> = serde_wasm_bindgen::from_value(ssid)?;
+        let presigning_data: HashMap<u16, Presignature> =
+            serde_wasm_bindgen::from_value(presigning_data)?;
+        let public_key: Vec<u8> = serde_wasm_bindgen::from_value(public_key)?;
+        let public_key = Point::<Secp256k1>::from_bytes(&public_key)?;
+        Ok(Self {
+            inner: native::SignPresignature::new(
+                ssid,
+                l,
+                m,
+                presigning_data,
+                public_key,
+            )?,
+        })
     }
 }
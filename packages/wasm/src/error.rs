@@ -0,0 +1,217 @@
+//! Errors generated by the native API.
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use cggmp_threshold_ecdsa::{
+    presign::state_machine::Error as PresignError,
+    refresh::state_machine::Error as RefreshError,
+    sign::state_machine::Error as SigningError,
+};
+use multi_party_ecdsa::protocols::multi_party_ecdsa::gg_2020::state_machine::{
+    keygen::Error as KeygenError,
+    sign::{Error as OfflineStageError, SignError},
+};
+
+/// Result type for the native API.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Round and parties responsible when a protocol aborts.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Blame {
+    /// Round that failed.
+    pub round: u16,
+    /// Indices of the parties identified as misbehaving, empty when
+    /// the protocol could not identify them.
+    pub parties: Vec<u16>,
+    /// Reason the round failed.
+    pub reason: String,
+}
+
+/// Error generated by the native API.
+#[derive(Debug, Error)]
+pub enum Error {
+    /// Error generated when a protocol round fails.
+    ///
+    /// Wraps the state machine error with the round and the
+    /// parties responsible so honest parties can exclude them.
+    #[error(
+        "round {} aborted, blame parties {:?}: {}",
+        .0.round,
+        .0.parties,
+        .1
+    )]
+    Abort(Blame, #[source] Box<Error>),
+
+    /// Error generated when the output of a protocol is requested
+    /// before the protocol has finished.
+    #[error("protocol has not finished")]
+    NotFinished,
+
+    /// Error generated when a signature is created before the
+    /// partial signature.
+    #[error(
+        "completed offline stage unavailable, has partial() been called?"
+    )]
+    CompletedOfflineStage,
+
+    /// Error generated when a presignature protocol finished
+    /// without a presignature.
+    #[error("presignature not available")]
+    PresignatureUnavailable,
+
+    /// Error generated when a signing protocol finished
+    /// without a signature.
+    #[error("signature not available")]
+    SignatureUnavailable,
+
+    /// Error generated when a presignature has already been used
+    /// or removed from a pool.
+    #[error("presignature {0} has already been used")]
+    PresignatureUsed(u16),
+
+    /// Error generated when a presignature already exists in a pool.
+    #[error("presignature {0} already exists")]
+    PresignatureExists(u16),
+
+    /// Error generated when a pool has no unused presignatures
+    /// for an SSID.
+    #[error("no presignatures available for {0}")]
+    PresignaturesExhausted(String),
+
+    /// Error generated when an SSID is not in a pool.
+    #[error("unknown ssid {0}")]
+    UnknownSsid(String),
+
+    /// Error generated when typed data is invalid.
+    #[error("invalid typed data: {0}")]
+    TypedData(String),
+
+    /// Error generated when encrypted data cannot be decrypted.
+    #[error("failed to decrypt, wrong key or corrupted data")]
+    Decrypt,
+
+    /// Error generated when data cannot be encrypted.
+    #[error("failed to encrypt")]
+    Encrypt,
+
+    /// Error generated when a message sender is not a party
+    /// to the protocol or is this party.
+    #[error("invalid message sender {0}")]
+    InvalidSender(u16),
+
+    /// Error generated when a message is addressed to another party.
+    #[error("message is addressed to party {0}")]
+    InvalidReceiver(u16),
+
+    /// Error generated when a message is for a round more than one
+    /// round ahead of the protocol.
+    #[error("message for round {0} is too far ahead")]
+    InvalidRound(u16),
+
+    /// Error generated when the state of a protocol is persisted
+    /// in a round where it cannot be captured.
+    #[error("protocol state cannot be persisted in round {0}")]
+    NotResumable(u16),
+
+    /// Error generated when a message is received for a phase
+    /// of a protocol that has already completed.
+    #[error("unexpected message from party {0}")]
+    UnexpectedMessage(u16),
+
+    /// Error generated when a key share is not internally consistent.
+    #[error("invalid key share: {0}")]
+    InvalidKeyShare(String),
+
+    /// Error generated when the public key hash reported by
+    /// parties does not match the public key of a key share.
+    #[error("public key does not match for parties {0:?}")]
+    PublicKeyMismatch(Vec<u16>),
+
+    /// Error generated when a signature fails verification.
+    #[error("failed to verify signature: {0}")]
+    VerifySignature(String),
+
+    /// Error generated encoding a value.
+    #[error(transparent)]
+    Encode(#[from] rmp_serde::encode::Error),
+
+    /// Error generated decoding a value.
+    #[error(transparent)]
+    Decode(#[from] rmp_serde::decode::Error),
+
+    /// Error generated by GG2020 key generation.
+    #[error(transparent)]
+    Keygen(#[from] KeygenError),
+
+    /// Error generated by the GG2020 offline stage.
+    #[error(transparent)]
+    OfflineStage(#[from] OfflineStageError),
+
+    /// Error generated by GG2020 signing.
+    #[error(transparent)]
+    Sign(#[from] SignError),
+
+    /// Error generated by CGGMP key refresh.
+    #[error(transparent)]
+    Refresh(#[from] RefreshError),
+
+    /// Error generated by CGGMP presigning.
+    #[error(transparent)]
+    Presign(#[from] PresignError),
+
+    /// Error generated by CGGMP signing.
+    #[error(transparent)]
+    Signing(#[from] SigningError),
+}
+
+impl Error {
+    /// Blame for the parties responsible when a protocol aborts.
+    pub fn blame(&self) -> Option<&Blame> {
+        match self {
+            Self::Abort(blame, _) => Some(blame),
+            _ => None,
+        }
+    }
+
+    /// Wrap a state machine error that occurred in a round.
+    ///
+    /// The parties reported by the state machine are blamed; when
+    /// there are none and the error was caused by a message then
+    /// the `sender` of the message is blamed.
+    pub(crate) fn abort(
+        round: u16,
+        sender: Option<u16>,
+        error: Error,
+    ) -> Self {
+        let mut parties = bad_actors(&error);
+        if parties.is_empty() {
+            parties.extend(sender);
+        }
+        let blame = Blame {
+            round,
+            parties,
+            reason: error.to_string(),
+        };
+        Self::Abort(blame, Box::new(error))
+    }
+}
+
+/// Parties reported as misbehaving by a state machine error.
+///
+/// The GG2020 and CGGMP errors do not share a trait for blame but
+/// report the culprits as zero-based `bad_actors` in an `ErrorType`
+/// which is included in the debug representation of the error.
+fn bad_actors(error: &Error) -> Vec<u16> {
+    let debug = format!("{:?}", error);
+    let mut parties = debug
+        .split("bad_actors: [")
+        .skip(1)
+        .filter_map(|list| list.split(']').next())
+        .flat_map(|list| list.split(','))
+        .filter_map(|index| index.trim().parse::<u16>().ok())
+        .map(|index| index + 1)
+        .collect::<Vec<_>>();
+    parties.sort_unstable();
+    parties.dedup();
+    parties
+}
@@ -1,71 +1,22 @@
 //! Message signing.
-use curv::{arithmetic::Converter, elliptic::curves::Secp256k1, BigInt};
-use multi_party_ecdsa::protocols::multi_party_ecdsa::gg_2020::{
-    party_i::{verify, SignatureRecid},
-    state_machine::{
-        keygen::LocalKey,
-        sign::{
-            CompletedOfflineStage, OfflineProtocolMessage, OfflineStage,
-            PartialSignature, SignManual,
-        },
-    },
+use curv::elliptic::curves::Secp256k1;
+use multi_party_ecdsa::protocols::multi_party_ecdsa::gg_2020::state_machine::{
+    keygen::LocalKey,
+    sign::PartialSignature,
 };
 
-use round_based::{Msg, StateMachine};
-use serde::{Deserialize, Serialize};
 use std::convert::TryInto;
 use wasm_bindgen::prelude::*;
 
-//use crate::{console_log, log};
-
-const ERR_COMPLETED_OFFLINE_STAGE: &str =
-    "completed offline stage unavailable, has partial() been called?";
-
-/// Wrapper for a round `Msg` that includes the round
-/// number so that we can ensure round messages are grouped
-/// together and out of order messages can thus be handled correctly.
-#[derive(Serialize)]
-struct RoundMsg {
-    round: u16,
-    sender: u16,
-    receiver: Option<u16>,
-    body: OfflineProtocolMessage,
-}
-
-impl RoundMsg {
-    fn from_round(
-        round: u16,
-        messages: Vec<Msg<<OfflineStage as StateMachine>::MessageBody>>,
-    ) -> Vec<Self> {
-        messages
-            .into_iter()
-            .map(|m| RoundMsg {
-                round,
-                sender: m.sender,
-                receiver: m.receiver,
-                body: m.body,
-            })
-            .collect::<Vec<_>>()
-    }
-}
+use crate::native::{self, Payload};
+use crate::protocol;
 
-/// Signature generated by a signer.
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Signature {
-    /// The generated ECDSA signature.
-    pub signature: SignatureRecid,
-    /// The public key.
-    #[serde(rename = "publicKey")]
-    pub public_key: Vec<u8>,
-    /// Address generated from the public key.
-    pub address: String,
-}
+//use crate::{console_log, log};
 
 /// Round-based signing protocol.
 #[wasm_bindgen]
 pub struct Signer {
-    inner: OfflineStage,
-    completed: Option<(CompletedOfflineStage, BigInt)>,
+    inner: native::Signer,
 }
 
 #[wasm_bindgen]
@@ -83,35 +34,25 @@ impl Signer {
         let local_key: LocalKey<Secp256k1> =
             serde_wasm_bindgen::from_value(local_key)?;
         Ok(Signer {
-            inner: OfflineStage::new(index, participants, local_key)?,
-            completed: None,
+            inner: native::Signer::new(index, participants, local_key)?,
         })
     }
 
-    /// Handle an incoming message.
-    #[wasm_bindgen(js_name = "handleIncoming")]
-    pub fn handle_incoming(
+    /// Compute the digest of a payload and generate the partial
+    /// signature for the digest.
+    ///
+    /// The payload is an object with a `kind` of `keccak256`,
+    /// `personal` (EIP-191) or `typedData` (EIP-712) and the
+    /// `value` to hash; the digest is included in the signature
+    /// returned by `create()`.
+    #[wasm_bindgen(js_name = "partialPayload")]
+    pub fn partial_payload(
         &mut self,
-        message: JsValue,
-    ) -> Result<(), JsError> {
-        let message: Msg<<OfflineStage as StateMachine>::MessageBody> =
-            serde_wasm_bindgen::from_value(message)?;
-
-        self.inner.handle_incoming(message)?;
-        Ok(())
-    }
-
-    /// Proceed to the next round.
-    pub fn proceed(&mut self) -> Result<JsValue, JsError> {
-        if self.inner.wants_to_proceed() {
-            self.inner.proceed()?;
-            let messages = self.inner.message_queue().drain(..).collect();
-            let round = self.inner.current_round();
-            let messages = RoundMsg::from_round(round, messages);
-            Ok(serde_wasm_bindgen::to_value(&(round, &messages))?)
-        } else {
-            Ok(serde_wasm_bindgen::to_value(&false)?)
-        }
+        payload: JsValue,
+    ) -> Result<JsValue, JsError> {
+        let payload: Payload = serde_wasm_bindgen::from_value(payload)?;
+        let partial = self.inner.partial_payload(&payload)?;
+        Ok(serde_wasm_bindgen::to_value(&partial)?)
     }
 
     /// Generate the completed offline stage and store the result
@@ -122,13 +63,7 @@ impl Signer {
     pub fn partial(&mut self, message: JsValue) -> Result<JsValue, JsError> {
         let message: Vec<u8> = serde_wasm_bindgen::from_value(message)?;
         let message: [u8; 32] = message.as_slice().try_into()?;
-        let completed_offline_stage = self.inner.pick_output().unwrap()?;
-        let data = BigInt::from_bytes(&message);
-        let (_sign, partial) =
-            SignManual::new(data.clone(), completed_offline_stage.clone())?;
-
-        self.completed = Some((completed_offline_stage, data));
-
+        let partial = self.inner.partial(message)?;
         Ok(serde_wasm_bindgen::to_value(&partial)?)
     }
 
@@ -136,28 +71,30 @@ impl Signer {
     pub fn create(&mut self, partials: JsValue) -> Result<JsValue, JsError> {
         let partials: Vec<PartialSignature> =
             serde_wasm_bindgen::from_value(partials)?;
+        let result = self.inner.create(&partials)?;
+        Ok(serde_wasm_bindgen::to_value(&result)?)
+    }
 
-        let (completed_offline_stage, data) = self
-            .completed
-            .take()
-            .ok_or_else(|| JsError::new(ERR_COMPLETED_OFFLINE_STAGE))?;
-        let pk = completed_offline_stage.public_key().clone();
-
-        let (sign, _partial) =
-            SignManual::new(data.clone(), completed_offline_stage)?;
-
-        let signature = sign.complete(&partials)?;
-        verify(&signature, &pk, &data).map_err(|e| {
-            JsError::new(&format!("failed to verify signature: {:?}", e))
-        })?;
-
-        let public_key = pk.to_bytes(false).to_vec();
-        let result = Signature {
-            signature,
-            address: crate::utils::address(&public_key),
-            public_key,
-        };
+    /// Encrypt the signer with a 32 byte key so it can be persisted,
+    /// for example across a page reload.
+    ///
+    /// Only available once the offline stage has completed and
+    /// `output()` or `partial()` has been called.
+    pub fn serialize(&self, key: Vec<u8>) -> Result<Vec<u8>, JsError> {
+        let key = key.as_slice().try_into()?;
+        Ok(native::Resumable::encrypt(&self.inner, key)?)
+    }
 
-        Ok(serde_wasm_bindgen::to_value(&result)?)
+    /// Decrypt a signer encrypted with a 32 byte key.
+    pub fn deserialize(
+        state: Vec<u8>,
+        key: Vec<u8>,
+    ) -> Result<Signer, JsError> {
+        let key = key.as_slice().try_into()?;
+        Ok(Signer {
+            inner: native::Resumable::decrypt(&state, key)?,
+        })
     }
 }
+
+protocol::bindings!(Signer, "Get the completed offline stage.");
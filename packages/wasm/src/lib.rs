@@ -1,4 +1,7 @@
-//! Webassembly bindings to the GG2020 protocol in [multi-party-ecdsa](https://github.com/ZenGo-X/multi-party-ecdsa) for MPC key generation and signing.
+//! Webassembly bindings to the GG2020 protocol in [multi-party-ecdsa](https://github.com/ZenGo-X/multi-party-ecdsa) and the CGGMP protocol in [cggmp-threshold-ecdsa](https://github.com/webb-tools/cggmp-threshold-ecdsa) for MPC key generation and signing.
+//!
+//! The [native] module exposes the same protocols to Rust code
+//! without Javascript values.
 #![deny(missing_docs)]
 use wasm_bindgen::prelude::*;
 
@@ -17,20 +20,125 @@ pub fn start() {
     log::info!("WASM: module started {:?}", std::thread::current().id());
 }
 
-mod cggmp;
+pub mod cggmp;
+mod error;
 mod gg2020;
+pub mod native;
+mod protocol;
 mod utils;
 
 // Expose these types for API documentation.
 pub use gg2020::keygen::KeyGenerator;
-pub use gg2020::sign::{Signature, Signer};
+pub use gg2020::sign::Signer;
+
+pub use cggmp::keygen::CggmpKeyGenerator;
+pub use cggmp::pool::{PresignaturePool, PresigningBatch};
+pub use cggmp::presign::PreSigning;
+pub use cggmp::refresh::KeyRefresh;
+pub use cggmp::sign::SignPresignature;
+pub use native::{AuxInfo, CggmpKeyShare};
 pub use utils::*;
 
+/// Encode a JSON-RPC message for a binary websocket frame using
+/// an encoding supported by the server (`json`, `msgpack` or `cbor`).
+#[wasm_bindgen]
+pub fn encode(value: JsValue, encoding: String) -> Result<Vec<u8>, JsError> {
+    let value: serde_json::Value = serde_wasm_bindgen::from_value(value)?;
+    match encoding.as_str() {
+        "json" => Ok(serde_json::to_vec(&value)?),
+        "msgpack" => Ok(rmp_serde::to_vec_named(&value)?),
+        "cbor" => {
+            let mut buffer = Vec::new();
+            ciborium::ser::into_writer(&value, &mut buffer)
+                .map_err(|e| JsError::new(&e.to_string()))?;
+            Ok(buffer)
+        }
+        _ => Err(unknown_encoding(&encoding)),
+    }
+}
+
+/// Decode a JSON-RPC message from a binary websocket frame using
+/// an encoding supported by the server (`json`, `msgpack` or `cbor`).
+#[wasm_bindgen]
+pub fn decode(bytes: Vec<u8>, encoding: String) -> Result<JsValue, JsError> {
+    use serde::Serialize;
+    let value: serde_json::Value = match encoding.as_str() {
+        "json" => serde_json::from_slice(&bytes)?,
+        "msgpack" => rmp_serde::from_slice(&bytes)?,
+        "cbor" => ciborium::de::from_reader(&bytes[..])
+            .map_err(|e| JsError::new(&e.to_string()))?,
+        _ => return Err(unknown_encoding(&encoding)),
+    };
+    let serializer = serde_wasm_bindgen::Serializer::json_compatible();
+    Ok(value.serialize(&serializer)?)
+}
+
+fn unknown_encoding(encoding: &str) -> JsError {
+    JsError::new(&format!("unknown encoding {}", encoding))
+}
+
+/// Compress bytes using raw DEFLATE.
+#[wasm_bindgen]
+pub fn compress(bytes: Vec<u8>) -> Vec<u8> {
+    miniz_oxide::deflate::compress_to_vec(&bytes, 6)
+}
+
+/// Decompress bytes using raw DEFLATE.
+#[wasm_bindgen]
+pub fn decompress(bytes: Vec<u8>) -> Result<Vec<u8>, JsError> {
+    const LIMIT: usize = 16 * 1024 * 1024;
+    miniz_oxide::inflate::decompress_to_vec_with_limit(&bytes, LIMIT)
+        .map_err(|e| JsError::new(&format!("{:?}", e.status)))
+}
+
 /// Compute the Keccak256 hash of a value.
 #[wasm_bindgen]
 pub fn keccak256(message: JsValue) -> Result<JsValue, JsError> {
-    use sha3::{Digest, Keccak256};
     let message: Vec<u8> = serde_wasm_bindgen::from_value(message)?;
-    let digest = Keccak256::digest(message).to_vec();
+    let digest = native::keccak256(&message).to_vec();
+    Ok(serde_wasm_bindgen::to_value(&digest)?)
+}
+
+/// Verify a key share is internally consistent.
+///
+/// Checks the secret share against the public share for the party
+/// and that the public shares are consistent with the public key.
+#[wasm_bindgen(js_name = "verifyKeyShare")]
+pub fn verify_key_share(key_share: JsValue) -> Result<(), JsError> {
+    let key_share: KeyShare = serde_wasm_bindgen::from_value(key_share)?;
+    Ok(native::verify_key_share(&key_share)?)
+}
+
+/// Compute the hex-encoded hash of the public key for a key share
+/// to compare with the other parties.
+#[wasm_bindgen(js_name = "publicKeyHash")]
+pub fn public_key_hash(key_share: JsValue) -> Result<String, JsError> {
+    let key_share: KeyShare = serde_wasm_bindgen::from_value(key_share)?;
+    Ok(native::public_key_hash(&key_share))
+}
+
+/// Check the public key hashes reported by every party match
+/// a key share.
+///
+/// The hashes are an array of party number and hash pairs.
+#[wasm_bindgen(js_name = "confirmKeyShare")]
+pub fn confirm_key_share(
+    key_share: JsValue,
+    hashes: JsValue,
+) -> Result<(), JsError> {
+    let key_share: KeyShare = serde_wasm_bindgen::from_value(key_share)?;
+    let hashes: Vec<(u16, String)> = serde_wasm_bindgen::from_value(hashes)?;
+    Ok(native::confirm_key_share(&key_share, &hashes)?)
+}
+
+/// Compute the digest to sign for a payload.
+///
+/// The payload is an object with a `kind` of `keccak256`,
+/// `personal` (EIP-191) or `typedData` (EIP-712) and the
+/// `value` to hash.
+#[wasm_bindgen(js_name = "hashPayload")]
+pub fn hash_payload(payload: JsValue) -> Result<JsValue, JsError> {
+    let payload: native::Payload = serde_wasm_bindgen::from_value(payload)?;
+    let digest = payload.digest()?.to_vec();
     Ok(serde_wasm_bindgen::to_value(&digest)?)
 }
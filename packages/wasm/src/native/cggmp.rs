@@ -0,0 +1,557 @@
+//! CGGMP key generation, key refresh, presigning and signing.
+use std::collections::HashMap;
+
+use curv::{
+    arithmetic::{BitManipulation, Modulo},
+    elliptic::curves::{secp256_k1::Secp256k1, Point, Scalar},
+    BigInt,
+};
+use paillier::{DecryptionKey, EncryptionKey};
+use serde::{Deserialize, Serialize};
+
+use cggmp_threshold_ecdsa::{
+    presign::{
+        state_machine as presign, PreSigningSecrets, PresigningOutput,
+        PresigningTranscript, SSID,
+    },
+    refresh::state_machine as refresh,
+    sign::{state_machine as sign, SigningOutput},
+};
+use multi_party_ecdsa::protocols::multi_party_ecdsa::gg_2020::{
+    party_i::{verify, SignatureRecid},
+    state_machine::keygen::{self, LocalKey},
+};
+
+use super::{
+    driver::{RoundDriver, RoundMsg},
+    hash::digest_bytes,
+    protocol::{Driven, Protocol},
+    KeyGenerator, PresignaturePool,
+};
+use crate::{
+    error::{Error, Result},
+    KeyShare, Parameters, PartySignup, Signature,
+};
+
+/// Output of the presigning protocol for a party.
+pub type Presignature =
+    (PresigningOutput<Secp256k1>, PresigningTranscript<Secp256k1>);
+
+/// Auxiliary information for presigning.
+///
+/// Contains the Paillier keys and ring-Pedersen parameters for
+/// every party indexed by party number.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AuxInfo {
+    /// Paillier encryption keys.
+    #[serde(rename = "paillierKeys")]
+    pub paillier_keys: HashMap<u16, EncryptionKey>,
+    /// Ring-Pedersen modulus `N_hat`.
+    #[serde(rename = "nHats")]
+    pub n_hats: HashMap<u16, BigInt>,
+    /// Ring-Pedersen parameter `s`.
+    pub s: HashMap<u16, BigInt>,
+    /// Ring-Pedersen parameter `t`.
+    pub t: HashMap<u16, BigInt>,
+}
+
+impl From<&LocalKey<Secp256k1>> for AuxInfo {
+    fn from(local_key: &LocalKey<Secp256k1>) -> Self {
+        let mut aux_info = AuxInfo {
+            paillier_keys: HashMap::new(),
+            n_hats: HashMap::new(),
+            s: HashMap::new(),
+            t: HashMap::new(),
+        };
+        let parties = local_key
+            .paillier_key_vec
+            .iter()
+            .zip(local_key.h1_h2_n_tilde_vec.iter());
+        for (index, (ek, statement)) in parties.enumerate() {
+            let number = index as u16 + 1;
+            aux_info.paillier_keys.insert(number, ek.clone());
+            aux_info.n_hats.insert(number, statement.N.clone());
+            aux_info.s.insert(number, statement.g.clone());
+            aux_info.t.insert(number, statement.ni.clone());
+        }
+        aux_info
+    }
+}
+
+/// Generated CGGMP key share.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CggmpKeyShare {
+    /// The key share.
+    #[serde(rename = "keyShare")]
+    pub key_share: KeyShare,
+    /// Auxiliary information for presigning.
+    #[serde(rename = "auxInfo")]
+    pub aux_info: AuxInfo,
+}
+
+impl CggmpKeyShare {
+    /// Secrets for this party used during presigning.
+    pub fn presigning_secrets(&self) -> PreSigningSecrets {
+        let local_key = &self.key_share.local_key;
+        PreSigningSecrets {
+            x_i: local_key.keys_linear.x_i.to_bigint(),
+            y_i: None,
+            ek: local_key.paillier_key_vec[local_key.i as usize - 1].clone(),
+            dk: local_key.paillier_dk.clone(),
+        }
+    }
+
+    /// Paillier decryption key for this party.
+    pub fn paillier_dk(&self) -> &DecryptionKey {
+        &self.key_share.local_key.paillier_dk
+    }
+}
+
+impl From<LocalKey<Secp256k1>> for CggmpKeyShare {
+    fn from(local_key: LocalKey<Secp256k1>) -> Self {
+        let aux_info = AuxInfo::from(&local_key);
+        Self {
+            key_share: local_key.into(),
+            aux_info,
+        }
+    }
+}
+
+/// Message for CGGMP key generation.
+///
+/// Key generation runs the distributed key generation rounds
+/// followed by the auxiliary information rounds which generate
+/// fresh Paillier keys and ring-Pedersen parameters for each party.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CggmpKeygenMessage {
+    /// Distributed key generation message.
+    Keygen(keygen::ProtocolMessage),
+    /// Auxiliary information message.
+    AuxInfo(refresh::ProtocolMessage),
+}
+
+/// Phase of CGGMP key generation.
+enum Phase {
+    Keygen(KeyGenerator),
+    AuxInfo(KeyRefresh),
+}
+
+/// CGGMP key generation.
+pub struct CggmpKeyGenerator {
+    parameters: Parameters,
+    phase: Phase,
+    keygen_rounds: u16,
+    /// Auxiliary information messages received from parties
+    /// that finished key generation before us.
+    pending: Vec<RoundMsg<refresh::ProtocolMessage>>,
+    /// Key generation messages not yet taken by `proceed()`
+    /// when key generation finished.
+    outgoing: Vec<RoundMsg<CggmpKeygenMessage>>,
+}
+
+impl CggmpKeyGenerator {
+    /// Create a key generator.
+    pub fn new(
+        parameters: Parameters,
+        party_signup: PartySignup,
+    ) -> Result<CggmpKeyGenerator> {
+        let inner = KeyGenerator::new(parameters.clone(), party_signup)?;
+        Ok(Self {
+            keygen_rounds: inner.total_rounds().unwrap_or_default(),
+            phase: Phase::Keygen(inner),
+            parameters,
+            pending: Vec::new(),
+            outgoing: Vec::new(),
+        })
+    }
+
+    /// Start the auxiliary information phase once key generation
+    /// has finished and deliver any messages that arrived early.
+    fn advance(&mut self) -> Result<()> {
+        let inner = match &mut self.phase {
+            Phase::Keygen(inner) if inner.is_finished() => inner,
+            _ => return Ok(()),
+        };
+        let (_, keygen_messages) = inner.proceed()?;
+        let local_key = inner.output()?.local_key;
+        self.outgoing.extend(
+            keygen_messages
+                .into_iter()
+                .map(|m| m.map_body(CggmpKeygenMessage::Keygen)),
+        );
+
+        let old_to_new = (1..=self.parameters.parties)
+            .map(|number| (number, number))
+            .collect::<HashMap<_, _>>();
+        let mut inner = KeyRefresh::new(
+            self.parameters.clone(),
+            Some(local_key),
+            None,
+            &old_to_new,
+        )?;
+        for message in std::mem::take(&mut self.pending) {
+            inner
+                .handle_incoming(aux_info_round(self.keygen_rounds, message)?)
+                .map_err(|e| aux_info_blame(self.keygen_rounds, e))?;
+        }
+        self.phase = Phase::AuxInfo(inner);
+        Ok(())
+    }
+}
+
+impl Protocol for CggmpKeyGenerator {
+    type Message = CggmpKeygenMessage;
+    type Output = CggmpKeyShare;
+
+    fn handle_incoming(
+        &mut self,
+        message: RoundMsg<Self::Message>,
+    ) -> Result<()> {
+        let (round, sender, receiver) =
+            (message.round, message.sender, message.receiver);
+        match (&mut self.phase, message.body) {
+            (Phase::Keygen(inner), CggmpKeygenMessage::Keygen(body)) => {
+                inner.handle_incoming(RoundMsg {
+                    round,
+                    sender,
+                    receiver,
+                    body,
+                })?;
+            }
+            (Phase::Keygen(_), CggmpKeygenMessage::AuxInfo(body)) => {
+                // Until we send our first auxiliary information
+                // message no party can be past the first round.
+                if round > self.keygen_rounds.saturating_add(1) {
+                    return Err(Error::InvalidRound(round));
+                }
+                self.pending.push(RoundMsg {
+                    round,
+                    sender,
+                    receiver,
+                    body,
+                });
+            }
+            (Phase::AuxInfo(inner), CggmpKeygenMessage::AuxInfo(body)) => {
+                inner
+                    .handle_incoming(aux_info_round(
+                        self.keygen_rounds,
+                        RoundMsg {
+                            round,
+                            sender,
+                            receiver,
+                            body,
+                        },
+                    )?)
+                    .map_err(|e| aux_info_blame(self.keygen_rounds, e))?;
+            }
+            (Phase::AuxInfo(_), CggmpKeygenMessage::Keygen(_)) => {
+                return Err(Error::UnexpectedMessage(sender));
+            }
+        }
+        self.advance()
+    }
+
+    fn wants_to_proceed(&self) -> bool {
+        !self.outgoing.is_empty()
+            || match &self.phase {
+                Phase::Keygen(inner) => inner.wants_to_proceed(),
+                Phase::AuxInfo(inner) => inner.wants_to_proceed(),
+            }
+    }
+
+    fn is_finished(&self) -> bool {
+        match &self.phase {
+            Phase::Keygen(_) => false,
+            Phase::AuxInfo(inner) => inner.is_finished(),
+        }
+    }
+
+    /// Current round.
+    ///
+    /// Rounds are numbered consecutively across both phases.
+    fn current_round(&self) -> u16 {
+        match &self.phase {
+            Phase::Keygen(inner) => inner.current_round(),
+            Phase::AuxInfo(inner) => {
+                self.keygen_rounds + inner.current_round()
+            }
+        }
+    }
+
+    /// Total number of rounds, known once the auxiliary
+    /// information phase has started.
+    fn total_rounds(&self) -> Option<u16> {
+        match &self.phase {
+            Phase::Keygen(_) => None,
+            Phase::AuxInfo(inner) => inner
+                .total_rounds()
+                .map(|rounds| self.keygen_rounds + rounds),
+        }
+    }
+
+    fn proceed(&mut self) -> Result<(u16, Vec<RoundMsg<Self::Message>>)> {
+        let mut messages = std::mem::take(&mut self.outgoing);
+        match &mut self.phase {
+            Phase::Keygen(inner) => {
+                let (_, keygen_messages) = inner.proceed()?;
+                messages.extend(
+                    keygen_messages
+                        .into_iter()
+                        .map(|m| m.map_body(CggmpKeygenMessage::Keygen)),
+                );
+            }
+            Phase::AuxInfo(inner) => {
+                let offset = self.keygen_rounds;
+                let (_, aux_messages) =
+                    inner.proceed().map_err(|e| aux_info_blame(offset, e))?;
+                messages.extend(aux_messages.into_iter().map(|mut m| {
+                    m.round += offset;
+                    m.map_body(CggmpKeygenMessage::AuxInfo)
+                }));
+            }
+        }
+        self.advance()?;
+        Ok((self.current_round(), messages))
+    }
+
+    /// Create the key share and auxiliary information.
+    fn output(&mut self) -> Result<CggmpKeyShare> {
+        match &mut self.phase {
+            Phase::Keygen(_) => Err(Error::NotFinished),
+            Phase::AuxInfo(inner) => Ok(inner.output()?.local_key.into()),
+        }
+    }
+}
+
+/// Convert the round number of an auxiliary information message
+/// to the round number for the key refresh state machine.
+fn aux_info_round<B>(
+    keygen_rounds: u16,
+    mut message: RoundMsg<B>,
+) -> Result<RoundMsg<B>> {
+    message.round = message
+        .round
+        .checked_sub(keygen_rounds)
+        .ok_or(Error::UnexpectedMessage(message.sender))?;
+    Ok(message)
+}
+
+/// Number the round of an auxiliary information failure
+/// consecutively with the key generation rounds.
+fn aux_info_blame(keygen_rounds: u16, error: Error) -> Error {
+    match error {
+        Error::Abort(mut blame, source) => {
+            blame.round += keygen_rounds;
+            Error::Abort(blame, source)
+        }
+        error => error,
+    }
+}
+
+/// Key refresh.
+pub struct KeyRefresh {
+    inner: RoundDriver<refresh::KeyRefresh>,
+}
+
+impl KeyRefresh {
+    /// Create a key refresh.
+    ///
+    /// Existing parties supply their `local_key` and new parties
+    /// supply their `new_party_index`; `old_to_new` maps the existing
+    /// party indices to their indices after the refresh.
+    pub fn new(
+        parameters: Parameters,
+        local_key: Option<LocalKey<Secp256k1>>,
+        new_party_index: Option<u16>,
+        old_to_new: &HashMap<u16, u16>,
+    ) -> Result<KeyRefresh> {
+        Ok(Self {
+            inner: RoundDriver::new(refresh::KeyRefresh::new(
+                local_key,
+                new_party_index,
+                old_to_new,
+                parameters.threshold,
+                parameters.parties,
+            )?),
+        })
+    }
+}
+
+impl Driven for KeyRefresh {
+    type Machine = refresh::KeyRefresh;
+    type Output = KeyShare;
+
+    fn driver(&self) -> &RoundDriver<refresh::KeyRefresh> {
+        &self.inner
+    }
+
+    fn driver_mut(&mut self) -> &mut RoundDriver<refresh::KeyRefresh> {
+        &mut self.inner
+    }
+
+    fn finish(&mut self, local_key: LocalKey<Secp256k1>) -> Result<KeyShare> {
+        Ok(local_key.into())
+    }
+}
+
+/// Pre signing.
+pub struct PreSigning {
+    inner: RoundDriver<presign::PreSigning>,
+}
+
+impl PreSigning {
+    /// Create a presignature state machine.
+    pub fn new(
+        ssid: SSID<Secp256k1>,
+        secrets: PreSigningSecrets,
+        s: HashMap<u16, BigInt>,
+        t: HashMap<u16, BigInt>,
+        n_hats: HashMap<u16, BigInt>,
+        l: usize,
+    ) -> Result<PreSigning> {
+        Ok(Self {
+            inner: RoundDriver::new(presign::PreSigning::new(
+                ssid, secrets, s, t, n_hats, l,
+            )?),
+        })
+    }
+
+    /// Create a presignature state machine using the secrets and
+    /// auxiliary information from a key share.
+    pub fn from_key_share(
+        ssid: SSID<Secp256k1>,
+        key_share: &CggmpKeyShare,
+        l: usize,
+    ) -> Result<PreSigning> {
+        let AuxInfo { s, t, n_hats, .. } = key_share.aux_info.clone();
+        Self::new(ssid, key_share.presigning_secrets(), s, t, n_hats, l)
+    }
+}
+
+impl Driven for PreSigning {
+    type Machine = presign::PreSigning;
+    type Output = Presignature;
+
+    fn driver(&self) -> &RoundDriver<presign::PreSigning> {
+        &self.inner
+    }
+
+    fn driver_mut(&mut self) -> &mut RoundDriver<presign::PreSigning> {
+        &mut self.inner
+    }
+
+    fn finish(
+        &mut self,
+        output: Option<Presignature>,
+    ) -> Result<Presignature> {
+        output.ok_or(Error::PresignatureUnavailable)
+    }
+}
+
+/// Sign using a presignature.
+pub struct SignPresignature {
+    inner: RoundDriver<sign::Signing>,
+    nonce: Point<Secp256k1>,
+    public_key: Point<Secp256k1>,
+}
+
+impl SignPresignature {
+    /// Create a signing state machine.
+    ///
+    /// The `public_key` is the public key for the key shares
+    /// used to generate the presignatures and is used to verify
+    /// the signature.
+    pub fn new(
+        ssid: SSID<Secp256k1>,
+        l: usize,
+        m: BigInt,
+        presigning_data: HashMap<u16, Presignature>,
+        public_key: Point<Secp256k1>,
+    ) -> Result<SignPresignature> {
+        let nonce = presigning_data
+            .get(&(l as u16))
+            .map(|(output, _)| output.R.clone())
+            .ok_or(Error::PresignatureUnavailable)?;
+        Ok(Self {
+            inner: RoundDriver::new(sign::Signing::new(
+                ssid,
+                l,
+                m,
+                presigning_data,
+            )?),
+            nonce,
+            public_key,
+        })
+    }
+
+    /// Create a signing state machine using the next unused
+    /// presignature for an SSID in a pool.
+    ///
+    /// The presignature is removed from the pool so it can not
+    /// be used again.
+    pub fn from_pool(
+        pool: &mut PresignaturePool,
+        id: &str,
+        m: BigInt,
+        public_key: Point<Secp256k1>,
+    ) -> Result<SignPresignature> {
+        let taken = pool.take(id)?;
+        let (ssid, l) = (taken.ssid.clone(), taken.index as usize);
+        Self::new(ssid, l, m, taken.presigning_data(), public_key)
+    }
+}
+
+impl Driven for SignPresignature {
+    type Machine = sign::Signing;
+    type Output = Signature;
+
+    fn driver(&self) -> &RoundDriver<sign::Signing> {
+        &self.inner
+    }
+
+    fn driver_mut(&mut self) -> &mut RoundDriver<sign::Signing> {
+        &mut self.inner
+    }
+
+    /// Create and verify the signature.
+    ///
+    /// The signature is normalized to the lower half of the
+    /// curve order and includes the recovery identifier.
+    fn finish(
+        &mut self,
+        output: Option<SigningOutput<Secp256k1>>,
+    ) -> Result<Signature> {
+        let output = output.ok_or(Error::SignatureUnavailable)?;
+
+        let q = Scalar::<Secp256k1>::group_order();
+        let x = self.nonce.x_coord().ok_or(Error::SignatureUnavailable)?;
+        let y = self.nonce.y_coord().ok_or(Error::SignatureUnavailable)?;
+
+        let mut recid = if y.test_bit(0) { 1 } else { 0 };
+        if &x >= q {
+            recid |= 2;
+        }
+        let mut s = BigInt::modulus(&output.sigma, q);
+        if s > q - &s {
+            s = q - &s;
+            recid ^= 1;
+        }
+
+        let signature = SignatureRecid {
+            r: Scalar::from_bigint(&output.r),
+            s: Scalar::from_bigint(&s),
+            recid,
+        };
+        verify(&signature, &self.public_key, &output.m)
+            .map_err(|e| Error::VerifySignature(format!("{:?}", e)))?;
+
+        let public_key = self.public_key.to_bytes(false).to_vec();
+        Ok(Signature {
+            signature,
+            address: crate::utils::address(&public_key),
+            public_key,
+            digest: digest_bytes(&output.m),
+        })
+    }
+}
@@ -0,0 +1,243 @@
+//! Drive round-based state machines.
+//!
+//! A [RoundDriver] wraps any `round_based::StateMachine` so that
+//! callers can deliver messages in the order they arrive:
+//!
+//! * Messages for the next round are buffered until the state
+//!   machine reaches the round. Messages for later rounds are
+//!   rejected, an honest party is never more than one round ahead
+//!   so a peer cannot grow the buffer with arbitrary rounds.
+//! * Duplicate messages (the same round and sender) are ignored.
+//! * Messages from an unknown sender or addressed to another
+//!   party are rejected.
+//! * Outgoing messages are tagged with the round they belong to.
+//! * State machine errors are wrapped in [Error::Abort] with the
+//!   round and the parties to blame.
+//!
+//! The caller loop is the same for every protocol:
+//!
+//! ```ignore
+//! while !driver.is_finished() {
+//!     for message in receive() {
+//!         driver.handle_incoming(message)?;
+//!     }
+//!     while driver.wants_to_proceed() {
+//!         let (_round, messages) = driver.proceed()?;
+//!         send(messages);
+//!     }
+//! }
+//! ```
+use std::collections::{BTreeMap, HashSet};
+
+use round_based::{Msg, StateMachine};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+
+/// Wrapper for a round `Msg` that includes the round
+/// number so that we can ensure round messages are grouped
+/// together and out of order messages can thus be handled correctly.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoundMsg<B> {
+    /// Round number.
+    pub round: u16,
+    /// Party index of the sender.
+    pub sender: u16,
+    /// Party index of the receiver or `None` for a broadcast.
+    pub receiver: Option<u16>,
+    /// Message body.
+    pub body: B,
+}
+
+impl<B> RoundMsg<B> {
+    /// Tag a message with a round number.
+    pub fn from_msg(round: u16, message: Msg<B>) -> Self {
+        Self {
+            round,
+            sender: message.sender,
+            receiver: message.receiver,
+            body: message.body,
+        }
+    }
+
+    /// Convert to a message without the round number.
+    pub fn into_msg(self) -> Msg<B> {
+        Msg {
+            sender: self.sender,
+            receiver: self.receiver,
+            body: self.body,
+        }
+    }
+
+    /// Map the message body.
+    pub fn map_body<T>(self, f: impl FnOnce(B) -> T) -> RoundMsg<T> {
+        RoundMsg {
+            round: self.round,
+            sender: self.sender,
+            receiver: self.receiver,
+            body: f(self.body),
+        }
+    }
+}
+
+/// Buffering and deduplicating driver for a state machine.
+pub struct RoundDriver<M: StateMachine> {
+    machine: M,
+    /// Messages for rounds the state machine has not reached.
+    buffer: BTreeMap<u16, Vec<RoundMsg<M::MessageBody>>>,
+    /// Round and sender for every accepted message.
+    seen: HashSet<(u16, u16)>,
+    /// Number of accepted messages for each round.
+    received: BTreeMap<u16, usize>,
+    /// Outgoing messages that have not been taken by `proceed()`.
+    outgoing: Vec<RoundMsg<M::MessageBody>>,
+}
+
+impl<M> RoundDriver<M>
+where
+    M: StateMachine,
+    Error: From<M::Err>,
+{
+    /// Create a driver for a state machine.
+    pub fn new(machine: M) -> Self {
+        let mut driver = Self {
+            machine,
+            buffer: BTreeMap::new(),
+            seen: HashSet::new(),
+            received: BTreeMap::new(),
+            outgoing: Vec::new(),
+        };
+        // Messages generated by the constructor.
+        driver.collect();
+        driver
+    }
+
+    /// Handle an incoming message.
+    ///
+    /// Returns `false` if the message is a duplicate and was ignored.
+    pub fn handle_incoming(
+        &mut self,
+        message: RoundMsg<M::MessageBody>,
+    ) -> Result<bool> {
+        let party = self.machine.party_ind();
+        if message.sender == 0
+            || message.sender > self.machine.parties()
+            || message.sender == party
+        {
+            return Err(Error::InvalidSender(message.sender));
+        }
+        if let Some(receiver) = message.receiver {
+            if receiver != party {
+                return Err(Error::InvalidReceiver(receiver));
+            }
+        }
+        if message.round > self.machine.current_round().saturating_add(1) {
+            return Err(Error::InvalidRound(message.round));
+        }
+        if !self.seen.insert((message.round, message.sender)) {
+            return Ok(false);
+        }
+        *self.received.entry(message.round).or_default() += 1;
+
+        if message.round > self.machine.current_round() {
+            self.buffer.entry(message.round).or_default().push(message);
+        } else {
+            self.deliver(message)?;
+            self.flush()?;
+        }
+        Ok(true)
+    }
+
+    /// Whether there are outgoing messages to send or the state
+    /// machine has received the messages it needs to proceed.
+    pub fn wants_to_proceed(&self) -> bool {
+        !self.outgoing.is_empty() || self.machine.wants_to_proceed()
+    }
+
+    /// Proceed to the next round if the state machine is ready and
+    /// return the current round and the messages to send to the
+    /// other parties.
+    pub fn proceed(
+        &mut self,
+    ) -> Result<(u16, Vec<RoundMsg<M::MessageBody>>)> {
+        if self.machine.wants_to_proceed() {
+            let round = self.machine.current_round();
+            self.machine
+                .proceed()
+                .map_err(|e| Error::abort(round, None, e.into()))?;
+            self.collect();
+            self.flush()?;
+        }
+        let messages = std::mem::take(&mut self.outgoing);
+        Ok((self.machine.current_round(), messages))
+    }
+
+    /// Whether the protocol has finished.
+    pub fn is_finished(&self) -> bool {
+        self.machine.is_finished()
+    }
+
+    /// Current round of the state machine.
+    pub fn current_round(&self) -> u16 {
+        self.machine.current_round()
+    }
+
+    /// Total number of rounds when known.
+    pub fn total_rounds(&self) -> Option<u16> {
+        self.machine.total_rounds()
+    }
+
+    /// Number of messages expected from the other parties
+    /// in each round.
+    pub fn expected_messages(&self) -> usize {
+        self.machine.parties() as usize - 1
+    }
+
+    /// Number of messages received for a round.
+    pub fn received_messages(&self, round: u16) -> usize {
+        self.received.get(&round).copied().unwrap_or_default()
+    }
+
+    /// Take the output of the state machine.
+    pub fn pick_output(&mut self) -> Result<M::Output> {
+        Ok(self.machine.pick_output().ok_or(Error::NotFinished)??)
+    }
+
+    /// Move messages generated by the state machine to the
+    /// outgoing queue.
+    fn collect(&mut self) {
+        let round = self.machine.current_round();
+        let messages = self.machine.message_queue().drain(..);
+        self.outgoing
+            .extend(messages.map(|m| RoundMsg::from_msg(round, m)));
+    }
+
+    /// Deliver buffered messages for rounds the state machine
+    /// has reached.
+    fn flush(&mut self) -> Result<()> {
+        loop {
+            let current = self.machine.current_round();
+            let round = match self.buffer.keys().next() {
+                Some(round) if *round <= current => *round,
+                _ => return Ok(()),
+            };
+            for message in self.buffer.remove(&round).unwrap_or_default() {
+                self.deliver(message)?;
+            }
+        }
+    }
+
+    /// Deliver a message to the state machine and collect the
+    /// messages it generates.
+    ///
+    /// Failures blame the parties reported by the state machine
+    /// or the sender of the message.
+    fn deliver(&mut self, message: RoundMsg<M::MessageBody>) -> Result<()> {
+        let (round, sender) = (message.round, message.sender);
+        self.machine
+            .handle_incoming(message.into_msg())
+            .map_err(|e| Error::abort(round, Some(sender), e.into()))?;
+        self.collect();
+        Ok(())
+    }
+}
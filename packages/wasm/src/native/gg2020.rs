@@ -0,0 +1,263 @@
+//! GG2020 key generation and signing.
+use super::{
+    driver::{RoundDriver, RoundMsg},
+    hash::digest_bytes,
+    protocol::{Driven, Protocol, Resumable},
+    Payload,
+};
+use crate::{
+    error::{Error, Result},
+    KeyShare, Parameters, PartySignup, Signature,
+};
+use curv::{arithmetic::Converter, elliptic::curves::Secp256k1, BigInt};
+use multi_party_ecdsa::protocols::multi_party_ecdsa::gg_2020::{
+    party_i::verify,
+    state_machine::{
+        keygen::{Keygen, LocalKey},
+        sign::{
+            CompletedOfflineStage, OfflineProtocolMessage, OfflineStage,
+            PartialSignature, SignManual,
+        },
+    },
+};
+use serde::{Deserialize, Serialize};
+
+/// Round-based key share generator.
+pub struct KeyGenerator {
+    inner: RoundDriver<Keygen>,
+}
+
+impl KeyGenerator {
+    /// Create a key generator.
+    pub fn new(
+        parameters: Parameters,
+        party_signup: PartySignup,
+    ) -> Result<KeyGenerator> {
+        Ok(Self {
+            inner: RoundDriver::new(Keygen::new(
+                party_signup.number,
+                parameters.threshold,
+                parameters.parties,
+            )?),
+        })
+    }
+}
+
+impl Driven for KeyGenerator {
+    type Machine = Keygen;
+    type Output = KeyShare;
+
+    fn driver(&self) -> &RoundDriver<Keygen> {
+        &self.inner
+    }
+
+    fn driver_mut(&mut self) -> &mut RoundDriver<Keygen> {
+        &mut self.inner
+    }
+
+    fn finish(&mut self, local_key: LocalKey<Secp256k1>) -> Result<KeyShare> {
+        Ok(local_key.into())
+    }
+}
+
+/// Round-based signing protocol.
+///
+/// The offline stage state machine cannot be serialized so the
+/// signer is [Resumable] only once the offline stage has completed.
+pub struct Signer {
+    stage: Stage,
+    offline: Option<CompletedOfflineStage>,
+    completed: Option<(CompletedOfflineStage, BigInt)>,
+}
+
+/// Stage of the signing protocol.
+enum Stage {
+    /// Running the offline stage.
+    Offline(RoundDriver<OfflineStage>),
+    /// Restored from a snapshot taken after the offline stage
+    /// completed.
+    Restored {
+        round: u16,
+        total_rounds: Option<u16>,
+    },
+}
+
+/// State of a [Signer] after the offline stage has completed.
+#[derive(Serialize, Deserialize)]
+pub struct SignerSnapshot {
+    round: u16,
+    total_rounds: Option<u16>,
+    offline: CompletedOfflineStage,
+    completed: Option<(CompletedOfflineStage, BigInt)>,
+}
+
+impl Signer {
+    /// Create a signer.
+    pub fn new(
+        index: u16,
+        participants: Vec<u16>,
+        local_key: LocalKey<Secp256k1>,
+    ) -> Result<Signer> {
+        Ok(Signer {
+            stage: Stage::Offline(RoundDriver::new(OfflineStage::new(
+                index,
+                participants,
+                local_key,
+            )?)),
+            offline: None,
+            completed: None,
+        })
+    }
+
+    /// Compute the digest of a payload and generate the partial
+    /// signature for the digest.
+    ///
+    /// Prefer this to `partial()` so the digest is computed the
+    /// same way by every participant.
+    pub fn partial_payload(
+        &mut self,
+        payload: &Payload,
+    ) -> Result<PartialSignature> {
+        self.partial(payload.digest()?)
+    }
+
+    /// Generate the completed offline stage and store the result
+    /// internally to be used when `create()` is called.
+    ///
+    /// Return a partial signature that must be sent to the other
+    /// signing participants.
+    pub fn partial(&mut self, message: [u8; 32]) -> Result<PartialSignature> {
+        let completed_offline_stage = match self.offline.clone() {
+            Some(completed_offline_stage) => completed_offline_stage,
+            None => self.output()?,
+        };
+        let data = BigInt::from_bytes(&message);
+        let (_sign, partial) =
+            SignManual::new(data.clone(), completed_offline_stage.clone())?;
+
+        self.completed = Some((completed_offline_stage, data));
+
+        Ok(partial)
+    }
+
+    /// Create and verify the signature.
+    pub fn create(
+        &mut self,
+        partials: &[PartialSignature],
+    ) -> Result<Signature> {
+        let (completed_offline_stage, data) =
+            self.completed.take().ok_or(Error::CompletedOfflineStage)?;
+        let pk = completed_offline_stage.public_key().clone();
+
+        let (sign, _partial) =
+            SignManual::new(data.clone(), completed_offline_stage)?;
+
+        let signature = sign.complete(partials)?;
+        verify(&signature, &pk, &data)
+            .map_err(|e| Error::VerifySignature(format!("{:?}", e)))?;
+
+        let public_key = pk.to_bytes(false).to_vec();
+        Ok(Signature {
+            signature,
+            address: crate::utils::address(&public_key),
+            public_key,
+            digest: digest_bytes(&data),
+        })
+    }
+}
+
+impl Protocol for Signer {
+    type Message = OfflineProtocolMessage;
+    type Output = CompletedOfflineStage;
+
+    /// Messages received after the signer was restored belong to
+    /// the completed offline stage and are ignored.
+    fn handle_incoming(
+        &mut self,
+        message: RoundMsg<OfflineProtocolMessage>,
+    ) -> Result<()> {
+        if let Stage::Offline(driver) = &mut self.stage {
+            driver.handle_incoming(message)?;
+        }
+        Ok(())
+    }
+
+    fn wants_to_proceed(&self) -> bool {
+        match &self.stage {
+            Stage::Offline(driver) => driver.wants_to_proceed(),
+            Stage::Restored { .. } => false,
+        }
+    }
+
+    fn proceed(
+        &mut self,
+    ) -> Result<(u16, Vec<RoundMsg<OfflineProtocolMessage>>)> {
+        match &mut self.stage {
+            Stage::Offline(driver) => driver.proceed(),
+            Stage::Restored { round, .. } => Ok((*round, Vec::new())),
+        }
+    }
+
+    fn is_finished(&self) -> bool {
+        match &self.stage {
+            Stage::Offline(driver) => driver.is_finished(),
+            Stage::Restored { .. } => true,
+        }
+    }
+
+    fn current_round(&self) -> u16 {
+        match &self.stage {
+            Stage::Offline(driver) => driver.current_round(),
+            Stage::Restored { round, .. } => *round,
+        }
+    }
+
+    fn total_rounds(&self) -> Option<u16> {
+        match &self.stage {
+            Stage::Offline(driver) => driver.total_rounds(),
+            Stage::Restored { total_rounds, .. } => *total_rounds,
+        }
+    }
+
+    /// The completed offline stage is kept so the partial
+    /// signature can be generated after taking the output.
+    fn output(&mut self) -> Result<CompletedOfflineStage> {
+        let completed_offline_stage = match &mut self.stage {
+            Stage::Offline(driver) => driver.pick_output()?,
+            Stage::Restored { .. } => {
+                self.offline.clone().ok_or(Error::NotFinished)?
+            }
+        };
+        self.offline = Some(completed_offline_stage.clone());
+        Ok(completed_offline_stage)
+    }
+}
+
+impl Resumable for Signer {
+    type Snapshot = SignerSnapshot;
+
+    /// Fails until the output of the offline stage has been taken.
+    fn snapshot(&self) -> Result<SignerSnapshot> {
+        let offline = self
+            .offline
+            .clone()
+            .ok_or_else(|| Error::NotResumable(self.current_round()))?;
+        Ok(SignerSnapshot {
+            round: self.current_round(),
+            total_rounds: self.total_rounds(),
+            offline,
+            completed: self.completed.clone(),
+        })
+    }
+
+    fn restore(snapshot: SignerSnapshot) -> Result<Signer> {
+        Ok(Signer {
+            stage: Stage::Restored {
+                round: snapshot.round,
+                total_rounds: snapshot.total_rounds,
+            },
+            offline: Some(snapshot.offline),
+            completed: snapshot.completed,
+        })
+    }
+}
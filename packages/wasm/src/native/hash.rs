@@ -0,0 +1,320 @@
+//! Message hashing for signing.
+//!
+//! Signing a [Payload] rather than a raw digest ensures the digest
+//! is computed the same way by every party and matches what Ethereum
+//! wallets and contracts expect:
+//!
+//! * [Payload::Keccak256] - the Keccak256 digest of the bytes.
+//! * [Payload::Personal] - an [EIP-191](https://eips.ethereum.org/EIPS/eip-191) personal message.
+//! * [Payload::TypedData] - [EIP-712](https://eips.ethereum.org/EIPS/eip-712) typed structured data.
+use std::collections::{BTreeMap, BTreeSet};
+
+use curv::{arithmetic::Converter, BigInt};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha3::{Digest, Keccak256};
+
+use crate::error::{Error, Result};
+
+/// Domain type for typed data.
+const EIP712_DOMAIN: &str = "EIP712Domain";
+
+/// Compute the Keccak256 digest of bytes.
+pub fn keccak256(bytes: &[u8]) -> [u8; 32] {
+    Keccak256::digest(bytes).into()
+}
+
+/// Compute the digest of an EIP-191 personal message.
+pub fn hash_personal_message(message: &[u8]) -> [u8; 32] {
+    let mut bytes =
+        format!("\x19Ethereum Signed Message:\n{}", message.len())
+            .into_bytes();
+    bytes.extend_from_slice(message);
+    keccak256(&bytes)
+}
+
+/// Field of a typed data struct.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TypedField {
+    /// Name of the field.
+    pub name: String,
+    /// Type of the field.
+    #[serde(rename = "type")]
+    pub kind: String,
+}
+
+/// EIP-712 typed data in the format used by `eth_signTypedData_v4`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TypedData {
+    /// Struct type definitions including `EIP712Domain`.
+    pub types: BTreeMap<String, Vec<TypedField>>,
+    /// Type of the message.
+    #[serde(rename = "primaryType")]
+    pub primary_type: String,
+    /// Domain separator values.
+    pub domain: Value,
+    /// Message values.
+    pub message: Value,
+}
+
+impl TypedData {
+    /// Compute the digest of the typed data.
+    pub fn digest(&self) -> Result<[u8; 32]> {
+        let mut bytes = vec![0x19, 0x01];
+        bytes.extend(self.hash_struct(EIP712_DOMAIN, &self.domain)?);
+        if self.primary_type != EIP712_DOMAIN {
+            bytes
+                .extend(self.hash_struct(&self.primary_type, &self.message)?);
+        }
+        Ok(keccak256(&bytes))
+    }
+
+    fn fields(&self, name: &str) -> Result<&Vec<TypedField>> {
+        self.types
+            .get(name)
+            .ok_or_else(|| Error::TypedData(format!("unknown type {}", name)))
+    }
+
+    /// Collect the struct types referenced by a type.
+    fn dependencies(
+        &self,
+        name: &str,
+        found: &mut BTreeSet<String>,
+    ) -> Result<()> {
+        let name = element_type(name);
+        if found.contains(name) || !self.types.contains_key(name) {
+            return Ok(());
+        }
+        found.insert(name.to_owned());
+        for field in self.fields(name)? {
+            self.dependencies(&field.kind, found)?;
+        }
+        Ok(())
+    }
+
+    fn encode_type(&self, name: &str) -> Result<String> {
+        let mut found = BTreeSet::new();
+        self.dependencies(name, &mut found)?;
+        found.remove(name);
+
+        let mut encoded = String::new();
+        for name in
+            std::iter::once(name).chain(found.iter().map(|s| s.as_str()))
+        {
+            let fields = self
+                .fields(name)?
+                .iter()
+                .map(|f| format!("{} {}", f.kind, f.name))
+                .collect::<Vec<_>>();
+            encoded.push_str(&format!("{}({})", name, fields.join(",")));
+        }
+        Ok(encoded)
+    }
+
+    fn hash_struct(&self, name: &str, value: &Value) -> Result<[u8; 32]> {
+        let mut bytes =
+            keccak256(self.encode_type(name)?.as_bytes()).to_vec();
+        for field in self.fields(name)? {
+            let value = value.get(&field.name).ok_or_else(|| {
+                Error::TypedData(format!("missing field {}", field.name))
+            })?;
+            bytes.extend(self.encode_value(&field.kind, value)?);
+        }
+        Ok(keccak256(&bytes))
+    }
+
+    fn encode_value(&self, kind: &str, value: &Value) -> Result<[u8; 32]> {
+        if kind.ends_with(']') {
+            let items = value.as_array().ok_or_else(|| {
+                Error::TypedData(format!("expected array for {}", kind))
+            })?;
+            let item_kind = element_type_once(kind);
+            let mut bytes = Vec::new();
+            for item in items {
+                bytes.extend(self.encode_value(item_kind, item)?);
+            }
+            return Ok(keccak256(&bytes));
+        }
+
+        if self.types.contains_key(kind) {
+            return self.hash_struct(kind, value);
+        }
+
+        match kind {
+            "string" => Ok(keccak256(as_str(kind, value)?.as_bytes())),
+            "bytes" => Ok(keccak256(&hex_bytes(kind, value)?)),
+            "bool" => {
+                let flag = value.as_bool().ok_or_else(|| {
+                    Error::TypedData(format!("expected boolean for {}", kind))
+                })?;
+                Ok(word(&[flag as u8]))
+            }
+            "address" => {
+                let bytes = hex_bytes(kind, value)?;
+                if bytes.len() != 20 {
+                    return Err(Error::TypedData(format!(
+                        "invalid address {}",
+                        value
+                    )));
+                }
+                Ok(word(&bytes))
+            }
+            _ if kind.starts_with("bytes") => {
+                let size = match type_size(kind, "bytes")? {
+                    Some(size) if (1..=32).contains(&size) => size,
+                    _ => return Err(unknown_type(kind)),
+                };
+                let bytes = hex_bytes(kind, value)?;
+                if bytes.len() != size {
+                    return Err(Error::TypedData(format!(
+                        "expected {} bytes for {}",
+                        size, kind
+                    )));
+                }
+                let mut encoded = [0u8; 32];
+                encoded[..bytes.len()].copy_from_slice(&bytes);
+                Ok(encoded)
+            }
+            _ if kind.starts_with("uint") || kind.starts_with("int") => {
+                let signed = kind.starts_with("int");
+                let prefix = if signed { "int" } else { "uint" };
+                let bits = type_size(kind, prefix)?.unwrap_or(256);
+                if bits == 0 || bits > 256 || bits % 8 != 0 {
+                    return Err(unknown_type(kind));
+                }
+                encode_integer(kind, bits, signed, value)
+            }
+            _ => Err(unknown_type(kind)),
+        }
+    }
+}
+
+/// Type without any array suffixes.
+fn element_type(kind: &str) -> &str {
+    kind.split('[').next().unwrap_or(kind)
+}
+
+/// Type with the last array suffix removed.
+fn element_type_once(kind: &str) -> &str {
+    kind.rfind('[').map(|index| &kind[..index]).unwrap_or(kind)
+}
+
+fn unknown_type(kind: &str) -> Error {
+    Error::TypedData(format!("unknown type {}", kind))
+}
+
+/// Size following the prefix of a type name, for example `8` for
+/// `uint8`, or `None` when the type name has no size.
+fn type_size(kind: &str, prefix: &str) -> Result<Option<usize>> {
+    let size = &kind[prefix.len()..];
+    if size.is_empty() {
+        return Ok(None);
+    }
+    if size.starts_with('0') || !size.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(unknown_type(kind));
+    }
+    size.parse().map(Some).map_err(|_| unknown_type(kind))
+}
+
+fn as_str<'a>(kind: &str, value: &'a Value) -> Result<&'a str> {
+    value.as_str().ok_or_else(|| {
+        Error::TypedData(format!("expected string for {}", kind))
+    })
+}
+
+fn hex_bytes(kind: &str, value: &Value) -> Result<Vec<u8>> {
+    let value = as_str(kind, value)?;
+    hex::decode(value.trim_start_matches("0x"))
+        .map_err(|_| Error::TypedData(format!("invalid hex for {}", kind)))
+}
+
+/// Left pad bytes to a 32 byte word.
+fn word(bytes: &[u8]) -> [u8; 32] {
+    let mut encoded = [0u8; 32];
+    encoded[32 - bytes.len()..].copy_from_slice(bytes);
+    encoded
+}
+
+/// Encode an integer given as a number, decimal string or
+/// hex string as a two's complement 256 bit word.
+///
+/// The integer must be in range for the declared number of
+/// `bits` and sign.
+fn encode_integer(
+    kind: &str,
+    bits: usize,
+    signed: bool,
+    value: &Value,
+) -> Result<[u8; 32]> {
+    let invalid =
+        || Error::TypedData(format!("invalid integer for {}", kind));
+    let text = match value {
+        Value::Number(number) => number.to_string(),
+        Value::String(text) => text.clone(),
+        _ => return Err(invalid()),
+    };
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text.as_str()),
+    };
+    let magnitude = match digits.strip_prefix("0x") {
+        Some(hex) => BigInt::from_str_radix(hex, 16),
+        None => BigInt::from_str_radix(digits, 10),
+    }
+    .map_err(|_| invalid())?;
+
+    // Signed integers range from -limit to limit - 1 and unsigned
+    // integers from 0 to limit - 1.
+    let limit = BigInt::from(1) << if signed { bits - 1 } else { bits };
+    let in_range = if negative {
+        signed && magnitude <= limit
+    } else {
+        magnitude < limit
+    };
+    if !in_range {
+        return Err(Error::TypedData(format!(
+            "integer out of range for {}",
+            kind
+        )));
+    }
+    let modulus = BigInt::from(1) << 256;
+    let value = if negative && magnitude > BigInt::from(0) {
+        modulus - magnitude
+    } else {
+        magnitude
+    };
+    Ok(word(&value.to_bytes()))
+}
+
+/// Pad a digest stored as an integer to 32 bytes.
+pub(crate) fn digest_bytes(digest: &BigInt) -> Vec<u8> {
+    word(&digest.to_bytes()).to_vec()
+}
+
+/// Payload to sign.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "value", rename_all = "camelCase")]
+pub enum Payload {
+    /// Sign the Keccak256 digest of the bytes.
+    Keccak256(Vec<u8>),
+    /// Sign an EIP-191 personal message.
+    Personal(Vec<u8>),
+    /// Sign EIP-712 typed data.
+    TypedData(TypedData),
+}
+
+impl Payload {
+    /// Compute the digest to sign.
+    pub fn digest(&self) -> Result<[u8; 32]> {
+        match self {
+            Payload::Keccak256(bytes) => Ok(keccak256(bytes)),
+            Payload::Personal(message) => Ok(hash_personal_message(message)),
+            Payload::TypedData(data) => data.digest(),
+        }
+    }
+
+    /// Compute the digest to sign as an integer.
+    pub fn to_bigint(&self) -> Result<BigInt> {
+        Ok(BigInt::from_bytes(&self.digest()?))
+    }
+}
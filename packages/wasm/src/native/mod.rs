@@ -0,0 +1,47 @@
+//! Native Rust API for the protocol state machines.
+//!
+//! These types take and return plain Rust values so they can be
+//! used outside of a browser, for example by a server-side co-signer
+//! or from `cargo test`. The webassembly bindings are thin wrappers
+//! that convert to and from Javascript values.
+//!
+//! Each protocol implements [Protocol] and is driven the same way:
+//! deliver messages from the other parties with `handle_incoming()`
+//! in any order, call `proceed()` while `wants_to_proceed()` to get
+//! the messages to send and repeat until `is_finished()` before
+//! taking the `output()`.
+//!
+//! After key generation a key share can be checked with
+//! [verify_key_share] and the parties confirm they derived the same
+//! public key by comparing the [public_key_hash] of their key shares.
+//!
+//! A [Signer] implements [Resumable] once the offline stage has
+//! completed so a party can encrypt and persist it and create the
+//! signature later. The upstream state machines cannot be serialized
+//! so a protocol cannot be persisted while a state machine is running.
+mod cggmp;
+mod driver;
+mod gg2020;
+mod hash;
+mod pool;
+mod protocol;
+mod seal;
+mod verify;
+
+pub use crate::error::{Blame, Error, Result};
+pub use cggmp::{
+    AuxInfo, CggmpKeyGenerator, CggmpKeyShare, CggmpKeygenMessage,
+    KeyRefresh, PreSigning, Presignature, SignPresignature,
+};
+pub use driver::{RoundDriver, RoundMsg};
+pub use gg2020::{KeyGenerator, Signer, SignerSnapshot};
+pub use hash::{
+    hash_personal_message, keccak256, Payload, TypedData, TypedField,
+};
+pub use pool::{
+    ssid_id, BatchMessage, PoolPresignature, PresignaturePool,
+    PresigningBatch,
+};
+pub use protocol::{Driven, Protocol, Resumable};
+pub use seal::KEY_LENGTH;
+pub use verify::{confirm_key_share, public_key_hash, verify_key_share};
@@ -0,0 +1,272 @@
+//! Presignature pools for fast online signing.
+//!
+//! Presignatures are generated offline in batches with a
+//! [PresigningBatch] and stored in a [PresignaturePool] indexed by
+//! the identifier of their SSID. Taking a presignature from the pool
+//! removes it so that a presignature can never be used to sign more
+//! than one message; signing twice with the same presignature leaks
+//! the secret key.
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use curv::elliptic::curves::secp256_k1::Secp256k1;
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Keccak256};
+
+use cggmp_threshold_ecdsa::presign::{state_machine as presign, SSID};
+
+use super::{
+    driver::RoundMsg,
+    protocol::Protocol,
+    seal::{self, KEY_LENGTH},
+    CggmpKeyShare, PreSigning, Presignature,
+};
+use crate::error::{Error, Result};
+
+/// Compute the identifier for an SSID.
+///
+/// The identifier is the hex-encoded Keccak256 digest of the
+/// MessagePack encoding of the SSID.
+pub fn ssid_id(ssid: &SSID<Secp256k1>) -> Result<String> {
+    let bytes = rmp_serde::to_vec_named(ssid)?;
+    Ok(hex::encode(Keccak256::digest(bytes)))
+}
+
+/// Presignatures generated for an SSID.
+#[derive(Serialize, Deserialize)]
+struct Batch {
+    ssid: SSID<Secp256k1>,
+    presignatures: BTreeMap<u16, Presignature>,
+    used: BTreeSet<u16>,
+}
+
+/// Presignature taken from a pool.
+pub struct PoolPresignature {
+    /// The SSID for the presignature.
+    pub ssid: SSID<Secp256k1>,
+    /// Index of the presignature.
+    pub index: u16,
+    /// The presignature.
+    pub presignature: Presignature,
+}
+
+impl PoolPresignature {
+    /// Presigning data for a signing state machine.
+    pub fn presigning_data(self) -> HashMap<u16, Presignature> {
+        let mut data = HashMap::new();
+        data.insert(self.index, self.presignature);
+        data
+    }
+}
+
+/// Store of presignatures indexed by SSID.
+#[derive(Default, Serialize, Deserialize)]
+pub struct PresignaturePool {
+    batches: HashMap<String, Batch>,
+}
+
+impl PresignaturePool {
+    /// Create an empty pool.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Add a presignature to the pool and return the SSID identifier.
+    ///
+    /// Fails if the presignature index has already been used
+    /// or is already in the pool.
+    pub fn insert(
+        &mut self,
+        ssid: SSID<Secp256k1>,
+        index: u16,
+        presignature: Presignature,
+    ) -> Result<String> {
+        self.insert_batch(ssid, vec![(index, presignature)])
+    }
+
+    /// Add a batch of presignatures to the pool and return
+    /// the SSID identifier.
+    pub fn insert_batch(
+        &mut self,
+        ssid: SSID<Secp256k1>,
+        presignatures: Vec<(u16, Presignature)>,
+    ) -> Result<String> {
+        let id = ssid_id(&ssid)?;
+        let batch = self.batches.entry(id.clone()).or_insert_with(|| Batch {
+            ssid,
+            presignatures: BTreeMap::new(),
+            used: BTreeSet::new(),
+        });
+        for (index, _) in presignatures.iter() {
+            if batch.used.contains(index) {
+                return Err(Error::PresignatureUsed(*index));
+            }
+            if batch.presignatures.contains_key(index) {
+                return Err(Error::PresignatureExists(*index));
+            }
+        }
+        batch.presignatures.extend(presignatures);
+        Ok(id)
+    }
+
+    /// Identifiers for the SSIDs in the pool.
+    pub fn ssids(&self) -> Vec<String> {
+        self.batches.keys().cloned().collect()
+    }
+
+    /// SSID for an identifier.
+    pub fn ssid(&self, id: &str) -> Option<&SSID<Secp256k1>> {
+        self.batches.get(id).map(|batch| &batch.ssid)
+    }
+
+    /// Number of unused presignatures for an SSID.
+    pub fn available(&self, id: &str) -> usize {
+        self.batches
+            .get(id)
+            .map(|batch| batch.presignatures.len())
+            .unwrap_or_default()
+    }
+
+    /// Take the unused presignature with the lowest index for an SSID.
+    pub fn take(&mut self, id: &str) -> Result<PoolPresignature> {
+        let index = self
+            .batches
+            .get(id)
+            .ok_or_else(|| Error::UnknownSsid(id.to_owned()))?
+            .presignatures
+            .keys()
+            .next()
+            .copied()
+            .ok_or_else(|| Error::PresignaturesExhausted(id.to_owned()))?;
+        self.take_index(id, index)
+    }
+
+    /// Take a presignature by index.
+    ///
+    /// The presignature is removed from the pool and the index is
+    /// marked as used so it can not be added again.
+    pub fn take_index(
+        &mut self,
+        id: &str,
+        index: u16,
+    ) -> Result<PoolPresignature> {
+        let batch = self
+            .batches
+            .get_mut(id)
+            .ok_or_else(|| Error::UnknownSsid(id.to_owned()))?;
+        let presignature = batch
+            .presignatures
+            .remove(&index)
+            .ok_or(Error::PresignatureUsed(index))?;
+        batch.used.insert(index);
+        Ok(PoolPresignature {
+            ssid: batch.ssid.clone(),
+            index,
+            presignature,
+        })
+    }
+
+    /// Encrypt the pool for storage.
+    pub fn encrypt(&self, key: &[u8; KEY_LENGTH]) -> Result<Vec<u8>> {
+        seal::seal(self, key)
+    }
+
+    /// Decrypt a pool.
+    pub fn decrypt(encrypted: &[u8], key: &[u8; KEY_LENGTH]) -> Result<Self> {
+        seal::open(encrypted, key)
+    }
+}
+
+/// Message for batch presigning.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BatchMessage {
+    /// Index of the presignature.
+    pub index: u16,
+    /// Presigning message.
+    pub body: presign::ProtocolMessage,
+}
+
+/// Generate a batch of presignatures for an SSID.
+///
+/// Runs a presigning state machine for each index in lockstep so
+/// the batch completes in the same number of rounds as a single
+/// presignature.
+pub struct PresigningBatch {
+    ssid: SSID<Secp256k1>,
+    machines: BTreeMap<u16, PreSigning>,
+}
+
+impl PresigningBatch {
+    /// Create a batch for the presignature indices.
+    pub fn new(
+        ssid: SSID<Secp256k1>,
+        key_share: &CggmpKeyShare,
+        indices: Vec<u16>,
+    ) -> Result<PresigningBatch> {
+        let machines = indices
+            .into_iter()
+            .map(|index| {
+                let machine = PreSigning::from_key_share(
+                    ssid.clone(),
+                    key_share,
+                    index as usize,
+                )?;
+                Ok((index, machine))
+            })
+            .collect::<Result<BTreeMap<_, _>>>()?;
+        Ok(Self { ssid, machines })
+    }
+
+    /// Handle an incoming message.
+    pub fn handle_incoming(
+        &mut self,
+        message: RoundMsg<BatchMessage>,
+    ) -> Result<()> {
+        let sender = message.sender;
+        let index = message.body.index;
+        let machine = self
+            .machines
+            .get_mut(&index)
+            .ok_or(Error::UnexpectedMessage(sender))?;
+        machine.handle_incoming(message.map_body(|body| body.body))
+    }
+
+    /// Whether any presignature in the batch has messages to send
+    /// or wants to proceed.
+    pub fn wants_to_proceed(&self) -> bool {
+        self.machines.values().any(|m| m.wants_to_proceed())
+    }
+
+    /// Whether every presignature in the batch has finished.
+    pub fn is_finished(&self) -> bool {
+        self.machines.values().all(|m| m.is_finished())
+    }
+
+    /// Proceed to the next round and return the round number
+    /// and the messages to send to the other parties.
+    pub fn proceed(&mut self) -> Result<(u16, Vec<RoundMsg<BatchMessage>>)> {
+        let mut round = u16::MAX;
+        let mut messages = Vec::new();
+        for (index, machine) in self.machines.iter_mut() {
+            let (machine_round, machine_messages) = machine.proceed()?;
+            round = round.min(machine_round);
+            messages.extend(machine_messages.into_iter().map(|m| {
+                m.map_body(|body| BatchMessage {
+                    index: *index,
+                    body,
+                })
+            }));
+        }
+        Ok((round, messages))
+    }
+
+    /// Add the generated presignatures to a pool and return
+    /// the SSID identifier.
+    pub fn finish(self, pool: &mut PresignaturePool) -> Result<String> {
+        let presignatures = self
+            .machines
+            .into_iter()
+            .map(|(index, mut machine)| Ok((index, machine.output()?)))
+            .collect::<Result<Vec<_>>>()?;
+        pool.insert_batch(self.ssid, presignatures)
+    }
+}
@@ -0,0 +1,146 @@
+//! Common interface for the protocol state machines.
+//!
+//! Every protocol implements [Protocol] so callers (and the
+//! webassembly bindings) can drive any of them with the same loop.
+//! A protocol that runs a single `round_based::StateMachine` only
+//! needs to implement [Driven] which describes where the
+//! [RoundDriver] lives and how the state machine output is
+//! converted to the protocol output.
+//!
+//! The upstream state machines do not implement serde so a protocol
+//! that can be persisted implements [Resumable] with an explicit
+//! snapshot of the state it is able to capture; the snapshot is
+//! encrypted so it can be stored and resumed after a restart (for
+//! example a page reload).
+use round_based::StateMachine;
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{
+    driver::{RoundDriver, RoundMsg},
+    seal::{self, KEY_LENGTH},
+};
+use crate::error::{Error, Result};
+
+/// Round-based protocol.
+pub trait Protocol {
+    /// Message body exchanged between the parties.
+    type Message;
+    /// Output of the protocol once it has finished.
+    type Output;
+
+    /// Handle an incoming message.
+    ///
+    /// Messages for future rounds are buffered and duplicate
+    /// messages are ignored.
+    fn handle_incoming(
+        &mut self,
+        message: RoundMsg<Self::Message>,
+    ) -> Result<()>;
+
+    /// Whether there are messages to send or the current round
+    /// has received all the messages it needs to proceed.
+    fn wants_to_proceed(&self) -> bool;
+
+    /// Proceed to the next round and return the round number
+    /// and the messages to send to the other parties.
+    fn proceed(&mut self) -> Result<(u16, Vec<RoundMsg<Self::Message>>)>;
+
+    /// Whether the protocol has finished.
+    fn is_finished(&self) -> bool;
+
+    /// Current round.
+    fn current_round(&self) -> u16;
+
+    /// Total number of rounds when known.
+    fn total_rounds(&self) -> Option<u16>;
+
+    /// Take the output of the protocol.
+    fn output(&mut self) -> Result<Self::Output>;
+}
+
+/// Protocol that runs a single state machine.
+pub trait Driven {
+    /// State machine for the protocol.
+    type Machine: StateMachine;
+    /// Output of the protocol.
+    type Output;
+
+    /// Driver for the state machine.
+    fn driver(&self) -> &RoundDriver<Self::Machine>;
+
+    /// Mutable driver for the state machine.
+    fn driver_mut(&mut self) -> &mut RoundDriver<Self::Machine>;
+
+    /// Convert the output of the state machine.
+    fn finish(
+        &mut self,
+        output: <Self::Machine as StateMachine>::Output,
+    ) -> Result<Self::Output>;
+}
+
+/// Protocol that can be persisted and resumed.
+pub trait Resumable: Protocol + Sized {
+    /// Serializable state of the protocol.
+    type Snapshot: Serialize + DeserializeOwned;
+
+    /// Capture the state of the protocol.
+    ///
+    /// Fails with [Error::NotResumable] in rounds where the state
+    /// is held by a state machine that cannot be serialized.
+    fn snapshot(&self) -> Result<Self::Snapshot>;
+
+    /// Resume a protocol from a snapshot.
+    fn restore(snapshot: Self::Snapshot) -> Result<Self>;
+
+    /// Encrypt the protocol state for storage.
+    fn encrypt(&self, key: &[u8; KEY_LENGTH]) -> Result<Vec<u8>> {
+        seal::seal(&self.snapshot()?, key)
+    }
+
+    /// Decrypt protocol state to resume the protocol.
+    fn decrypt(encrypted: &[u8], key: &[u8; KEY_LENGTH]) -> Result<Self> {
+        Self::restore(seal::open(encrypted, key)?)
+    }
+}
+
+impl<T> Protocol for T
+where
+    T: Driven,
+    Error: From<<T::Machine as StateMachine>::Err>,
+{
+    type Message = <T::Machine as StateMachine>::MessageBody;
+    type Output = <T as Driven>::Output;
+
+    fn handle_incoming(
+        &mut self,
+        message: RoundMsg<Self::Message>,
+    ) -> Result<()> {
+        self.driver_mut().handle_incoming(message)?;
+        Ok(())
+    }
+
+    fn wants_to_proceed(&self) -> bool {
+        self.driver().wants_to_proceed()
+    }
+
+    fn proceed(&mut self) -> Result<(u16, Vec<RoundMsg<Self::Message>>)> {
+        self.driver_mut().proceed()
+    }
+
+    fn is_finished(&self) -> bool {
+        self.driver().is_finished()
+    }
+
+    fn current_round(&self) -> u16 {
+        self.driver().current_round()
+    }
+
+    fn total_rounds(&self) -> Option<u16> {
+        self.driver().total_rounds()
+    }
+
+    fn output(&mut self) -> Result<Self::Output> {
+        let output = self.driver_mut().pick_output()?;
+        self.finish(output)
+    }
+}
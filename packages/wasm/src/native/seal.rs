@@ -0,0 +1,51 @@
+//! Encrypt values for storage.
+//!
+//! Values are encoded as MessagePack and encrypted with
+//! XChaCha20-Poly1305 using a caller-supplied 32 byte key; the
+//! random nonce is prepended to the ciphertext.
+use chacha20poly1305::{
+    aead::{Aead, KeyInit},
+    Key, XChaCha20Poly1305, XNonce,
+};
+use rand::RngCore;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::error::{Error, Result};
+
+/// Length of an encryption key.
+pub const KEY_LENGTH: usize = 32;
+
+const NONCE_LENGTH: usize = 24;
+
+/// Encode and encrypt a value.
+pub(crate) fn seal<T: Serialize>(
+    value: &T,
+    key: &[u8; KEY_LENGTH],
+) -> Result<Vec<u8>> {
+    let plaintext = rmp_serde::to_vec_named(value)?;
+    let cipher = XChaCha20Poly1305::new(Key::from_slice(key));
+    let mut nonce = [0u8; NONCE_LENGTH];
+    rand::thread_rng().fill_bytes(&mut nonce);
+    let ciphertext = cipher
+        .encrypt(XNonce::from_slice(&nonce), plaintext.as_slice())
+        .map_err(|_| Error::Encrypt)?;
+    let mut sealed = nonce.to_vec();
+    sealed.extend(ciphertext);
+    Ok(sealed)
+}
+
+/// Decrypt and decode a value.
+pub(crate) fn open<T: DeserializeOwned>(
+    sealed: &[u8],
+    key: &[u8; KEY_LENGTH],
+) -> Result<T> {
+    if sealed.len() < NONCE_LENGTH {
+        return Err(Error::Decrypt);
+    }
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LENGTH);
+    let cipher = XChaCha20Poly1305::new(Key::from_slice(key));
+    let plaintext = cipher
+        .decrypt(XNonce::from_slice(nonce), ciphertext)
+        .map_err(|_| Error::Decrypt)?;
+    Ok(rmp_serde::from_slice(&plaintext)?)
+}
@@ -0,0 +1,157 @@
+//! Verify key shares after key generation.
+//!
+//! [verify_key_share] checks that a key share is internally
+//! consistent:
+//!
+//! * The secret share of the party matches its public share.
+//! * The public shares of every party lie on a polynomial of degree
+//!   `t` whose constant term is the public key.
+//! * The parameters and number of commitments of the verifiable
+//!   secret sharing (VSS) scheme match the threshold.
+//! * The `public_key` and `address` match the local key.
+//!
+//! The VSS scheme in a key share commits to the polynomial of this
+//! party only whereas the secret share is the sum of the shares from
+//! every party so the secret share is not validated against the
+//! commitments.
+//!
+//! A consistent key share may still differ from the key shares of the
+//! other parties so before a key is used the parties should compare
+//! the [public_key_hash] of their key shares (the server provides the
+//! `Session.confirm` method to exchange them) and check the hashes
+//! with [confirm_key_share].
+use curv::elliptic::curves::{Point, Scalar, Secp256k1};
+
+use super::hash::keccak256;
+use crate::{
+    error::{Error, Result},
+    utils::address,
+    KeyShare,
+};
+
+/// Verify a key share is internally consistent.
+pub fn verify_key_share(key_share: &KeyShare) -> Result<()> {
+    let local_key = &key_share.local_key;
+    let (i, t, n) = (local_key.i, local_key.t, local_key.n);
+    if i == 0 || i > n || t >= n {
+        return Err(Error::InvalidKeyShare(format!(
+            "party {} with threshold {} is out of range for {} parties",
+            i, t, n
+        )));
+    }
+
+    let parties = n as usize;
+    if local_key.pk_vec.len() != parties
+        || local_key.paillier_key_vec.len() != parties
+        || local_key.h1_h2_n_tilde_vec.len() != parties
+    {
+        return Err(invalid("expected public values for every party"));
+    }
+
+    let vss_scheme = &local_key.vss_scheme;
+    if vss_scheme.parameters.threshold != t
+        || vss_scheme.parameters.share_count != n
+        || vss_scheme.commitments.len() != t as usize + 1
+    {
+        return Err(invalid("VSS commitments do not match the parameters"));
+    }
+
+    let shared_keys = &local_key.keys_linear;
+    if Point::generator() * &shared_keys.x_i
+        != local_key.pk_vec[i as usize - 1]
+    {
+        return Err(invalid("secret share does not match the public share"));
+    }
+    if shared_keys.y != local_key.y_sum_s {
+        return Err(invalid("shared key does not match the public key"));
+    }
+
+    // Interpolate the polynomial from the first `t + 1` public shares
+    // and check the public key and the remaining public shares
+    let points = (1..=t + 1).collect::<Vec<u16>>();
+    if interpolate(&local_key.pk_vec, &points, 0)? != local_key.y_sum_s {
+        return Err(invalid("public shares do not match the public key"));
+    }
+    for x in t + 2..=n {
+        if interpolate(&local_key.pk_vec, &points, x)?
+            != local_key.pk_vec[x as usize - 1]
+        {
+            return Err(Error::InvalidKeyShare(format!(
+                "public share for party {} is not consistent",
+                x
+            )));
+        }
+    }
+
+    let public_key = local_key.y_sum_s.to_bytes(false).to_vec();
+    if key_share.public_key != public_key
+        || key_share.address != address(&public_key)
+    {
+        return Err(invalid("public key does not match the local key"));
+    }
+
+    Ok(())
+}
+
+/// Compute the hex-encoded Keccak256 digest of the public key
+/// for a key share.
+pub fn public_key_hash(key_share: &KeyShare) -> String {
+    hex::encode(keccak256(&key_share.public_key))
+}
+
+/// Check the public key hashes reported by every party match
+/// the public key of a key share.
+///
+/// The `hashes` are pairs of the party number and the
+/// [public_key_hash] reported by the party.
+pub fn confirm_key_share(
+    key_share: &KeyShare,
+    hashes: &[(u16, String)],
+) -> Result<()> {
+    let local_key = &key_share.local_key;
+    let expected = public_key_hash(key_share);
+    let mut mismatched = hashes
+        .iter()
+        .filter(|(_, hash)| hash != &expected)
+        .map(|(number, _)| *number)
+        .collect::<Vec<_>>();
+    for number in 1..=local_key.n {
+        if !hashes.iter().any(|(n, _)| *n == number) {
+            mismatched.push(number);
+        }
+    }
+    if !mismatched.is_empty() {
+        mismatched.sort_unstable();
+        mismatched.dedup();
+        return Err(Error::PublicKeyMismatch(mismatched));
+    }
+    Ok(())
+}
+
+fn invalid(reason: &str) -> Error {
+    Error::InvalidKeyShare(reason.to_owned())
+}
+
+/// Evaluate at `x` the polynomial that passes through the public
+/// shares of `parties` using Lagrange interpolation.
+fn interpolate(
+    shares: &[Point<Secp256k1>],
+    parties: &[u16],
+    x: u16,
+) -> Result<Point<Secp256k1>> {
+    let x = Scalar::<Secp256k1>::from(x);
+    let mut point = Point::zero();
+    for k in parties {
+        let x_k = Scalar::<Secp256k1>::from(*k);
+        let mut coefficient = Scalar::<Secp256k1>::from(1u16);
+        for m in parties.iter().filter(|m| *m != k) {
+            let x_m = Scalar::<Secp256k1>::from(*m);
+            let denominator = (&x_k - &x_m)
+                .invert()
+                .ok_or_else(|| invalid("duplicate party in interpolation"))?;
+            coefficient = coefficient * (&x - &x_m) * denominator;
+        }
+        point = point + &shares[*k as usize - 1] * &coefficient;
+    }
+    Ok(point)
+}
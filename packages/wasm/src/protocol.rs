@@ -0,0 +1,110 @@
+//! Javascript bindings shared by every protocol.
+//!
+//! A protocol wrapper is a struct with an `inner` field that
+//! implements [Protocol](crate::native::Protocol); it only needs a
+//! constructor that converts the input from Javascript values and a
+//! call to [bindings] which adds the common methods:
+//!
+//! * `handleIncoming(message)`
+//! * `wantsToProceed()`
+//! * `proceed()`
+//! * `isFinished()`
+//! * `currentRound()`
+//! * `totalRounds()`
+//! * `output()`
+//!
+//! When a round fails `handleIncoming()` and `proceed()` throw an
+//! `AbortError` with a `blame` property naming the `round` and the
+//! `parties` responsible so it can be reported to the other parties.
+use wasm_bindgen::prelude::*;
+
+use crate::native::Error;
+
+/// Convert an error to a Javascript error that includes the
+/// blame when a protocol round fails.
+pub(crate) fn js_error(error: Error) -> JsValue {
+    let js_error = js_sys::Error::new(&error.to_string());
+    if let Some(blame) = error.blame() {
+        js_error.set_name("AbortError");
+        if let Ok(blame) = serde_wasm_bindgen::to_value(blame) {
+            let _ = js_sys::Reflect::set(&js_error, &"blame".into(), &blame);
+        }
+    }
+    js_error.into()
+}
+
+/// Add the common protocol methods to a wrapper.
+///
+/// The second argument documents the value returned by `output()`.
+macro_rules! bindings {
+    ($name:ident, $output:literal) => {
+        #[::wasm_bindgen::prelude::wasm_bindgen]
+        impl $name {
+            /// Handle an incoming message.
+            ///
+            /// Messages for future rounds are buffered and duplicate
+            /// messages are ignored.
+            #[wasm_bindgen(js_name = "handleIncoming")]
+            pub fn handle_incoming(
+                &mut self,
+                message: ::wasm_bindgen::JsValue,
+            ) -> Result<(), ::wasm_bindgen::JsValue> {
+                let message = serde_wasm_bindgen::from_value(message)?;
+                $crate::native::Protocol::handle_incoming(
+                    &mut self.inner,
+                    message,
+                )
+                .map_err($crate::protocol::js_error)
+            }
+
+            /// Whether there are messages to send or the current round
+            /// has received all the messages it needs to proceed.
+            #[wasm_bindgen(js_name = "wantsToProceed")]
+            pub fn wants_to_proceed(&self) -> bool {
+                $crate::native::Protocol::wants_to_proceed(&self.inner)
+            }
+
+            /// Proceed to the next round if ready and return the
+            /// current round and the messages to send.
+            pub fn proceed(
+                &mut self,
+            ) -> Result<::wasm_bindgen::JsValue, ::wasm_bindgen::JsValue>
+            {
+                let result =
+                    $crate::native::Protocol::proceed(&mut self.inner)
+                        .map_err($crate::protocol::js_error)?;
+                Ok(serde_wasm_bindgen::to_value(&result)?)
+            }
+
+            /// Whether the protocol has finished.
+            #[wasm_bindgen(js_name = "isFinished")]
+            pub fn is_finished(&self) -> bool {
+                $crate::native::Protocol::is_finished(&self.inner)
+            }
+
+            /// Current round.
+            #[wasm_bindgen(js_name = "currentRound")]
+            pub fn current_round(&self) -> u16 {
+                $crate::native::Protocol::current_round(&self.inner)
+            }
+
+            /// Total number of rounds when known.
+            #[wasm_bindgen(js_name = "totalRounds")]
+            pub fn total_rounds(&self) -> Option<u16> {
+                $crate::native::Protocol::total_rounds(&self.inner)
+            }
+
+            #[doc = $output]
+            pub fn output(
+                &mut self,
+            ) -> Result<::wasm_bindgen::JsValue, ::wasm_bindgen::JsError>
+            {
+                let output =
+                    $crate::native::Protocol::output(&mut self.inner)?;
+                Ok(serde_wasm_bindgen::to_value(&output)?)
+            }
+        }
+    };
+}
+
+pub(crate) use bindings;
@@ -1,8 +1,8 @@
-use serde::{Serialize, Deserialize};
 use curv::elliptic::curves::secp256_k1::Secp256k1;
-use multi_party_ecdsa::protocols::multi_party_ecdsa::gg_2020::state_machine::keygen::{
-    LocalKey
+use multi_party_ecdsa::protocols::multi_party_ecdsa::gg_2020::{
+    party_i::SignatureRecid, state_machine::keygen::LocalKey,
 };
+use serde::{Deserialize, Serialize};
 use sha3::{Digest, Keccak256};
 
 /// Parameters used during key generation.
@@ -59,6 +59,21 @@ impl From<LocalKey<Secp256k1>> for KeyShare {
     }
 }
 
+/// Signature generated by a signer.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Signature {
+    /// The generated ECDSA signature.
+    pub signature: SignatureRecid,
+    /// The public key.
+    #[serde(rename = "publicKey")]
+    pub public_key: Vec<u8>,
+    /// Address generated from the public key.
+    pub address: String,
+    /// The signed digest.
+    #[serde(default)]
+    pub digest: Vec<u8>,
+}
+
 /// Compute the address of an uncompressed public key (65 bytes).
 pub(crate) fn address(public_key: &[u8]) -> String {
     // Remove the leading 0x04
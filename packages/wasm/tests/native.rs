@@ -0,0 +1,506 @@
+//! Run the protocols natively without a browser.
+use mpc_ecdsa_wasm::{
+    native::{
+        confirm_key_share, public_key_hash, verify_key_share,
+        CggmpKeyGenerator, Error, KeyGenerator, Payload, Protocol, Result,
+        Resumable, RoundMsg, Signer, TypedData,
+    },
+    KeyShare, Parameters, PartySignup,
+};
+
+/// Deliver messages to the parties they are addressed to.
+///
+/// Parties are identified by their position in the list starting
+/// from one; broadcast messages are delivered to every party except
+/// the sender.
+fn route<B: Clone>(
+    messages: Vec<RoundMsg<B>>,
+    mut handle: impl FnMut(usize, RoundMsg<B>) -> Result<()>,
+    parties: usize,
+) -> Result<()> {
+    for message in messages {
+        for number in 1..=parties as u16 {
+            let addressed = message.receiver.map_or(true, |r| r == number);
+            if number != message.sender && addressed {
+                handle(number as usize - 1, message.clone())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn keygen(parameters: Parameters) -> Result<Vec<KeyShare>> {
+    let parties = parameters.parties as usize;
+    let mut generators = (1..=parameters.parties)
+        .map(|number| {
+            KeyGenerator::new(
+                parameters.clone(),
+                PartySignup {
+                    number,
+                    uuid: String::from("native-keygen"),
+                },
+            )
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    // Deliver each party's messages as soon as they are produced, and
+    // twice, so peers see messages for future rounds and duplicates.
+    while !generators.iter().all(|g| g.is_finished()) {
+        for index in 0..parties {
+            let (_, outgoing) = generators[index].proceed()?;
+            for _ in 0..2 {
+                route(
+                    outgoing.clone(),
+                    |index, message| {
+                        generators[index].handle_incoming(message)
+                    },
+                    parties,
+                )?;
+            }
+        }
+    }
+
+    generators.iter_mut().map(|g| g.output()).collect()
+}
+
+#[test]
+fn keygen_and_sign() -> Result<()> {
+    let parameters = Parameters {
+        parties: 3,
+        threshold: 1,
+    };
+    let key_shares = keygen(parameters)?;
+    let address = &key_shares[0].address;
+    assert!(key_shares.iter().all(|k| &k.address == address));
+
+    // Sign with the first two key shares
+    let participants = vec![1, 2];
+    let mut signers = key_shares
+        .iter()
+        .take(participants.len())
+        .enumerate()
+        .map(|(index, key_share)| {
+            Signer::new(
+                index as u16 + 1,
+                participants.clone(),
+                key_share.local_key.clone(),
+            )
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    while !signers.iter().all(|s| s.is_finished()) {
+        let mut outgoing = Vec::new();
+        for signer in signers.iter_mut() {
+            outgoing.extend(signer.proceed()?.1);
+        }
+        route(
+            outgoing,
+            |index, message| signers[index].handle_incoming(message),
+            participants.len(),
+        )?;
+    }
+
+    let payload = Payload::Personal(b"hello".to_vec());
+    let partials = signers
+        .iter_mut()
+        .map(|s| s.partial_payload(&payload))
+        .collect::<Result<Vec<_>>>()?;
+
+    for (index, signer) in signers.iter_mut().enumerate() {
+        let others = partials
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i != index)
+            .map(|(_, partial)| partial.clone())
+            .collect::<Vec<_>>();
+        let signature = signer.create(&others)?;
+        assert_eq!(address, &signature.address);
+        assert_eq!(key_shares[0].public_key, signature.public_key);
+        assert_eq!(payload.digest()?.to_vec(), signature.digest);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn keygen_key_shares_verify() -> Result<()> {
+    let parameters = Parameters {
+        parties: 3,
+        threshold: 1,
+    };
+    let key_shares = keygen(parameters)?;
+    for key_share in &key_shares {
+        verify_key_share(key_share)?;
+    }
+
+    let mut hashes = key_shares
+        .iter()
+        .enumerate()
+        .map(|(index, k)| (index as u16 + 1, public_key_hash(k)))
+        .collect::<Vec<_>>();
+    confirm_key_share(&key_shares[0], &hashes)?;
+
+    // Public shares must match the secret share
+    let mut tampered = key_shares[0].clone();
+    tampered.local_key.pk_vec.swap(0, 1);
+    assert!(matches!(
+        verify_key_share(&tampered),
+        Err(Error::InvalidKeyShare(_))
+    ));
+
+    // Every party must report the same hash
+    hashes[1].1 = hex::encode([0u8; 32]);
+    hashes.pop();
+    assert!(matches!(
+        confirm_key_share(&key_shares[0], &hashes),
+        Err(Error::PublicKeyMismatch(parties)) if parties == vec![2, 3]
+    ));
+    Ok(())
+}
+
+#[test]
+fn signer_resumes_from_encrypted_state() -> Result<()> {
+    let parameters = Parameters {
+        parties: 3,
+        threshold: 1,
+    };
+    let key_shares = keygen(parameters)?;
+    let participants = vec![1, 2];
+    let mut signers = key_shares
+        .iter()
+        .take(participants.len())
+        .enumerate()
+        .map(|(index, key_share)| {
+            Signer::new(
+                index as u16 + 1,
+                participants.clone(),
+                key_share.local_key.clone(),
+            )
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let key = [7u8; 32];
+    let mut replay = Vec::new();
+    while !signers.iter().all(|s| s.is_finished()) {
+        // The offline stage state machine cannot be persisted
+        assert!(matches!(
+            signers[0].encrypt(&key),
+            Err(Error::NotResumable(_))
+        ));
+        let mut outgoing = Vec::new();
+        for signer in signers.iter_mut() {
+            outgoing.extend(signer.proceed()?.1);
+        }
+        replay.extend(outgoing.iter().filter(|m| m.sender != 1).cloned());
+        route(
+            outgoing,
+            |index, message| signers[index].handle_incoming(message),
+            participants.len(),
+        )?;
+    }
+
+    // Persist and resume the first signer after the offline stage
+    signers[0].output()?;
+    let round = signers[0].current_round();
+    let state = signers[0].encrypt(&key)?;
+    assert!(matches!(
+        Signer::decrypt(&state, &[0u8; 32]),
+        Err(Error::Decrypt)
+    ));
+    signers[0] = Signer::decrypt(&state, &key)?;
+    assert!(signers[0].is_finished());
+    assert_eq!(round, signers[0].current_round());
+
+    // Messages relayed again after resuming are ignored
+    for message in replay {
+        signers[0].handle_incoming(message)?;
+    }
+    assert!(!signers[0].wants_to_proceed());
+
+    let payload = Payload::Personal(b"hello".to_vec());
+    let partials = signers
+        .iter_mut()
+        .map(|s| s.partial_payload(&payload))
+        .collect::<Result<Vec<_>>>()?;
+    let signature = signers[0].create(&partials[1..])?;
+    assert_eq!(key_shares[0].address, signature.address);
+    Ok(())
+}
+
+#[test]
+fn reject_message_for_distant_round() -> Result<()> {
+    let parameters = Parameters {
+        parties: 3,
+        threshold: 1,
+    };
+    let mut generators = (1..=parameters.parties)
+        .map(|number| {
+            KeyGenerator::new(
+                parameters.clone(),
+                PartySignup {
+                    number,
+                    uuid: String::from("native-keygen-round"),
+                },
+            )
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let round = generators[0].current_round();
+    let mut message = generators[1]
+        .proceed()?
+        .1
+        .into_iter()
+        .find(|m| m.receiver.map_or(true, |r| r == 1))
+        .expect("no message for party 1");
+
+    message.round = round + 2;
+    assert!(matches!(
+        generators[0].handle_incoming(message.clone()),
+        Err(Error::InvalidRound(r)) if r == round + 2
+    ));
+
+    // The next round is buffered
+    message.round = round + 1;
+    generators[0].handle_incoming(message)?;
+    Ok(())
+}
+
+#[test]
+fn keygen_blames_tampered_commitment() -> Result<()> {
+    let parameters = Parameters {
+        parties: 3,
+        threshold: 1,
+    };
+    let parties = parameters.parties as usize;
+    let mut generators = (1..=parameters.parties)
+        .map(|number| {
+            KeyGenerator::new(
+                parameters.clone(),
+                PartySignup {
+                    number,
+                    uuid: String::from("native-keygen-blame"),
+                },
+            )
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    // Party 1 receives the commitments of parties 2 and 3 swapped
+    let mut commitments = Vec::new();
+    for generator in generators.iter_mut() {
+        commitments.extend(generator.proceed()?.1);
+    }
+    let mut tampered = commitments
+        .iter()
+        .filter(|m| m.sender != 1)
+        .cloned()
+        .collect::<Vec<_>>();
+    let (first, second) =
+        (tampered[0].body.clone(), tampered[1].body.clone());
+    tampered[0].body = second;
+    tampered[1].body = first;
+    for message in tampered {
+        generators[0].handle_incoming(message)?;
+    }
+    route(
+        commitments,
+        |index, message| match index {
+            0 => Ok(()),
+            _ => generators[index].handle_incoming(message),
+        },
+        parties,
+    )?;
+
+    let mut run = || -> Result<()> {
+        while !generators.iter().all(|g| g.is_finished()) {
+            let mut outgoing = Vec::new();
+            for generator in generators.iter_mut() {
+                outgoing.extend(generator.proceed()?.1);
+            }
+            route(
+                outgoing,
+                |index, message| generators[index].handle_incoming(message),
+                parties,
+            )?;
+        }
+        Ok(())
+    };
+    let error = run().expect_err("tampered commitment was accepted");
+    let blame = error.blame().expect("error does not blame a party");
+    assert_eq!(2, blame.round);
+    assert!(!blame.parties.is_empty());
+    assert!(!blame.parties.contains(&1));
+
+    Ok(())
+}
+
+#[test]
+fn cggmp_keygen_aux_info() -> Result<()> {
+    let parameters = Parameters {
+        parties: 3,
+        threshold: 1,
+    };
+    let parties = parameters.parties as usize;
+    let mut generators = (1..=parameters.parties)
+        .map(|number| {
+            CggmpKeyGenerator::new(
+                parameters.clone(),
+                PartySignup {
+                    number,
+                    uuid: String::from("native-cggmp-keygen"),
+                },
+            )
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    while !generators.iter().all(|g| g.is_finished()) {
+        let mut outgoing = Vec::new();
+        for generator in generators.iter_mut() {
+            outgoing.extend(generator.proceed()?.1);
+        }
+        route(
+            outgoing,
+            |index, message| generators[index].handle_incoming(message),
+            parties,
+        )?;
+    }
+
+    let key_shares = generators
+        .iter_mut()
+        .map(|g| g.output())
+        .collect::<Result<Vec<_>>>()?;
+    let aux_info = &key_shares[0].aux_info;
+    assert_eq!(parties, aux_info.n_hats.len());
+    assert_eq!(parties, aux_info.s.len());
+    assert_eq!(parties, aux_info.t.len());
+    assert_eq!(parties, aux_info.paillier_keys.len());
+    for key_share in key_shares.iter() {
+        assert_eq!(
+            key_shares[0].key_share.address,
+            key_share.key_share.address
+        );
+        assert_eq!(aux_info.n_hats, key_share.aux_info.n_hats);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn personal_message_digest() {
+    let payload = Payload::Personal(b"hello".to_vec());
+    assert_eq!(
+        "50b2c43fd39106bafbba0da34fc430e1f91e3c96ea2acee2bc34119f92b37750",
+        hex::encode(payload.digest().unwrap())
+    );
+}
+
+#[test]
+fn typed_data_digest() {
+    // Example from EIP-712
+    let typed_data: TypedData = serde_json::from_value(serde_json::json!({
+        "types": {
+            "EIP712Domain": [
+                {"name": "name", "type": "string"},
+                {"name": "version", "type": "string"},
+                {"name": "chainId", "type": "uint256"},
+                {"name": "verifyingContract", "type": "address"}
+            ],
+            "Person": [
+                {"name": "name", "type": "string"},
+                {"name": "wallet", "type": "address"}
+            ],
+            "Mail": [
+                {"name": "from", "type": "Person"},
+                {"name": "to", "type": "Person"},
+                {"name": "contents", "type": "string"}
+            ]
+        },
+        "primaryType": "Mail",
+        "domain": {
+            "name": "Ether Mail",
+            "version": "1",
+            "chainId": 1,
+            "verifyingContract": "0xCcCCccccCCCCcCCCCCCcCcCccCcCCCcCcccccccC"
+        },
+        "message": {
+            "from": {
+                "name": "Cow",
+                "wallet": "0xCD2a3d9F938E13CD947Ec05AbC7FE734Df8DD826"
+            },
+            "to": {
+                "name": "Bob",
+                "wallet": "0xbBbBBBBbbBBBbbbBbbBbbbbBBbBbbbbBbBbbBBbB"
+            },
+            "contents": "Hello, Bob!"
+        }
+    }))
+    .unwrap();
+    assert_eq!(
+        "be609aee343fb3c4b28e1df9e632fca64fcfaede20f02e86244efddf30957bd2",
+        hex::encode(typed_data.digest().unwrap())
+    );
+}
+
+/// Typed data with a single field of a type in the message.
+fn typed_data_field(kind: &str, value: serde_json::Value) -> TypedData {
+    serde_json::from_value(serde_json::json!({
+        "types": {
+            "EIP712Domain": [
+                {"name": "name", "type": "string"}
+            ],
+            "Value": [
+                {"name": "value", "type": kind}
+            ]
+        },
+        "primaryType": "Value",
+        "domain": {
+            "name": "Typed Value"
+        },
+        "message": {
+            "value": value
+        }
+    }))
+    .unwrap()
+}
+
+#[test]
+fn typed_data_checks_declared_sizes() {
+    use serde_json::json;
+
+    let valid = [
+        ("uint8", json!(255)),
+        ("uint", json!("0xff")),
+        ("int8", json!(-128)),
+        ("int8", json!(127)),
+        ("int256", json!("-1")),
+        ("bytes4", json!("0x01020304")),
+        ("bytes32", json!(format!("0x{}", "ab".repeat(32)))),
+    ];
+    for (kind, value) in valid {
+        assert!(typed_data_field(kind, value).digest().is_ok(), "{}", kind);
+    }
+
+    let invalid = [
+        ("uint8", json!(300)),
+        ("uint8", json!(256)),
+        ("uint", json!(-1)),
+        ("uint256", json!("-0x01")),
+        ("int8", json!(128)),
+        ("int8", json!(-129)),
+        ("uint7", json!(1)),
+        ("uint264", json!(1)),
+        ("bytes4", json!(format!("0x{}", "ab".repeat(33)))),
+        ("bytes4", json!("0x010203")),
+        ("bytes33", json!(format!("0x{}", "ab".repeat(33)))),
+        ("bytes0", json!("0x")),
+    ];
+    for (kind, value) in invalid {
+        assert!(
+            matches!(
+                typed_data_field(kind, value).digest(),
+                Err(Error::TypedData(_))
+            ),
+            "{}",
+            kind
+        );
+    }
+}